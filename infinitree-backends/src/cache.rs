@@ -1,7 +1,10 @@
 use super::block_on;
 use anyhow::Context;
 use infinitree::{
-    backends::{Backend, BackendError, Directory, Result},
+    backends::{
+        list_pages, AsyncBackend, Backend, BackendError, BoxFuture, Directory, ObjectList,
+        ObjectPage, Result, SpawnBlocking,
+    },
     object::{ObjectId, ReadObject, WriteObject},
 };
 use lru::LruCache;
@@ -23,7 +26,7 @@ pub struct FSCache<const BLOCK_SIZE: usize> {
     warm: Arc<HashSet<ObjectId>>,
 
     size_limit: usize,
-    upstream: Arc<dyn Backend>,
+    upstream: Arc<dyn AsyncBackend>,
     directory: Arc<Directory>,
 }
 
@@ -32,6 +35,16 @@ impl<const BLOCK_SIZE: usize> FSCache<BLOCK_SIZE> {
         local: impl AsRef<Path>,
         size_limit_b: NonZeroUsize,
         upstream: Arc<dyn Backend>,
+    ) -> Result<Arc<Self>> {
+        Self::with_async_upstream(local, size_limit_b, SpawnBlocking::new(upstream))
+    }
+
+    /// Create a cache in front of an [`AsyncBackend`], which is used
+    /// without blocking any threads.
+    pub fn with_async_upstream(
+        local: impl AsRef<Path>,
+        size_limit_b: NonZeroUsize,
+        upstream: Arc<dyn AsyncBackend>,
     ) -> Result<Arc<Self>> {
         let size_limit = size_limit_b.get();
         if size_limit < BLOCK_SIZE {
@@ -122,7 +135,7 @@ impl<const BLOCK_SIZE: usize> FSCache<BLOCK_SIZE> {
         let cache = self.clone();
         tokio::task::spawn_blocking(move || cache.directory.write_object(&obj))
             .await
-            .expect("the task shouldn't be aborted")?;
+            .map_err(|e| BackendError::from(anyhow::Error::from(e)))??;
 
         if !self.warm.contains(&id) {
             self.file_list.write().await.put(id, FileAccess::new(id));
@@ -132,11 +145,7 @@ impl<const BLOCK_SIZE: usize> FSCache<BLOCK_SIZE> {
     }
 
     async fn read_upstream(&self, id: &ObjectId) -> Result<Arc<ReadObject>> {
-        let id = *id;
-        let cache = self.clone();
-        let object = tokio::task::spawn_blocking(move || cache.upstream.read_object(&id))
-            .await
-            .expect("the task shouldn't be aborted");
+        let object = self.upstream.read_object(id).await;
 
         if let Ok(ref obj) = object {
             self.add_new_object(obj.clone().into()).await?;
@@ -159,29 +168,31 @@ impl<const BLOCK_SIZE: usize> FSCache<BLOCK_SIZE> {
     }
 }
 
-impl<const BLOCK_SIZE: usize> Backend for FSCache<BLOCK_SIZE> {
-    fn write_object(&self, object: &WriteObject) -> Result<()> {
-        self.upstream.write_object(object)?;
-        block_on(self.add_new_object(object.clone()))?;
-        Ok(())
+impl<const BLOCK_SIZE: usize> AsyncBackend for FSCache<BLOCK_SIZE> {
+    fn write_object<'a>(&'a self, object: &'a WriteObject) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            self.upstream.write_object(object).await?;
+            self.add_new_object(object.clone()).await?;
+            Ok(())
+        })
     }
 
-    fn read_object(&self, id: &ObjectId) -> Result<Arc<ReadObject>> {
-        block_on(self.read_cache_or_upstream(id))
+    fn read_object<'a>(&'a self, id: &'a ObjectId) -> BoxFuture<'a, Result<Arc<ReadObject>>> {
+        Box::pin(self.read_cache_or_upstream(id))
     }
 
-    fn read_fresh(&self, id: &ObjectId) -> Result<Arc<ReadObject>> {
-        block_on(self.read_upstream(id))
+    fn read_fresh<'a>(&'a self, id: &'a ObjectId) -> BoxFuture<'a, Result<Arc<ReadObject>>> {
+        Box::pin(self.read_upstream(id))
     }
 
-    fn keep_warm(&self, objects: &[ObjectId]) -> Result<()> {
-        if objects.len() * BLOCK_SIZE > self.size_limit {
-            return Err(BackendError::from(anyhow::anyhow!(
-                "keep-warm list is larger than cache size!"
-            )));
-        }
+    fn keep_warm<'a>(&'a self, objects: &'a [ObjectId]) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            if objects.len() * BLOCK_SIZE > self.size_limit {
+                return Err(BackendError::from(anyhow::anyhow!(
+                    "keep-warm list is larger than cache size!"
+                )));
+            }
 
-        block_on(async {
             self.warm.clear_async().await;
 
             let mut lru = self.file_list.write().await;
@@ -194,27 +205,68 @@ impl<const BLOCK_SIZE: usize> Backend for FSCache<BLOCK_SIZE> {
                     .await
                     .expect("warm list is cleared above");
             }
-        });
 
-        Ok(())
+            Ok(())
+        })
     }
 
-    fn preload(&self, objects: &[ObjectId]) -> Result<()> {
+    fn preload<'a>(&'a self, objects: &'a [ObjectId]) -> BoxFuture<'a, Result<()>> {
         let cache = self.clone();
         let objects = objects.to_vec();
 
-        tokio::task::spawn_blocking(move || {
-            for id in objects {
-                let _ = cache.read_object(&id).unwrap();
-            }
-        });
+        Box::pin(async move {
+            tokio::task::spawn(async move {
+                for id in objects {
+                    cache.read_cache_or_upstream(&id).await?;
+                }
 
-        Ok(())
+                Ok(())
+            })
+            .await
+            .map_err(|e| BackendError::from(anyhow::Error::from(e)))?
+        })
     }
 
-    fn sync(&self) -> Result<()> {
+    fn sync(&self) -> BoxFuture<'_, Result<()>> {
         self.upstream.sync()
     }
+
+    fn list_objects(&self, page: Option<String>) -> BoxFuture<'_, Result<ObjectPage>> {
+        // the cache only ever holds a subset of upstream
+        self.upstream.list_objects(page)
+    }
+}
+
+impl<const BLOCK_SIZE: usize> Backend for FSCache<BLOCK_SIZE> {
+    fn write_object(&self, object: &WriteObject) -> Result<()> {
+        block_on(AsyncBackend::write_object(self, object))
+    }
+
+    fn read_object(&self, id: &ObjectId) -> Result<Arc<ReadObject>> {
+        block_on(AsyncBackend::read_object(self, id))
+    }
+
+    fn read_fresh(&self, id: &ObjectId) -> Result<Arc<ReadObject>> {
+        block_on(AsyncBackend::read_fresh(self, id))
+    }
+
+    fn keep_warm(&self, objects: &[ObjectId]) -> Result<()> {
+        block_on(AsyncBackend::keep_warm(self, objects))
+    }
+
+    fn preload(&self, objects: &[ObjectId]) -> Result<()> {
+        block_on(AsyncBackend::preload(self, objects))
+    }
+
+    fn sync(&self) -> Result<()> {
+        block_on(AsyncBackend::sync(self))
+    }
+
+    fn list_objects(&self) -> Result<ObjectList<'_>> {
+        Ok(list_pages(move |page| {
+            block_on(AsyncBackend::list_objects(self, page))
+        }))
+    }
 }
 
 struct FileAccess {
@@ -265,6 +317,29 @@ mod test {
         .unwrap();
     }
 
+    #[tokio::test(flavor = "current_thread")]
+    async fn block_on_current_thread_runtime() {
+        let object = WriteObject::default();
+
+        let data_root = Path::new(&env::var("CARGO_MANIFEST_DIR").unwrap())
+            .join(TEST_DATA_DIR)
+            .join("cache_current_thread");
+        std::fs::create_dir_all(&data_root).unwrap();
+
+        let backend = Cache::new(
+            &data_root,
+            NonZeroUsize::new(infinitree::BLOCK_SIZE).unwrap(),
+            InMemoryBackend::shared(),
+        )
+        .unwrap();
+
+        write_and_wait_for_commit(backend.as_ref(), &object);
+        backend.preload(&[*object.id()]).unwrap();
+        backend.read_object(object.id()).unwrap();
+
+        std::fs::remove_dir_all(data_root).unwrap();
+    }
+
     #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
     async fn write_twice_and_evict() {
         let mut object = WriteObject::default();
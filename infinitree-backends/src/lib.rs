@@ -28,11 +28,44 @@ pub use cache::*;
 mod s3;
 pub use s3::*;
 
-use std::future::Future;
-use tokio::{runtime, task};
+use std::{future::Future, panic, sync::OnceLock, thread};
+use tokio::{
+    runtime::{self, Handle, Runtime, RuntimeFlavor},
+    task,
+};
 
-pub(crate) fn block_on<O>(fut: impl Future<Output = O>) -> O {
-    task::block_in_place(move || runtime::Handle::current().block_on(fut))
+/// The runtime that blocking calls are driven on when the caller
+/// can't block the runtime it's running on.
+fn runtime() -> &'static Runtime {
+    static RUNTIME: OnceLock<Runtime> = OnceLock::new();
+    RUNTIME.get_or_init(|| {
+        runtime::Builder::new_multi_thread()
+            .thread_name("infinitree-backends")
+            .enable_all()
+            .build()
+            .expect("failed to start the runtime of the backends")
+    })
+}
+
+/// Block the current thread until `fut` completes.
+///
+/// On a multi-threaded runtime, the future runs in place. A
+/// current-thread runtime can't make progress while it's blocked, so
+/// the future is moved to a dedicated runtime, which is also used
+/// outside of a runtime.
+pub(crate) fn block_on<O: Send>(fut: impl Future<Output = O> + Send) -> O {
+    match Handle::try_current() {
+        Ok(handle) if handle.runtime_flavor() == RuntimeFlavor::MultiThread => {
+            task::block_in_place(move || handle.block_on(fut))
+        }
+        Ok(_) => thread::scope(|scope| {
+            scope
+                .spawn(|| runtime().block_on(fut))
+                .join()
+                .unwrap_or_else(|error| panic::resume_unwind(error))
+        }),
+        Err(_) => runtime().block_on(fut),
+    }
 }
 
 #[cfg(test)]
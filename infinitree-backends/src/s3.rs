@@ -1,14 +1,22 @@
 use super::block_on;
 use anyhow::Context;
 use infinitree::{
-    backends::{Backend, Result},
+    backends::{
+        list_pages, AsyncBackend, Backend, BackendError, BoxFuture, ObjectInfo, ObjectList,
+        ObjectPage, Result,
+    },
     object::{Object, ObjectId, ReadBuffer, ReadObject, WriteObject},
 };
 use reqwest::Client;
+use rusty_s3::{actions::ListObjectsV2, S3Action, UrlStyle};
 pub use rusty_s3::{Bucket, Credentials};
-use rusty_s3::{S3Action, UrlStyle};
 use scc::HashMap;
-use std::{env, future::Future, sync::Arc, time::Duration};
+use std::{
+    env,
+    future::Future,
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
 use tokio::{
     sync::Semaphore,
     task::{self, JoinError, JoinHandle},
@@ -53,60 +61,55 @@ impl<TaskResult> InFlightTracker<TaskResult>
 where
     TaskResult: 'static + Send,
 {
-    pub fn complete_all(&self) -> std::result::Result<Vec<TaskResult>, JoinError> {
-        block_on(async move {
-            let mut handles = vec![];
-            self.active
-                .retain_async(|_, v| {
-                    if let Some(handle) = std::mem::take(Arc::get_mut(v).unwrap()) {
-                        handles.push(handle);
-                    }
-
-                    false
-                })
-                .await;
+    pub async fn complete_all(&self) -> std::result::Result<Vec<TaskResult>, JoinError> {
+        let mut handles = vec![];
+        self.active
+            .retain_async(|_, v| {
+                if let Some(handle) = std::mem::take(Arc::get_mut(v).unwrap()) {
+                    handles.push(handle);
+                }
 
-            futures::future::join_all(handles).await
-        })
-        .into_iter()
-        .filter(|result| match result {
-            Ok(_) => true,
-            Err(e) => !e.is_cancelled(),
-        })
-        .collect::<std::result::Result<Vec<_>, _>>()
+                false
+            })
+            .await;
+
+        futures::future::join_all(handles)
+            .await
+            .into_iter()
+            .filter(|result| match result {
+                Ok(_) => true,
+                Err(e) => !e.is_cancelled(),
+            })
+            .collect::<std::result::Result<Vec<_>, _>>()
     }
 
-    pub fn add_task<F: 'static + Send + Future<Output = TaskResult>>(
+    pub async fn add_task<F: 'static + Send + Future<Output = TaskResult>>(
         &self,
         key: ObjectId,
         task: F,
     ) {
-        let permits = self.permits.clone();
+        let permit = self.permits.clone().acquire_owned().await;
         let active = self.active.clone();
 
-        block_on(async {
-            let permit = permits.acquire_owned().await;
-
-            let handle = Arc::new(Some(task::spawn(async move {
-                let _permit = permit;
-                let result = task.await;
-                active.remove_async(&key).await;
-                result
-            })));
-
-            match self.active.entry_async(key).await {
-                scc::hash_map::Entry::Occupied(mut entry) => {
-                    if let Some(handle) = entry.get().as_ref() {
-                        handle.abort();
-                    }
-
-                    *entry.get_mut() = handle.clone();
-                }
-                scc::hash_map::Entry::Vacant(entry) => {
-                    entry.insert_entry(handle);
+        let handle = Arc::new(Some(task::spawn(async move {
+            let _permit = permit;
+            let result = task.await;
+            active.remove_async(&key).await;
+            result
+        })));
+
+        match self.active.entry_async(key).await {
+            scc::hash_map::Entry::Occupied(mut entry) => {
+                if let Some(handle) = entry.get().as_ref() {
+                    handle.abort();
                 }
+
+                *entry.get_mut() = handle.clone();
             }
-        })
+            scc::hash_map::Entry::Vacant(entry) => {
+                entry.insert_entry(handle);
+            }
+        }
     }
 }
 
@@ -166,95 +169,220 @@ impl S3 {
         // note that `base_path` automatically has a "/" appended to the string
         format!("{}{}", &self.base_path, id.to_string())
     }
+
+    /// Fetch a single page of the bucket listing using
+    /// `ListObjectsV2`.
+    ///
+    /// The token of the next page is the continuation token returned
+    /// by S3.
+    async fn list_page(&self, continuation_token: Option<String>) -> Result<ObjectPage> {
+        let url = {
+            let mut action = self.bucket.list_objects_v2(Some(&self.credentials));
+            if !self.base_path.is_empty() {
+                action.with_prefix(self.base_path.as_str());
+            }
+            if let Some(token) = continuation_token {
+                action.with_continuation_token(token);
+            }
+            action.sign(Duration::from_secs(30))
+        };
+
+        let resp = self.client.get(url).send().await.context("Query error")?;
+        let status_code = resp.status().as_u16();
+        let body = resp.text().await.context("Read error")?;
+
+        if !(200..300).contains(&status_code) {
+            return Err(anyhow::anyhow!("Bad response: {}, {}", status_code, body).into());
+        }
+
+        let page = ListObjectsV2::parse_response(&body).context("Invalid listing")?;
+        let objects = page
+            .contents
+            .into_iter()
+            .filter_map(|content| {
+                // anything that's not named like an object is not ours
+                let name = content.key.strip_prefix(&self.base_path)?;
+                let id = match name.len() {
+                    64 => name.parse::<ObjectId>().ok()?,
+                    _ => return None,
+                };
+
+                Some(ObjectInfo {
+                    id,
+                    size: Some(content.size),
+                    modified: parse_timestamp(&content.last_modified),
+                })
+            })
+            .collect();
+
+        Ok(ObjectPage {
+            objects,
+            next: page.next_continuation_token,
+        })
+    }
 }
 
-impl Backend for S3 {
-    fn write_object(&self, object: &WriteObject) -> Result<()> {
+/// Parse an ISO 8601 timestamp, as returned by S3, e.g.
+/// `2009-10-12T17:50:30.000Z`.
+///
+/// Only UTC timestamps are supported.
+fn parse_timestamp(timestamp: &str) -> Option<SystemTime> {
+    let (date, time) = timestamp.strip_suffix('Z')?.split_once('T')?;
+
+    let mut date = date.splitn(3, '-').map(str::parse::<i64>);
+    let (year, month, day) = (date.next()?.ok()?, date.next()?.ok()?, date.next()?.ok()?);
+
+    let (time, fraction) = time.split_once('.').unwrap_or((time, ""));
+    let mut time = time.splitn(3, ':').map(str::parse::<u64>);
+    let (hour, minute, second) = (time.next()?.ok()?, time.next()?.ok()?, time.next()?.ok()?);
+
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) || hour > 23 || minute > 59 {
+        return None;
+    }
+
+    // days since the epoch, see http://howardhinnant.github.io/date_algorithms.html#days_from_civil
+    let days = {
+        let year = if month <= 2 { year - 1 } else { year };
+        let era = year.div_euclid(400);
+        let year_of_era = year.rem_euclid(400);
+        let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+        let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+        era * 146_097 + day_of_era - 719_468
+    };
+
+    let nanos = match fraction {
+        "" => 0,
+        fraction if fraction.bytes().all(|b| b.is_ascii_digit()) => {
+            format!("{:0<9}", &fraction[..fraction.len().min(9)])
+                .parse::<u32>()
+                .ok()?
+        }
+        _ => return None,
+    };
+
+    let seconds = u64::try_from(days).ok()? * 86_400 + hour * 3600 + minute * 60 + second;
+    Some(SystemTime::UNIX_EPOCH + Duration::new(seconds, nanos))
+}
+
+impl AsyncBackend for S3 {
+    fn write_object<'a>(&'a self, object: &'a WriteObject) -> BoxFuture<'a, Result<()>> {
         let body = object.as_inner().to_vec();
         let key = self.get_path(object.id());
         let id = *object.id();
 
         let this = self.clone();
-        self.in_flight.add_task(id, async move {
-            let url = this
+        Box::pin(async move {
+            self.in_flight
+                .add_task(id, async move {
+                    let url = this
+                        .bucket
+                        .put_object(Some(&this.credentials), &key)
+                        .sign(Duration::from_secs(30));
+
+                    let resp = this
+                        .client
+                        .put(url)
+                        .body(body)
+                        .send()
+                        .await
+                        .expect("Server error");
+
+                    let status_code = resp.status().as_u16();
+                    let resp_body = resp.bytes().await.expect("Response error");
+                    if (200..300).contains(&status_code) {
+                        Ok(status_code)
+                    } else {
+                        panic!(
+                            "Bad response: {}, {}",
+                            status_code,
+                            String::from_utf8_lossy(resp_body.as_ref())
+                        )
+                    }
+                })
+                .await;
+
+            Ok(())
+        })
+    }
+
+    fn read_object<'a>(&'a self, id: &'a ObjectId) -> BoxFuture<'a, Result<Arc<ReadObject>>> {
+        let key = self.get_path(id);
+
+        Box::pin(async move {
+            let url = self
                 .bucket
-                .put_object(Some(&this.credentials), &key)
+                .get_object(Some(&self.credentials), &key)
                 .sign(Duration::from_secs(30));
 
-            let resp = this
-                .client
-                .put(url)
-                .body(body)
-                .send()
-                .await
-                .expect("Server error");
-
+            let resp = self.client.get(url).send().await.context("Query error")?;
             let status_code = resp.status().as_u16();
-            let resp_body = resp.bytes().await.expect("Response error");
+            let body = resp.bytes().await.context("Read error")?;
+
             if (200..300).contains(&status_code) {
-                Ok(status_code)
+                Ok(Arc::new(Object::with_id(
+                    *id,
+                    ReadBuffer::new(body.to_vec()),
+                )))
+            } else if status_code == 404 {
+                Err(BackendError::NotFound { id: *id })
             } else {
-                panic!(
+                Err(anyhow::anyhow!(
                     "Bad response: {}, {}",
                     status_code,
-                    String::from_utf8_lossy(resp_body.as_ref())
+                    String::from_utf8_lossy(body.as_ref())
                 )
+                .into())
             }
-        });
+        })
+    }
+
+    fn sync(&self) -> BoxFuture<'_, Result<()>> {
+        Box::pin(async move {
+            self.in_flight
+                .complete_all()
+                .await
+                .context("Failed transactions with server")?;
 
-        Ok(())
+            Ok(())
+        })
     }
 
-    fn read_object(&self, id: &ObjectId) -> Result<Arc<ReadObject>> {
-        let this = self.clone();
-        let object: Result<Vec<u8>> = {
-            let key = self.get_path(id);
-
-            block_on(async move {
-                let url = this
-                    .bucket
-                    .get_object(Some(&this.credentials), &key)
-                    .sign(Duration::from_secs(30));
-
-                let resp = this.client.get(url).send().await.context("Query error")?;
-                let status_code = resp.status().as_u16();
-                let body = resp.bytes().await.context("Read error")?;
-
-                if (200..300).contains(&status_code) {
-                    Ok(body.to_vec())
-                } else {
-                    Err(anyhow::anyhow!(
-                        "Bad response: {}, {}",
-                        status_code,
-                        String::from_utf8_lossy(body.as_ref())
-                    )
-                    .into())
-                }
-            })
-        };
+    fn list_objects(&self, page: Option<String>) -> BoxFuture<'_, Result<ObjectPage>> {
+        Box::pin(self.list_page(page))
+    }
+}
 
-        Ok(Arc::new(Object::with_id(*id, ReadBuffer::new(object?))))
+impl Backend for S3 {
+    fn write_object(&self, object: &WriteObject) -> Result<()> {
+        block_on(AsyncBackend::write_object(self, object))
+    }
+
+    fn read_object(&self, id: &ObjectId) -> Result<Arc<ReadObject>> {
+        block_on(AsyncBackend::read_object(self, id))
     }
 
     fn sync(&self) -> Result<()> {
-        self.in_flight
-            .complete_all()
-            .context("Failed transactions with server")?;
+        block_on(AsyncBackend::sync(self))
+    }
 
-        Ok(())
+    fn list_objects(&self) -> Result<ObjectList<'_>> {
+        Ok(list_pages(move |page| block_on(self.list_page(page))))
     }
 }
 
 #[cfg(test)]
 mod test {
-    use super::S3;
+    use super::{parse_timestamp, S3};
     use crate::test::{write_and_wait_for_commit, TEST_DATA_DIR};
     use hyper::server::conn::http1;
     use hyper_util::rt::TokioIo;
     use infinitree::{backends::Backend, object::WriteObject, ObjectId};
     use s3s::{auth::SimpleAuth, service::S3ServiceBuilder};
     use s3s_fs::FileSystem;
-    use std::net::SocketAddr;
+    use std::{
+        net::SocketAddr,
+        time::{Duration, SystemTime},
+    };
     use tokio::{net::TcpListener, task};
 
     const AWS_ACCESS_KEY_ID: &str = "MEEMIEW3EEKI8IEY1U";
@@ -262,6 +390,7 @@ mod test {
 
     const SERVER_ADDR_RW: ([u8; 4], u16) = ([127, 0, 0, 1], 12312);
     const SERVER_ADDR_RO: ([u8; 4], u16) = ([127, 0, 0, 1], 12313);
+    const SERVER_ADDR_LIST: ([u8; 4], u16) = ([127, 0, 0, 1], 12314);
 
     fn setup_s3_server(addr: &SocketAddr) {
         let fs = FileSystem::new(TEST_DATA_DIR).unwrap();
@@ -329,4 +458,40 @@ mod test {
 
         let _obj_1_read_ref = backend.read_object(&id).unwrap();
     }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn s3_write_list() {
+        let addr = SocketAddr::from(SERVER_ADDR_LIST);
+        setup_s3_server(&addr);
+
+        let backend = S3::new(format!("http://{addr}").parse().unwrap(), "bucket").unwrap();
+
+        let object = WriteObject::default();
+        write_and_wait_for_commit(backend.as_ref(), &object);
+
+        let listed = backend
+            .list_objects()
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+
+        let info = listed.iter().find(|info| &info.id == object.id()).unwrap();
+        assert_eq!(info.size, Some(object.as_inner().len() as u64));
+        assert!(info.modified.is_some());
+    }
+
+    #[test]
+    fn s3_timestamps() {
+        assert_eq!(
+            parse_timestamp("1970-01-01T00:00:00Z"),
+            Some(SystemTime::UNIX_EPOCH)
+        );
+        assert_eq!(
+            parse_timestamp("2009-10-12T17:50:30.250Z"),
+            Some(SystemTime::UNIX_EPOCH + Duration::new(1_255_369_830, 250_000_000))
+        );
+        assert_eq!(parse_timestamp("2009-13-12T17:50:30Z"), None);
+        assert_eq!(parse_timestamp("yesterday"), None);
+        assert_eq!(parse_timestamp("2009-10-12T17:50:30.25ééééZ"), None);
+    }
 }
@@ -5,6 +5,17 @@ use proc_macro_crate::{crate_name, FoundCrate};
 use quote::quote;
 use syn::{Data, DataStruct, DeriveInput, Field, Fields, Ident, Lit, LitStr, Type};
 
+/// The optional methods of `Index` that can be enabled with a
+/// struct-level attribute, and the traits of the fields they need.
+const OPERATIONS: &[(&str, &str)] = &[
+    ("walk", "Walk"),
+    ("squash", "Squash"),
+    ("merge", "Merge"),
+    ("refresh", "Refresh"),
+    ("revert", "Revert"),
+    ("journal", "Journal"),
+];
+
 struct StructField {
     field: Field,
     skip: bool,
@@ -38,6 +49,19 @@ pub fn expand(infinitree_crate: TokenStream, input: DeriveInput) -> syn::Result<
         _ => panic!("this derive macro only works on structs with named fields"),
     };
 
+    let mut enabled = vec![];
+    for attr in input.attrs.iter().filter(|attr| attr.path().is_ident("infinitree")) {
+        attr.parse_nested_meta(|meta| {
+            match OPERATIONS.iter().find(|(name, _)| meta.path.is_ident(name)) {
+                Some(operation) => {
+                    enabled.push(operation);
+                    Ok(())
+                }
+                None => Err(meta.error("unrecognized operation")),
+            }
+        })?;
+    }
+
     let fields = fields
         .into_iter()
         .filter_map(|f| {
@@ -126,6 +150,20 @@ pub fn expand(infinitree_crate: TokenStream, input: DeriveInput) -> syn::Result<
         })
         .collect::<TokenStream>();
 
+    let operations = OPERATIONS
+        .iter()
+        .filter(|operation| enabled.contains(operation))
+        .map(|(name, intent)| {
+            let method_name = Ident::new(&format!("{name}_all"), Span::call_site());
+            let intent = Ident::new(intent, Span::call_site());
+            quote! {
+                fn #method_name(&'_ self) -> #infinitree_crate::anyhow::Result<Vec<#infinitree_crate::fields::Intent<Box<dyn #infinitree_crate::fields::#intent>>>> {
+                    Ok(vec![#strategies])
+                }
+            }
+        })
+        .collect::<TokenStream>();
+
     let st_name = input.ident;
     let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
 
@@ -149,6 +187,8 @@ pub fn expand(infinitree_crate: TokenStream, input: DeriveInput) -> syn::Result<
             fn load_all(&'_ self) -> #infinitree_crate::anyhow::Result<Vec<#infinitree_crate::fields::Intent<Box<dyn #infinitree_crate::fields::Load>>>> {
                 Ok(vec![#strategies])
             }
+
+            #operations
         }
         })
     }
@@ -27,7 +27,10 @@ mod derive_index;
 ///     temperature: f32
 /// }
 ///
+/// // also generate the methods needed by garbage collection,
+/// // verification and merging
 /// #[derive(infinitree::Index, Default, Clone)]
+/// #[infinitree(walk, merge)]
 /// pub struct Measurements {
 ///     // rename the field when serializing
 ///     #[infinitree(name = "last_time")]
@@ -45,6 +48,13 @@ mod derive_index;
 ///     current_time: usize,
 /// }
 /// ```
+///
+/// Only `store_all` and `load_all` are generated by default. The rest
+/// of the methods of `infinitree::Index` can be enabled by listing
+/// them in the `#[infinitree(...)]` attribute of the struct as `walk`,
+/// `squash`, `merge`, `refresh`, `revert` and `journal`. Every field
+/// then needs to implement the matching trait in
+/// `infinitree::fields`, e.g. `Walk` for `walk`.
 #[proc_macro_derive(Index, attributes(infinitree))]
 pub fn derive_index_macro(input: TokenStream) -> TokenStream {
     let input = parse_macro_input!(input as DeriveInput);
@@ -147,4 +157,65 @@ mod tests {
 
         assert_eq!(result.to_string(), expected.to_string());
     }
+    #[test]
+    fn test_operations() {
+        use quote::quote;
+        use syn::parse_quote;
+
+        let input = parse_quote! {
+        #[derive(Default, Index)]
+        #[infinitree(journal, walk)]
+        pub struct TestStruct {
+            chunks: ChunkIndex,
+        }
+        };
+
+        let result = super::derive_index::expand(quote::quote!(::infinitree), input).unwrap();
+
+        #[rustfmt::skip]
+        let expected = quote! {
+        #[automatically_derived]
+        impl TestStruct {
+            #[inline]
+            pub fn chunks(&'_ self) -> ::infinitree::fields::Intent<Box<::infinitree::fields::LocalField<ChunkIndex>>> {
+                use ::infinitree::fields::{Intent, strategy::Strategy};
+                Intent::new(
+                    "chunks",
+                    Box::new(::infinitree::fields::LocalField::for_field(
+                        &self.chunks,
+                    )),
+                )
+            }
+            pub fn fields(&self) -> Vec<String> {
+                vec!["chunks".into(),]
+            }
+        }
+        impl ::infinitree::Index for TestStruct {
+            fn store_all(&'_ self) -> ::infinitree::anyhow::Result<Vec<::infinitree::fields::Intent<Box<dyn ::infinitree::fields::Store>>>> {
+                Ok(vec![self.chunks().into(),])
+            }
+            fn load_all(&'_ self) -> ::infinitree::anyhow::Result<Vec<::infinitree::fields::Intent<Box<dyn ::infinitree::fields::Load>>>> {
+                Ok(vec![self.chunks().into(),])
+            }
+            fn walk_all(&'_ self) -> ::infinitree::anyhow::Result<Vec<::infinitree::fields::Intent<Box<dyn ::infinitree::fields::Walk>>>> {
+                Ok(vec![self.chunks().into(),])
+            }
+            fn journal_all(&'_ self) -> ::infinitree::anyhow::Result<Vec<::infinitree::fields::Intent<Box<dyn ::infinitree::fields::Journal>>>> {
+                Ok(vec![self.chunks().into(),])
+            }
+        }
+            };
+
+        assert_eq!(result.to_string(), expected.to_string());
+
+        let input = parse_quote! {
+        #[derive(Default, Index)]
+        #[infinitree(gc)]
+        pub struct TestStruct {
+            chunks: ChunkIndex,
+        }
+        };
+
+        assert!(super::derive_index::expand(quote::quote!(::infinitree), input).is_err());
+    }
 }
@@ -8,10 +8,15 @@
 //! [`infinitree-backends`](https://docs.rs/infinitree-backends)
 //! crate.
 use crate::object::{ObjectId, ReadObject, WriteObject};
-use std::{io, sync::Arc};
+use std::{io, sync::Arc, time::SystemTime};
 
 mod directory;
-pub use directory::Directory;
+pub use directory::{Directory, Layout};
+
+#[cfg(feature = "async")]
+mod asynchronous;
+#[cfg(feature = "async")]
+pub use asynchronous::*;
 
 #[derive(thiserror::Error, Debug)]
 pub enum BackendError {
@@ -24,6 +29,8 @@ pub enum BackendError {
     NotFound { id: ObjectId },
     #[error("Can't create object")]
     Create,
+    #[error("Operation not supported by the backend")]
+    Unsupported,
     #[error("Backend Error: {source}")]
     Generic {
         #[from]
@@ -33,6 +40,30 @@ pub enum BackendError {
 
 pub type Result<T> = std::result::Result<T, BackendError>;
 
+/// Information about an object, as returned by [`Backend::list_objects`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ObjectInfo {
+    /// Id of the object.
+    pub id: ObjectId,
+    /// Size of the stored object in bytes, if the backend knows it.
+    pub size: Option<u64>,
+    /// Last modification time, if the backend knows it.
+    pub modified: Option<SystemTime>,
+}
+
+impl From<ObjectId> for ObjectInfo {
+    fn from(id: ObjectId) -> Self {
+        Self {
+            id,
+            size: None,
+            modified: None,
+        }
+    }
+}
+
+/// A streaming listing of the objects in a backend.
+pub type ObjectList<'a> = Box<dyn Iterator<Item = Result<ObjectInfo>> + Send + 'a>;
+
 pub trait Backend: Send + Sync {
     fn write_object(&self, object: &WriteObject) -> Result<()>;
     fn read_object(&self, id: &ObjectId) -> Result<Arc<ReadObject>>;
@@ -45,6 +76,11 @@ pub trait Backend: Send + Sync {
         Ok(())
     }
 
+    /// Make every object that has been written or deleted before
+    /// the call durable.
+    ///
+    /// Backends that are durable as soon as a write returns don't
+    /// need to do anything.
     fn sync(&self) -> Result<()> {
         Ok(())
     }
@@ -56,6 +92,17 @@ pub trait Backend: Send + Sync {
     fn keep_warm(&self, _objects: &[ObjectId]) -> Result<()> {
         Ok(())
     }
+
+    /// List all objects that are stored in the backend.
+    ///
+    /// The listing is produced lazily, so backends may fetch it in
+    /// pages, and errors can occur during iteration.
+    ///
+    /// Backends that can't enumerate their contents will return
+    /// [`BackendError::Unsupported`].
+    fn list_objects(&self) -> Result<ObjectList<'_>> {
+        Err(BackendError::Unsupported)
+    }
 }
 
 #[cfg(any(test, bench, feature = "test"))]
@@ -93,6 +140,32 @@ pub mod test {
                 .ok_or(BackendError::NotFound { id: *id })
                 .map(Arc::clone)
         }
+
+        fn delete(&self, objects: &[ObjectId]) -> Result<()> {
+            let mut map = self.0.lock().unwrap();
+            for id in objects {
+                map.remove(id);
+            }
+            Ok(())
+        }
+
+        fn list_objects(&self) -> Result<ObjectList<'_>> {
+            let objects = self
+                .0
+                .lock()
+                .unwrap()
+                .iter()
+                .map(|(id, object)| {
+                    Ok(ObjectInfo {
+                        id: *id,
+                        size: Some(object.as_inner().len() as u64),
+                        modified: None,
+                    })
+                })
+                .collect::<Vec<_>>();
+
+            Ok(Box::new(objects.into_iter()))
+        }
     }
 
     #[derive(Clone, Default)]
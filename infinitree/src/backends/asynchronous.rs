@@ -0,0 +1,363 @@
+use super::{Backend, BackendError, ObjectInfo, ObjectList, Result};
+use crate::object::{ObjectId, ReadObject, WriteObject};
+use std::{
+    future::Future,
+    iter, panic,
+    pin::Pin,
+    sync::{Arc, OnceLock},
+    thread,
+};
+use tokio::{
+    runtime::{self, Handle, Runtime, RuntimeFlavor},
+    task,
+};
+
+/// A boxed future, as returned by the methods of [`AsyncBackend`].
+pub type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+/// A page of the objects stored in an [`AsyncBackend`].
+#[derive(Clone, Debug, Default)]
+pub struct ObjectPage {
+    /// The objects on this page.
+    pub objects: Vec<ObjectInfo>,
+
+    /// The token to request the next page with, or `None` if this is
+    /// the last one.
+    pub next: Option<String>,
+}
+
+/// Lazily walk through the pages of a listing, calling `fetch` with
+/// the token of the next page whenever the previous one runs out.
+///
+/// The first page is requested with `None`. This is useful to
+/// implement [`Backend::list_objects`] for backends that list
+/// objects in pages.
+pub fn list_pages<'a>(
+    mut fetch: impl FnMut(Option<String>) -> Result<ObjectPage> + Send + 'a,
+) -> ObjectList<'a> {
+    let mut page = Vec::new().into_iter();
+    let mut next = None;
+    let mut done = false;
+
+    Box::new(iter::from_fn(move || loop {
+        if let Some(object) = page.next() {
+            return Some(Ok(object));
+        }
+
+        if done {
+            return None;
+        }
+
+        match fetch(next.take()) {
+            Ok(fetched) => {
+                done = fetched.next.is_none();
+                next = fetched.next;
+                page = fetched.objects.into_iter();
+            }
+            Err(error) => {
+                done = true;
+                return Some(Err(error));
+            }
+        }
+    }))
+}
+
+/// The async counterpart of [`Backend`].
+///
+/// Use [`BlockOn`] to use an `AsyncBackend` where a [`Backend`] is
+/// expected, and [`SpawnBlocking`] the other way around.
+pub trait AsyncBackend: Send + Sync {
+    fn write_object<'a>(&'a self, object: &'a WriteObject) -> BoxFuture<'a, Result<()>>;
+    fn read_object<'a>(&'a self, id: &'a ObjectId) -> BoxFuture<'a, Result<Arc<ReadObject>>>;
+
+    fn preload<'a>(&'a self, _objects: &'a [ObjectId]) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async { Ok(()) })
+    }
+
+    fn delete<'a>(&'a self, _objects: &'a [ObjectId]) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async { Ok(()) })
+    }
+
+    fn sync(&self) -> BoxFuture<'_, Result<()>> {
+        Box::pin(async { Ok(()) })
+    }
+
+    fn read_fresh<'a>(&'a self, id: &'a ObjectId) -> BoxFuture<'a, Result<Arc<ReadObject>>> {
+        self.read_object(id)
+    }
+
+    fn keep_warm<'a>(&'a self, _objects: &'a [ObjectId]) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async { Ok(()) })
+    }
+
+    /// List a page of the objects that are stored in the backend.
+    ///
+    /// Start with `None`, then pass the [`next`](ObjectPage::next)
+    /// token of each page to get the one after it. How many objects
+    /// are on a page is up to the backend.
+    fn list_objects(&self, _page: Option<String>) -> BoxFuture<'_, Result<ObjectPage>> {
+        Box::pin(async { Err(BackendError::Unsupported) })
+    }
+}
+
+/// Use an [`AsyncBackend`] as a blocking [`Backend`].
+///
+/// Every call blocks the current thread until the future completes
+/// on the runtime the adapter was created in.
+///
+/// On a multi-threaded runtime, the future runs in place. A
+/// current-thread runtime can't make progress while it's blocked, so
+/// there the future is moved to another thread, and driven by a
+/// dedicated runtime unless the adapter's runtime is multi-threaded.
+pub struct BlockOn {
+    inner: Arc<dyn AsyncBackend>,
+    handle: Handle,
+}
+
+impl BlockOn {
+    /// Wrap `backend` using the runtime of the current context.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called outside of a Tokio runtime.
+    pub fn new(backend: Arc<dyn AsyncBackend>) -> Arc<Self> {
+        Self::with_handle(backend, Handle::current())
+    }
+
+    /// Wrap `backend` using the runtime behind `handle`.
+    pub fn with_handle(backend: Arc<dyn AsyncBackend>, handle: Handle) -> Arc<Self> {
+        Arc::new(Self {
+            inner: backend,
+            handle,
+        })
+    }
+
+    fn block_on<T: Send>(&self, future: impl Future<Output = Result<T>> + Send) -> Result<T> {
+        match Handle::try_current() {
+            Ok(current) if current.runtime_flavor() == RuntimeFlavor::MultiThread => {
+                task::block_in_place(|| self.handle.block_on(future))
+            }
+            Ok(_) => thread::scope(|scope| {
+                scope
+                    .spawn(|| match self.handle.runtime_flavor() {
+                        RuntimeFlavor::MultiThread => self.handle.block_on(future),
+                        // the runtime of the adapter may be the one that's blocked
+                        _ => runtime().block_on(future),
+                    })
+                    .join()
+                    .unwrap_or_else(|error| panic::resume_unwind(error))
+            }),
+            Err(_) => self.handle.block_on(future),
+        }
+    }
+}
+
+/// The runtime that blocking calls are driven on when the caller
+/// can't block the runtime it's running on.
+fn runtime() -> &'static Runtime {
+    static RUNTIME: OnceLock<Runtime> = OnceLock::new();
+    RUNTIME.get_or_init(|| {
+        runtime::Builder::new_multi_thread()
+            .thread_name("infinitree-block-on")
+            .enable_all()
+            .build()
+            .expect("failed to start the runtime of blocking calls")
+    })
+}
+
+impl Backend for BlockOn {
+    fn write_object(&self, object: &WriteObject) -> Result<()> {
+        self.block_on(self.inner.write_object(object))
+    }
+
+    fn read_object(&self, id: &ObjectId) -> Result<Arc<ReadObject>> {
+        self.block_on(self.inner.read_object(id))
+    }
+
+    fn preload(&self, objects: &[ObjectId]) -> Result<()> {
+        self.block_on(self.inner.preload(objects))
+    }
+
+    fn delete(&self, objects: &[ObjectId]) -> Result<()> {
+        self.block_on(self.inner.delete(objects))
+    }
+
+    fn sync(&self) -> Result<()> {
+        self.block_on(self.inner.sync())
+    }
+
+    fn read_fresh(&self, id: &ObjectId) -> Result<Arc<ReadObject>> {
+        self.block_on(self.inner.read_fresh(id))
+    }
+
+    fn keep_warm(&self, objects: &[ObjectId]) -> Result<()> {
+        self.block_on(self.inner.keep_warm(objects))
+    }
+
+    fn list_objects(&self) -> Result<ObjectList<'_>> {
+        Ok(list_pages(move |page| {
+            self.block_on(self.inner.list_objects(page))
+        }))
+    }
+}
+
+/// Use a blocking [`Backend`] as an [`AsyncBackend`].
+///
+/// Every call is moved to the blocking thread pool of the Tokio
+/// runtime, so the async tasks can keep running while it finishes.
+pub struct SpawnBlocking {
+    inner: Arc<dyn Backend>,
+}
+
+impl SpawnBlocking {
+    /// Wrap `backend`.
+    pub fn new(backend: Arc<dyn Backend>) -> Arc<Self> {
+        Arc::new(Self { inner: backend })
+    }
+
+    async fn spawn<T: Send + 'static>(
+        &self,
+        f: impl FnOnce(&dyn Backend) -> Result<T> + Send + 'static,
+    ) -> Result<T> {
+        let backend = self.inner.clone();
+        task::spawn_blocking(move || f(backend.as_ref()))
+            .await
+            .map_err(|e| BackendError::from(anyhow::Error::from(e)))?
+    }
+}
+
+impl AsyncBackend for SpawnBlocking {
+    fn write_object<'a>(&'a self, object: &'a WriteObject) -> BoxFuture<'a, Result<()>> {
+        let object = object.clone();
+        Box::pin(self.spawn(move |b| b.write_object(&object)))
+    }
+
+    fn read_object<'a>(&'a self, id: &'a ObjectId) -> BoxFuture<'a, Result<Arc<ReadObject>>> {
+        let id = *id;
+        Box::pin(self.spawn(move |b| b.read_object(&id)))
+    }
+
+    fn preload<'a>(&'a self, objects: &'a [ObjectId]) -> BoxFuture<'a, Result<()>> {
+        let objects = objects.to_vec();
+        Box::pin(self.spawn(move |b| b.preload(&objects)))
+    }
+
+    fn delete<'a>(&'a self, objects: &'a [ObjectId]) -> BoxFuture<'a, Result<()>> {
+        let objects = objects.to_vec();
+        Box::pin(self.spawn(move |b| b.delete(&objects)))
+    }
+
+    fn sync(&self) -> BoxFuture<'_, Result<()>> {
+        Box::pin(self.spawn(|b| b.sync()))
+    }
+
+    fn read_fresh<'a>(&'a self, id: &'a ObjectId) -> BoxFuture<'a, Result<Arc<ReadObject>>> {
+        let id = *id;
+        Box::pin(self.spawn(move |b| b.read_fresh(&id)))
+    }
+
+    fn keep_warm<'a>(&'a self, objects: &'a [ObjectId]) -> BoxFuture<'a, Result<()>> {
+        let objects = objects.to_vec();
+        Box::pin(self.spawn(move |b| b.keep_warm(&objects)))
+    }
+
+    /// A blocking listing can't be continued from a token, so all
+    /// objects are returned on a single page.
+    fn list_objects(&self, _page: Option<String>) -> BoxFuture<'_, Result<ObjectPage>> {
+        Box::pin(self.spawn(|b| {
+            Ok(ObjectPage {
+                objects: b.list_objects()?.collect::<Result<_>>()?,
+                next: None,
+            })
+        }))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{AsyncBackend, BlockOn, BoxFuture, ObjectPage};
+    use crate::{
+        backends::{Backend, Result},
+        object::{ObjectId, ReadObject, WriteObject},
+    };
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    /// Lists `objects` objects, two on each page.
+    #[derive(Default)]
+    struct Paged {
+        objects: usize,
+        fetched: AtomicUsize,
+    }
+
+    impl AsyncBackend for Paged {
+        fn write_object<'a>(&'a self, _object: &'a WriteObject) -> BoxFuture<'a, Result<()>> {
+            unimplemented!()
+        }
+
+        fn read_object<'a>(&'a self, _id: &'a ObjectId) -> BoxFuture<'a, Result<Arc<ReadObject>>> {
+            unimplemented!()
+        }
+
+        fn list_objects(&self, page: Option<String>) -> BoxFuture<'_, Result<ObjectPage>> {
+            Box::pin(async move {
+                self.fetched.fetch_add(1, Ordering::SeqCst);
+
+                let start = page.map_or(0, |token| token.parse().unwrap());
+                let end = self.objects.min(start + 2);
+                Ok(ObjectPage {
+                    objects: (start..end)
+                        .map(|i| ObjectId::from_bytes([i as u8; 32]).into())
+                        .collect(),
+                    next: (end < self.objects).then(|| end.to_string()),
+                })
+            })
+        }
+    }
+
+    #[test]
+    fn pages_are_listed_lazily() {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        let paged = Arc::new(Paged {
+            objects: 5,
+            ..Default::default()
+        });
+        let backend = BlockOn::with_handle(paged.clone(), runtime.handle().clone());
+
+        assert_eq!(backend.list_objects().unwrap().take(3).count(), 3);
+        assert_eq!(paged.fetched.load(Ordering::SeqCst), 2);
+
+        let listed = backend
+            .list_objects()
+            .unwrap()
+            .map(|info| info.unwrap().id)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            listed,
+            (0..5u8)
+                .map(|i| ObjectId::from_bytes([i; 32]))
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(paged.fetched.load(Ordering::SeqCst), 5);
+    }
+
+    #[test]
+    fn block_inside_a_current_thread_runtime() {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+
+        let listed = runtime.block_on(async {
+            let backend = BlockOn::new(Arc::new(Paged {
+                objects: 3,
+                ..Default::default()
+            }));
+            backend.list_objects().unwrap().count()
+        });
+        assert_eq!(listed, 3);
+    }
+}
@@ -1,14 +1,25 @@
-use super::{Backend, Result};
+use super::{Backend, ObjectInfo, ObjectList, Result};
 use crate::object::{Object, ObjectId, ReadBuffer, ReadObject, WriteObject};
 
 use lru::LruCache;
 use std::{
-    fs,
+    collections::HashSet,
+    fs::{self, File},
+    io::{self, Write},
+    iter,
+    mem::size_of,
     num::NonZeroUsize,
     path::{Path, PathBuf},
-    sync::{Arc, Mutex},
+    process,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
 };
 
+/// Distinguishes the temporary files of concurrent writes.
+static TEMP_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
 #[cfg(feature = "mmap")]
 mod mmap {
     use super::Result;
@@ -102,10 +113,43 @@ type Buffer = ReadObject;
 #[cfg(feature = "mmap")]
 type Buffer = mmap::MmappedFile;
 
+/// A directory with this file in it uses [`Layout::Sharded`].
+const SHARDED_MARKER: &str = ".sharded";
+
+/// How objects are arranged in a [`Directory`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Layout {
+    /// Every object is a file in the directory.
+    #[default]
+    Flat,
+
+    /// Objects are spread over two levels of subdirectories, named
+    /// after the first two pairs of characters in their id, e.g.
+    /// `ab/cd/abcd...`.
+    ///
+    /// This keeps directories small on filesystems that slow down
+    /// with lots of files in a single directory.
+    Sharded,
+}
+
+/// Stores every object as a file in a directory.
+///
+/// Objects are written to a temporary file first, which is then
+/// renamed, so an object is either fully written, or not changed at
+/// all. Every write is flushed to the disk before it returns, and
+/// [`sync`](Backend::sync) makes deleting objects durable.
+///
+/// The [`Layout`] of the directory is detected when it's opened.
 #[derive(Clone)]
 pub struct Directory {
     target: PathBuf,
+
+    /// Switched to sharded when another process changes the layout.
+    layout: Arc<Mutex<Layout>>,
     read_lru: Arc<Mutex<LruCache<ObjectId, Arc<Buffer>>>>,
+
+    /// Directories where files have been deleted since the last sync.
+    dirty: Arc<Mutex<HashSet<PathBuf>>>,
 }
 
 impl Directory {
@@ -118,25 +162,221 @@ impl Directory {
         target: impl AsRef<Path>,
         limit: NonZeroUsize,
     ) -> Result<Arc<Directory>> {
+        Self::open(target, limit).map(Arc::new)
+    }
+
+    /// This is equivalent to `Directory::with_layout(target, 256, Layout::Sharded)`
+    pub fn sharded(target: impl AsRef<Path>) -> Result<Arc<Directory>> {
+        Self::with_layout(target, 256.try_into().unwrap(), Layout::Sharded)
+    }
+
+    /// Open a directory with the given `layout`.
+    ///
+    /// A flat directory can be switched to a sharded layout, in which
+    /// case new objects are written into subdirectories, and existing
+    /// ones remain readable until they are [migrated](Self::migrate).
+    ///
+    /// Returns an error if a sharded directory is opened as flat.
+    pub fn with_layout(
+        target: impl AsRef<Path>,
+        limit: NonZeroUsize,
+        layout: Layout,
+    ) -> Result<Arc<Directory>> {
+        let directory = Self::open(target, limit)?;
+
+        match (directory.layout(), layout) {
+            (Layout::Flat, Layout::Sharded) => {
+                File::create(directory.target.join(SHARDED_MARKER))?.sync_all()?;
+                sync_dir(&directory.target)?;
+                *directory.layout.lock().unwrap() = Layout::Sharded;
+            }
+            (Layout::Sharded, Layout::Flat) => {
+                return Err(anyhow::anyhow!("the directory has a sharded layout").into())
+            }
+            _ => {}
+        }
+
+        Ok(Arc::new(directory))
+    }
+
+    fn open(target: impl AsRef<Path>, limit: NonZeroUsize) -> Result<Directory> {
         std::fs::create_dir_all(&target)?;
         let absolute = target.as_ref().canonicalize()?;
 
-        Ok(Arc::new(Directory {
+        let layout = match absolute.join(SHARDED_MARKER).exists() {
+            true => Layout::Sharded,
+            false => Layout::Flat,
+        };
+
+        Ok(Directory {
             target: absolute,
+            layout: Arc::new(Mutex::new(layout)),
             read_lru: Arc::new(Mutex::new(LruCache::new(limit))),
-        }))
+            dirty: Default::default(),
+        })
     }
 
     pub fn path(&self) -> &Path {
         &self.target
     }
+
+    pub fn layout(&self) -> Layout {
+        *self.layout.lock().unwrap()
+    }
+
+    /// Pick up a sharded layout that another process has switched to
+    /// since the directory was opened.
+    fn detect_layout(&self) {
+        let mut layout = self.layout.lock().unwrap();
+        if *layout == Layout::Flat && self.target.join(SHARDED_MARKER).exists() {
+            *layout = Layout::Sharded;
+        }
+    }
+
+    /// Move the objects that are stored flat into the subdirectories
+    /// of a sharded layout.
+    ///
+    /// Objects remain readable while they are moved, and an
+    /// interrupted migration can be continued by calling this again.
+    ///
+    /// Processes that opened the directory before the layout was
+    /// switched pick it up before their next write, but a write that
+    /// races with the switch may still end up in the flat layout. If
+    /// an object has both a flat and a sharded copy, the one that was
+    /// modified last is kept.
+    ///
+    /// Returns the number of objects moved, or an error if the
+    /// directory doesn't have a sharded layout.
+    pub fn migrate(&self) -> Result<usize> {
+        if self.layout() != Layout::Sharded {
+            return Err(anyhow::anyhow!("only a sharded directory can be migrated").into());
+        }
+
+        let mut flat = vec![];
+        for entry in fs::read_dir(&self.target)? {
+            if let Some(info) = object_info(entry?)? {
+                flat.push(info.id);
+            }
+        }
+
+        let mut moved = 0;
+        for id in flat.iter() {
+            let path = self.object_path(id);
+            let flat_path = self.flat_path(id);
+            if !prefer_flat(&path, &flat_path) {
+                fs::remove_file(flat_path)?;
+                continue;
+            }
+
+            self.create_shard(&path)?;
+            fs::rename(flat_path, &path)?;
+            sync_dir(parent(&path))?;
+            moved += 1;
+        }
+
+        sync_dir(&self.target)?;
+        Ok(moved)
+    }
+
+    fn flat_path(&self, id: &ObjectId) -> PathBuf {
+        self.target.join(id.to_string())
+    }
+
+    fn object_path(&self, id: &ObjectId) -> PathBuf {
+        let name = id.to_string();
+        match self.layout() {
+            Layout::Flat => self.target.join(name),
+            Layout::Sharded => sharded_path(&self.target, &name),
+        }
+    }
+
+    /// Create the subdirectories for the object at `path`, if they
+    /// don't exist yet.
+    fn create_shard(&self, path: &Path) -> Result<()> {
+        let shard = parent(path);
+        if self.layout() == Layout::Flat || shard.exists() {
+            return Ok(());
+        }
+
+        fs::create_dir_all(shard)?;
+        sync_dir(parent(shard))?;
+        sync_dir(&self.target)?;
+        Ok(())
+    }
+
+    /// Read the object `id`.
+    ///
+    /// In a sharded directory, the flat copy is read if the object
+    /// hasn't been migrated, or if a process that hadn't picked up
+    /// the new layout wrote it last.
+    fn read_buf(&self, id: &ObjectId) -> Result<Buffer> {
+        let path = self.object_path(id);
+        match self.layout() {
+            Layout::Sharded if prefer_flat(&path, &self.flat_path(id)) => {
+                get_buf(self.flat_path(id))
+            }
+            _ => get_buf(path),
+        }
+    }
+
+    /// Delete the object `id`, including the flat copy of objects
+    /// that haven't been migrated, so it doesn't show up again.
+    fn remove_file(&self, id: &ObjectId) -> Result<()> {
+        let mut paths = vec![self.object_path(id)];
+        if self.layout() == Layout::Sharded {
+            paths.push(self.flat_path(id));
+        }
+
+        let mut found = false;
+        for path in paths {
+            match fs::remove_file(&path) {
+                Ok(()) => {
+                    self.dirty.lock().unwrap().insert(parent(&path).to_owned());
+                    found = true;
+                }
+                Err(error) if error.kind() == io::ErrorKind::NotFound => {}
+                Err(error) => return Err(error.into()),
+            }
+        }
+
+        if !found {
+            return Err(io::Error::from(io::ErrorKind::NotFound).into());
+        }
+
+        Ok(())
+    }
 }
 
 impl Backend for Directory {
     fn write_object(&self, object: &WriteObject) -> Result<()> {
-        let filename = self.target.join(object.id().to_string());
-        fs::write(filename, object.as_inner())?;
-        Ok(())
+        self.detect_layout();
+        let filename = self.object_path(object.id());
+        self.create_shard(&filename)?;
+
+        // the name of temporary files never looks like an object
+        let temp = filename.with_file_name(format!(
+            "{}.{}-{}.tmp",
+            object.id().to_string(),
+            process::id(),
+            TEMP_COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+
+        let written = File::create(&temp)
+            .and_then(|mut file| {
+                file.write_all(object.as_inner())?;
+                file.sync_all()
+            })
+            .and_then(|_| fs::rename(&temp, &filename));
+
+        if let Err(error) = written {
+            let _ = fs::remove_file(&temp);
+            return Err(error.into());
+        }
+
+        // the cached buffer still has the contents of the replaced file
+        let _ = self.read_lru.lock().unwrap().pop(object.id());
+
+        sync_dir(parent(&filename))
     }
 
     fn read_object(&self, id: &ObjectId) -> Result<Arc<ReadObject>> {
@@ -148,8 +388,7 @@ impl Backend for Directory {
         match lru {
             Some(handle) => Ok(Object::with_id(*id, ReadBuffer::with_inner(handle)).into()),
             None => {
-                let path = self.target.join(id.to_string());
-                let buffer = Arc::new(get_buf(path)?);
+                let buffer = Arc::new(self.read_buf(id)?);
 
                 self.read_lru.lock().unwrap().put(*id, buffer.clone());
                 Ok(Object::with_id(*id, ReadBuffer::with_inner(buffer)).into())
@@ -157,18 +396,24 @@ impl Backend for Directory {
         }
     }
 
+    fn read_fresh(&self, id: &ObjectId) -> Result<Arc<ReadObject>> {
+        // another process may have rewritten the file since it was cached
+        let buffer = Arc::new(self.read_buf(id)?);
+
+        self.read_lru.lock().unwrap().put(*id, buffer.clone());
+        Ok(Object::with_id(*id, ReadBuffer::with_inner(buffer)).into())
+    }
+
     #[cfg(all(windows, feature = "mmap"))]
     fn delete(&self, objects: &[ObjectId]) -> Result<()> {
-        use super::BackendError;
-
         for id in objects {
             self.read_lru
                 .lock()
                 .unwrap()
                 .pop(id)
-                .ok_or(BackendError::NotFound { id: *id })
+                .ok_or(super::BackendError::NotFound { id: *id })
                 .and_then(|handle| Ok(handle.mark_for_delete()))
-                .or_else(|_| fs::remove_file(self.target.join(id.to_string())))?;
+                .or_else(|_| self.remove_file(id))?;
         }
 
         Ok(())
@@ -178,23 +423,149 @@ impl Backend for Directory {
     fn delete(&self, objects: &[ObjectId]) -> Result<()> {
         for id in objects {
             let _ = self.read_lru.lock().unwrap().pop(id);
-            fs::remove_file(self.target.join(id.to_string()))?;
+            self.remove_file(id)?;
         }
 
         Ok(())
     }
+
+    fn list_objects(&self) -> Result<ObjectList<'_>> {
+        let depth = match self.layout() {
+            Layout::Flat => 0,
+            Layout::Sharded => 2,
+        };
+
+        Ok(list_entries(fs::read_dir(&self.target)?, depth, false))
+    }
+
+    fn sync(&self) -> Result<()> {
+        let dirty = std::mem::take(&mut *self.dirty.lock().unwrap());
+        for dir in dirty.iter() {
+            sync_dir(dir)?;
+        }
+
+        sync_dir(&self.target)
+    }
+}
+
+/// List the objects in `entries`, descending into `depth` levels of
+/// shards.
+///
+/// If an object in a sharded directory has both a flat and a sharded
+/// copy, only the one that is read is listed.
+fn list_entries(entries: fs::ReadDir, depth: usize, shard: bool) -> ObjectList<'static> {
+    Box::new(entries.flat_map(move |entry| -> ObjectList<'static> {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(error) => return Box::new(iter::once(Err(error.into()))),
+        };
+
+        if depth > 0 && is_shard(&entry) {
+            return match fs::read_dir(entry.path()) {
+                Ok(entries) => list_entries(entries, depth - 1, true),
+                Err(error) => Box::new(iter::once(Err(error.into()))),
+            };
+        }
+
+        let path = entry.path();
+        let info = object_info(entry).transpose().filter(|info| match info {
+            Ok(info) if depth > 0 => {
+                prefer_flat(&sharded_path(parent(&path), &info.id.to_string()), &path)
+            }
+            Ok(info) if shard => {
+                let target = parent(parent(parent(&path)));
+                !prefer_flat(&path, &target.join(info.id.to_string()))
+            }
+            _ => true,
+        });
+
+        Box::new(info.into_iter())
+    }))
+}
+
+fn sharded_path(target: &Path, name: &str) -> PathBuf {
+    target.join(&name[..2]).join(&name[2..4]).join(name)
+}
+
+fn is_shard(entry: &fs::DirEntry) -> bool {
+    let name = entry.file_name();
+    let is_dir = entry.file_type().map(|t| t.is_dir()).unwrap_or(false);
+
+    is_dir
+        && name
+            .to_str()
+            .is_some_and(|name| name.len() == 2 && name.chars().all(|c| c.is_ascii_hexdigit()))
+}
+
+/// Returns `None` for anything that doesn't look like an object.
+fn object_info(entry: fs::DirEntry) -> Result<Option<ObjectInfo>> {
+    let id = match entry.file_name().to_str() {
+        Some(name) if name.len() == 2 * size_of::<ObjectId>() => match name.parse() {
+            Ok(id) => id,
+            Err(_) => return Ok(None),
+        },
+        _ => return Ok(None),
+    };
+
+    let metadata = entry.metadata()?;
+    if !metadata.is_file() {
+        return Ok(None);
+    }
+
+    Ok(Some(ObjectInfo {
+        id,
+        size: Some(metadata.len()),
+        modified: metadata.modified().ok(),
+    }))
+}
+
+/// Returns `true` if the `flat` copy of an object is used instead of
+/// the `sharded` one, because the sharded copy is missing, or the flat
+/// one has been modified since.
+fn prefer_flat(sharded: &Path, flat: &Path) -> bool {
+    let modified = |path: &Path| fs::metadata(path).and_then(|m| m.modified()).ok();
+
+    match (modified(sharded), modified(flat)) {
+        (Some(sharded), Some(flat)) => flat > sharded,
+        (Some(_), None) => false,
+        (None, _) => true,
+    }
+}
+
+fn parent(path: &Path) -> &Path {
+    path.parent().expect("objects are always in a directory")
+}
+
+/// Make the files that have been created, renamed, or deleted in
+/// `dir` durable.
+#[cfg(unix)]
+fn sync_dir(dir: &Path) -> Result<()> {
+    File::open(dir)?.sync_all()?;
+    Ok(())
+}
+
+/// Directories can't be opened as files on this platform, and
+/// renames are durable once they return.
+#[cfg(not(unix))]
+fn sync_dir(_dir: &Path) -> Result<()> {
+    Ok(())
 }
 
 #[cfg(test)]
 mod test {
     use crate::{
-        backends::{Backend, Directory},
-        object::{Object, ReadBuffer, WriteObject},
-        TEST_DATA_DIR,
+        backends::{Backend, Directory, Layout},
+        crypto::UsernamePassword,
+        fields::Serialized,
+        object::{Object, ObjectId, ReadBuffer, WriteObject},
+        Index, Infinitree, TEST_DATA_DIR,
     };
+    use ring::rand::SystemRandom;
     use std::{
+        fs::File,
         path::{Path, PathBuf},
         sync::Arc,
+        time::{Duration, SystemTime},
     };
 
     #[test]
@@ -220,6 +591,247 @@ mod test {
         assert!(!test_filename.exists());
     }
 
+    #[test]
+    fn write_then_list() {
+        let data_root = Path::new(TEST_DATA_DIR).join("dir-list");
+        let _ = std::fs::remove_dir_all(&data_root);
+
+        let backend = Directory::new(&data_root).unwrap();
+        let object = WriteObject::default();
+        backend.write_object(&object).unwrap();
+        std::fs::write(data_root.join("not-an-object"), b"").unwrap();
+
+        let listed = backend
+            .list_objects()
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+
+        assert_eq!(listed.len(), 1);
+        assert_eq!(&listed[0].id, object.id());
+        assert_eq!(listed[0].size, Some(object.as_inner().len() as u64));
+    }
+
+    #[test]
+    fn overwrite_leaves_no_temporary_files() {
+        let data_root = Path::new(TEST_DATA_DIR).join("dir-overwrite");
+        let _ = std::fs::remove_dir_all(&data_root);
+
+        let backend = Directory::new(&data_root).unwrap();
+        let mut object = WriteObject::default();
+        backend.write_object(&object).unwrap();
+
+        object.as_inner_mut().fill(1);
+        backend.write_object(&object).unwrap();
+        backend.sync().unwrap();
+
+        assert_eq!(std::fs::read_dir(&data_root).unwrap().count(), 1);
+        assert_eq!(
+            backend.read_fresh(object.id()).unwrap().as_inner(),
+            object.as_inner()
+        );
+    }
+
+    #[test]
+    fn read_after_overwrite() {
+        let data_root = Path::new(TEST_DATA_DIR).join("dir-reread");
+        let _ = std::fs::remove_dir_all(&data_root);
+
+        let backend = Directory::new(&data_root).unwrap();
+        let mut object = WriteObject::default();
+        backend.write_object(&object).unwrap();
+        assert_eq!(
+            backend.read_object(object.id()).unwrap().as_inner(),
+            object.as_inner()
+        );
+
+        object.as_inner_mut().fill(1);
+        backend.write_object(&object).unwrap();
+        assert_eq!(
+            backend.read_object(object.id()).unwrap().as_inner(),
+            object.as_inner()
+        );
+    }
+
+    #[test]
+    fn sharded_layout_is_detected() {
+        let data_root = Path::new(TEST_DATA_DIR).join("dir-sharded");
+        let _ = std::fs::remove_dir_all(&data_root);
+
+        let backend = Directory::sharded(&data_root).unwrap();
+        let object = WriteObject::default();
+        backend.write_object(&object).unwrap();
+
+        let name = object.id().to_string();
+        assert!(data_root
+            .join(&name[..2])
+            .join(&name[2..4])
+            .join(&name)
+            .is_file());
+
+        let backend = Directory::new(&data_root).unwrap();
+        assert_eq!(backend.layout(), Layout::Sharded);
+        assert!(Directory::with_layout(&data_root, 1.try_into().unwrap(), Layout::Flat).is_err());
+
+        let listed = backend
+            .list_objects()
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(listed.len(), 1);
+        assert_eq!(&listed[0].id, object.id());
+
+        backend.delete(&[*object.id()]).unwrap();
+        backend.sync().unwrap();
+        assert!(backend.read_fresh(object.id()).is_err());
+    }
+
+    #[test]
+    fn migrate_flat_to_sharded() {
+        let data_root = Path::new(TEST_DATA_DIR).join("dir-migrate");
+        let _ = std::fs::remove_dir_all(&data_root);
+
+        let flat = Directory::new(&data_root).unwrap();
+        let objects = (0..3)
+            .map(|_| {
+                let mut object = WriteObject::default();
+                object.set_id(ObjectId::new(&SystemRandom::new()));
+                object
+            })
+            .collect::<Vec<_>>();
+        for object in objects.iter() {
+            flat.write_object(object).unwrap();
+        }
+
+        // flat objects can still be read before they're migrated
+        let backend = Directory::sharded(&data_root).unwrap();
+        assert_eq!(
+            backend.read_object(objects[0].id()).unwrap().as_inner(),
+            objects[0].as_inner()
+        );
+        assert_eq!(backend.list_objects().unwrap().count(), 3);
+
+        assert_eq!(backend.migrate().unwrap(), 3);
+        assert_eq!(backend.migrate().unwrap(), 0);
+
+        // only the marker of the layout is left at the top
+        assert_eq!(
+            std::fs::read_dir(&data_root)
+                .unwrap()
+                .filter(|entry| entry.as_ref().unwrap().file_type().unwrap().is_file())
+                .count(),
+            1
+        );
+
+        for object in objects.iter() {
+            assert_eq!(
+                backend.read_fresh(object.id()).unwrap().as_inner(),
+                object.as_inner()
+            );
+        }
+        assert_eq!(backend.list_objects().unwrap().count(), 3);
+    }
+
+    #[test]
+    fn migrate_keeps_writes_of_stale_processes() {
+        let data_root = Path::new(TEST_DATA_DIR).join("dir-migrate-stale");
+        let _ = std::fs::remove_dir_all(&data_root);
+
+        let stale = Directory::new(&data_root).unwrap();
+        let backend = Directory::sharded(&data_root).unwrap();
+
+        // the new layout is picked up before writing
+        let mut object = WriteObject::default();
+        object.set_id(ObjectId::new(&SystemRandom::new()));
+        stale.write_object(&object).unwrap();
+        assert_eq!(stale.layout(), Layout::Sharded);
+
+        let flat = data_root.join(object.id().to_string());
+        assert!(!flat.exists());
+
+        // a write that raced with the switch is newer than the shard
+        object.as_inner_mut().fill(1);
+        std::fs::write(&flat, object.as_inner()).unwrap();
+        File::options()
+            .write(true)
+            .open(&flat)
+            .unwrap()
+            .set_modified(SystemTime::now() + Duration::from_secs(1))
+            .unwrap();
+
+        // it's read and listed before it's migrated
+        assert_eq!(
+            backend.read_fresh(object.id()).unwrap().as_inner(),
+            object.as_inner()
+        );
+        let listed = backend
+            .list_objects()
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(listed.len(), 1);
+        assert_eq!(
+            listed[0].modified,
+            std::fs::metadata(&flat).unwrap().modified().ok()
+        );
+
+        assert_eq!(backend.migrate().unwrap(), 1);
+        assert!(!flat.exists());
+        assert_eq!(
+            backend.read_fresh(object.id()).unwrap().as_inner(),
+            object.as_inner()
+        );
+    }
+
+    #[derive(Index, Default)]
+    struct State {
+        value: Serialized<usize>,
+    }
+
+    fn key() -> UsernamePassword {
+        UsernamePassword::with_credentials("dir_user".to_string(), "dir_password".to_string())
+            .unwrap()
+    }
+
+    #[test]
+    fn migrate_keeps_newer_sharded_copies() {
+        let data_root = Path::new(TEST_DATA_DIR).join("dir-migrate-tree");
+        let _ = std::fs::remove_dir_all(&data_root);
+
+        {
+            let tree =
+                Infinitree::<State>::empty(Directory::new(&data_root).unwrap(), key()).unwrap();
+            *tree.index().value.write() = 1;
+            tree.commit("flat").unwrap();
+        }
+
+        // the root object is rewritten into a shard
+        let backend = Directory::sharded(&data_root).unwrap();
+        {
+            let tree = Infinitree::<State>::open(backend.clone(), key()).unwrap();
+            tree.load_all().unwrap();
+            *tree.index().value.write() = 2;
+            tree.commit("sharded").unwrap();
+        }
+
+        let listed = backend
+            .list_objects()
+            .unwrap()
+            .map(|info| info.unwrap().id)
+            .collect::<Vec<_>>();
+        let unique = listed.iter().collect::<std::collections::HashSet<_>>();
+        assert_eq!(listed.len(), unique.len());
+
+        let moved = backend.migrate().unwrap();
+        assert!(moved < listed.len());
+        assert_eq!(backend.list_objects().unwrap().count(), listed.len());
+
+        let tree = Infinitree::<State>::open(Directory::new(&data_root).unwrap(), key()).unwrap();
+        assert_eq!(tree.commit_list().len(), 2);
+        tree.load_all().unwrap();
+        assert_eq!(*tree.index().value.read(), 2);
+    }
+
     fn write_object_get_ref_then_delete(
         dir_name: &'static str,
     ) -> (Arc<Object<ReadBuffer>>, PathBuf) {
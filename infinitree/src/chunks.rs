@@ -3,6 +3,9 @@ use crate::object::ObjectId;
 use serde::{Deserialize, Serialize};
 use std::mem::size_of;
 
+mod visit;
+pub(crate) use visit::visit_pointers;
+
 #[derive(Debug, Clone, Eq, PartialEq, Hash, Default, Serialize, Deserialize)]
 pub struct RawChunkPointer {
     pub offs: u32,
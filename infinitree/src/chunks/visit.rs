@@ -0,0 +1,345 @@
+//! Find every [`ChunkPointer`] in a serializable value.
+//!
+//! Index fields can store `ChunkPointer`s in arbitrary positions of
+//! their records, e.g. nested in a
+//! [`SizedPointer`](crate::object::serializer::SizedPointer) or a
+//! [`Stream`](crate::object::Stream). Instead of requiring every type
+//! to report these manually, we run the value through a [`Serializer`]
+//! that discards everything, except for the `ChunkPointer`s.
+use super::{ChunkPointer, RawChunkPointer};
+use serde::{
+    ser::{self, Error as _},
+    Serialize, Serializer,
+};
+
+type Error = rmp_serde::encode::Error;
+type Result<T> = std::result::Result<T, Error>;
+
+/// The name `ChunkPointer` uses when serialized as a newtype struct.
+const CHUNK_POINTER: &str = "ChunkPointer";
+
+/// Call `visitor` for every [`ChunkPointer`] that's contained in `value`.
+pub(crate) fn visit_pointers<T: Serialize + ?Sized>(
+    value: &T,
+    visitor: &mut dyn FnMut(&ChunkPointer),
+) -> Result<()> {
+    value.serialize(&mut PointerVisitor { visitor })
+}
+
+struct PointerVisitor<'v> {
+    visitor: &'v mut dyn FnMut(&ChunkPointer),
+}
+
+impl<'a, 'v> Serializer for &'a mut PointerVisitor<'v> {
+    type Ok = ();
+    type Error = Error;
+
+    type SerializeSeq = Self;
+    type SerializeTuple = Self;
+    type SerializeTupleStruct = Self;
+    type SerializeTupleVariant = Self;
+    type SerializeMap = Self;
+    type SerializeStruct = Self;
+    type SerializeStructVariant = Self;
+
+    fn is_human_readable(&self) -> bool {
+        false
+    }
+
+    fn serialize_bool(self, _v: bool) -> Result<()> {
+        Ok(())
+    }
+
+    fn serialize_i8(self, _v: i8) -> Result<()> {
+        Ok(())
+    }
+
+    fn serialize_i16(self, _v: i16) -> Result<()> {
+        Ok(())
+    }
+
+    fn serialize_i32(self, _v: i32) -> Result<()> {
+        Ok(())
+    }
+
+    fn serialize_i64(self, _v: i64) -> Result<()> {
+        Ok(())
+    }
+
+    fn serialize_i128(self, _v: i128) -> Result<()> {
+        Ok(())
+    }
+
+    fn serialize_u8(self, _v: u8) -> Result<()> {
+        Ok(())
+    }
+
+    fn serialize_u16(self, _v: u16) -> Result<()> {
+        Ok(())
+    }
+
+    fn serialize_u32(self, _v: u32) -> Result<()> {
+        Ok(())
+    }
+
+    fn serialize_u64(self, _v: u64) -> Result<()> {
+        Ok(())
+    }
+
+    fn serialize_u128(self, _v: u128) -> Result<()> {
+        Ok(())
+    }
+
+    fn serialize_f32(self, _v: f32) -> Result<()> {
+        Ok(())
+    }
+
+    fn serialize_f64(self, _v: f64) -> Result<()> {
+        Ok(())
+    }
+
+    fn serialize_char(self, _v: char) -> Result<()> {
+        Ok(())
+    }
+
+    fn serialize_str(self, _v: &str) -> Result<()> {
+        Ok(())
+    }
+
+    fn serialize_bytes(self, _v: &[u8]) -> Result<()> {
+        Ok(())
+    }
+
+    fn serialize_none(self) -> Result<()> {
+        Ok(())
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<()> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<()> {
+        Ok(())
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<()> {
+        Ok(())
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+    ) -> Result<()> {
+        Ok(())
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        name: &'static str,
+        value: &T,
+    ) -> Result<()> {
+        if name != CHUNK_POINTER {
+            return value.serialize(self);
+        }
+
+        // We can't downcast `value`, so take a round trip through the
+        // wire format instead.
+        let raw: RawChunkPointer = crate::deserialize_from_slice(&crate::serialize_to_vec(value)?)
+            .map_err(Error::custom)?;
+        (self.visitor)(&raw.into());
+
+        Ok(())
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        value: &T,
+    ) -> Result<()> {
+        value.serialize(self)
+    }
+
+    fn serialize_seq(self, _len: Option<usize>) -> Result<Self> {
+        Ok(self)
+    }
+
+    fn serialize_tuple(self, _len: usize) -> Result<Self> {
+        Ok(self)
+    }
+
+    fn serialize_tuple_struct(self, _name: &'static str, _len: usize) -> Result<Self> {
+        Ok(self)
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self> {
+        Ok(self)
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<Self> {
+        Ok(self)
+    }
+
+    fn serialize_struct(self, _name: &'static str, _len: usize) -> Result<Self> {
+        Ok(self)
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self> {
+        Ok(self)
+    }
+}
+
+impl<'a, 'v> ser::SerializeSeq for &'a mut PointerVisitor<'v> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<()> {
+        Ok(())
+    }
+}
+
+impl<'a, 'v> ser::SerializeTuple for &'a mut PointerVisitor<'v> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<()> {
+        Ok(())
+    }
+}
+
+impl<'a, 'v> ser::SerializeTupleStruct for &'a mut PointerVisitor<'v> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<()> {
+        Ok(())
+    }
+}
+
+impl<'a, 'v> ser::SerializeTupleVariant for &'a mut PointerVisitor<'v> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<()> {
+        Ok(())
+    }
+}
+
+impl<'a, 'v> ser::SerializeMap for &'a mut PointerVisitor<'v> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<()> {
+        key.serialize(&mut **self)
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<()> {
+        Ok(())
+    }
+}
+
+impl<'a, 'v> ser::SerializeStruct for &'a mut PointerVisitor<'v> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        _key: &'static str,
+        value: &T,
+    ) -> Result<()> {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<()> {
+        Ok(())
+    }
+}
+
+impl<'a, 'v> ser::SerializeStructVariant for &'a mut PointerVisitor<'v> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        _key: &'static str,
+        value: &T,
+    ) -> Result<()> {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::visit_pointers;
+    use crate::{chunks::RawChunkPointer, object::Stream, ChunkPointer, ObjectId};
+    use std::collections::HashMap;
+
+    fn pointer(object: u8) -> ChunkPointer {
+        RawChunkPointer {
+            object: ObjectId::from_bytes([object; 32]),
+            ..Default::default()
+        }
+        .into()
+    }
+
+    #[test]
+    fn find_nested_pointers() {
+        let value = (
+            "key",
+            Some(pointer(1)),
+            HashMap::from([(1usize, Stream::from(vec![pointer(2), pointer(3)]))]),
+        );
+
+        let mut found = vec![];
+        visit_pointers(&value, &mut |ptr| found.push(*ptr.object_id())).unwrap();
+        found.sort_by_key(|id| id.as_ref()[0]);
+
+        assert_eq!(
+            found,
+            vec![
+                ObjectId::from_bytes([1; 32]),
+                ObjectId::from_bytes([2; 32]),
+                ObjectId::from_bytes([3; 32])
+            ]
+        );
+    }
+}
@@ -80,6 +80,7 @@ mod header;
 mod ops;
 mod rawkey;
 mod scheme;
+mod signing;
 
 pub(crate) mod symmetric;
 pub(crate) use error::*;
@@ -87,6 +88,7 @@ pub(crate) use header::*;
 pub(crate) use ops::*;
 pub use rawkey::*;
 pub use scheme::*;
+pub use signing::*;
 pub use symmetric::UsernamePassword;
 
 #[cfg(feature = "cryptobox")]
@@ -213,7 +213,7 @@ impl ICryptoOps for CryptoBoxOps {
         target: &'buf mut [u8],
         source: &[u8],
         chunk_ptr: &ChunkPointer,
-    ) -> &'buf mut [u8] {
+    ) -> Result<&'buf mut [u8]> {
         let sk = self
             .1
             .sk
@@ -225,24 +225,26 @@ impl ICryptoOps for CryptoBoxOps {
         let start = chunk.offs as usize;
         let end = start + size;
 
-        let source = &source[start..end];
+        let source = source.get(start..end).ok_or(CryptoError::Authentication)?;
         let nonce = &chunk.object.as_ref()[..crypto_box_NONCEBYTES as usize];
 
-        unsafe {
-            assert!(
-                crypto_box_open_detached(
-                    target.as_mut_ptr(),
-                    source.as_ptr(),
-                    chunk.tag.as_ptr(),
-                    size.try_into().unwrap(),
-                    nonce.as_ptr(),
-                    chunk.key.as_ptr(),
-                    sk.expose_secret().as_ptr()
-                ) == 0
-            );
+        let result = unsafe {
+            crypto_box_open_detached(
+                target.as_mut_ptr(),
+                source.as_ptr(),
+                chunk.tag.as_ptr(),
+                size.try_into().unwrap(),
+                nonce.as_ptr(),
+                chunk.key.as_ptr(),
+                sk.expose_secret().as_ptr(),
+            )
+        };
+
+        if result != 0 {
+            return Err(CryptoError::Authentication);
         }
 
-        &mut target[..size]
+        Ok(&mut target[..size])
     }
 
     #[inline]
@@ -306,7 +308,9 @@ mod test {
         obj.write(&encrypted).unwrap();
 
         let mut decrypted = vec![0; SIZE];
-        crypto.decrypt_chunk(&mut decrypted, obj.as_ref(), &cp);
+        crypto
+            .decrypt_chunk(&mut decrypted, obj.as_ref(), &cp)
+            .unwrap();
 
         assert_eq!(&decrypted[..SIZE], CLEARTEXT);
     }
@@ -12,8 +12,12 @@ pub enum CryptoError {
         #[from]
         source: argon2::Error,
     },
+    #[error("Chunk failed authentication")]
+    Authentication,
     #[error("Unsupported operation")]
     Unsupported,
+    #[error("Invalid signing key")]
+    SigningKey,
     #[error("Fatal error")]
     Fatal,
 }
@@ -1,3 +1,4 @@
+use super::Result;
 use crate::{ChunkPointer, Digest, ObjectId};
 use blake3::Hasher;
 use std::sync::Arc;
@@ -20,7 +21,7 @@ pub(crate) trait ICryptoOps: Send + Sync {
         target: &'buf mut [u8],
         source: &[u8],
         chunk: &ChunkPointer,
-    ) -> &'buf mut [u8];
+    ) -> Result<&'buf mut [u8]>;
 
     /// Provide a hash (or HMAC) of `data`
     fn hash(&self, data: &[u8]) -> Digest;
@@ -62,7 +63,7 @@ macro_rules! key_type {
                 target: &'buf mut [u8],
                 source: &[u8],
                 chunk: &ChunkPointer,
-            ) -> &'buf mut [u8] {
+            ) -> Result<&'buf mut [u8]> {
                 self.0.decrypt_chunk(target, source, chunk)
             }
 
@@ -47,6 +47,10 @@ where
         self.header.root_object_id()
     }
 
+    fn backup_root_object_id(&self, slot: usize) -> Result<ObjectId> {
+        self.header.backup_root_object_id(slot)
+    }
+
     fn open_root(self: Arc<Self>, header: SealedHeader) -> Result<Header> {
         let (root_ptr, key) = self.header.clone().open_header(header, &self.convergence)?;
 
@@ -138,6 +142,10 @@ where
         self.opener.root_object_id()
     }
 
+    fn backup_root_object_id(&self, slot: usize) -> Result<ObjectId> {
+        self.opener.backup_root_object_id(slot)
+    }
+
     fn open_root(self: Arc<Self>, header: SealedHeader) -> Result<Header> {
         let (root_ptr, key) = self.opener.clone().open_header(header, &self.convergence)?;
 
@@ -170,6 +178,92 @@ where
     }
 }
 
+/// Guard against writing a tree by accident.
+///
+/// A tree opened with this key can't be committed to or resealed, as
+/// sealing a new root will fail.
+///
+/// Like the write-protect tab of a floppy disk, this only prevents
+/// mistakes, and it's not an access control. The wrapped key still
+/// holds every secret of the tree: the index and the storage are
+/// encrypted symmetrically, so any key that can read them can also
+/// write them, and the root is sealed with the same key that opens
+/// it. Don't hand this out to anyone who shouldn't be able to write
+/// the tree.
+///
+/// # Examples
+///
+/// ```no_run
+/// use infinitree::{*, crypto::*, fields::VersionedMap, backends::Directory};
+///
+/// let key = WriteProtected::new(
+///     UsernamePassword::with_credentials("username".to_string(),
+///                                        "password".to_string()).unwrap(),
+/// );
+///
+/// let tree = Infinitree::<VersionedMap<String, String>>::open(
+///     Directory::new("/storage").unwrap(),
+///     key
+/// ).unwrap();
+///
+/// assert!(tree.commit("this will fail").is_err());
+/// ```
+pub struct WriteProtected {
+    inner: Key,
+}
+
+impl WriteProtected {
+    pub fn new(key: impl Into<Key>) -> Self {
+        Self { inner: key.into() }
+    }
+}
+
+#[allow(clippy::from_over_into)]
+impl Into<Key> for WriteProtected {
+    fn into(self) -> Key {
+        Arc::new(self)
+    }
+}
+
+impl Scheme for WriteProtected {
+    fn root_object_id(&self) -> Result<ObjectId> {
+        self.inner.root_object_id()
+    }
+
+    fn backup_root_object_id(&self, slot: usize) -> Result<ObjectId> {
+        self.inner.backup_root_object_id(slot)
+    }
+
+    fn open_root(self: Arc<Self>, header: SealedHeader) -> Result<Header> {
+        let header = self.inner.clone().open_root(header)?;
+
+        Ok(Header {
+            root_ptr: header.root_ptr,
+            key: Arc::new(WriteProtected { inner: header.key }),
+        })
+    }
+
+    fn seal_root(&self, _root_ptr: &RawChunkPointer) -> Result<SealedHeader> {
+        Err(CryptoError::Unsupported)
+    }
+
+    fn chunk_key(&self) -> Result<ChunkKey> {
+        self.inner.chunk_key()
+    }
+
+    fn index_key(&self) -> Result<IndexKey> {
+        self.inner.index_key()
+    }
+
+    fn storage_key(&self) -> Result<StorageKey> {
+        self.inner.storage_key()
+    }
+
+    fn write_protected(&self) -> bool {
+        true
+    }
+}
+
 pub(crate) use private::*;
 pub(crate) mod private {
     use super::*;
@@ -180,12 +274,19 @@ pub(crate) mod private {
     /// operations that must be supported by a scheme.
     pub trait Scheme: Send + Sync {
         fn root_object_id(&self) -> Result<ObjectId>;
+        fn backup_root_object_id(&self, slot: usize) -> Result<ObjectId>;
         fn open_root(self: Arc<Self>, header: SealedHeader) -> Result<Header>;
         fn seal_root(&self, root_ptr: &RawChunkPointer) -> Result<SealedHeader>;
 
         fn chunk_key(&self) -> Result<ChunkKey>;
         fn index_key(&self) -> Result<IndexKey>;
         fn storage_key(&self) -> Result<StorageKey>;
+
+        /// Returns `true` if the key must not be used to write a
+        /// tree.
+        fn write_protected(&self) -> bool {
+            false
+        }
     }
 
     pub trait HeaderScheme: Send + Sync {
@@ -215,6 +316,7 @@ pub(crate) mod private {
         }
 
         fn root_object_id(&self) -> Result<ObjectId>;
+        fn backup_root_object_id(&self, slot: usize) -> Result<ObjectId>;
     }
 
     pub trait InternalScheme: Send + Sync {
@@ -0,0 +1,74 @@
+use super::{CryptoError, Result, SystemRandom};
+use ring::signature::{Ed25519KeyPair, KeyPair, UnparsedPublicKey, ED25519};
+use serde::{Deserialize, Serialize};
+
+/// The public key of a writer that signs commits.
+///
+/// Writers can be trusted by recording their keys in the tree using
+/// [`Infinitree::trust_writer`](crate::Infinitree::trust_writer).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct WriterKey([u8; 32]);
+
+impl WriterKey {
+    /// Use a raw Ed25519 public key.
+    pub fn from_bytes(bytes: [u8; 32]) -> Self {
+        Self(bytes)
+    }
+
+    /// The raw Ed25519 public key.
+    pub fn as_bytes(&self) -> &[u8; 32] {
+        &self.0
+    }
+
+    /// Return `true` if `signature` is a valid signature of
+    /// `message` by this writer.
+    pub(crate) fn verify(&self, message: &[u8], signature: &[u8]) -> bool {
+        UnparsedPublicKey::new(&ED25519, &self.0)
+            .verify(message, signature)
+            .is_ok()
+    }
+}
+
+/// An Ed25519 key that is used to sign commits.
+///
+/// # Examples
+///
+/// ```
+/// use infinitree::crypto::SigningKey;
+///
+/// let pkcs8 = SigningKey::generate_pkcs8().unwrap();
+/// let key = SigningKey::from_pkcs8(&pkcs8).unwrap();
+///
+/// // share this with the readers of the tree
+/// let writer = key.writer();
+/// ```
+pub struct SigningKey(Ed25519KeyPair);
+
+impl SigningKey {
+    /// Generate a new key.
+    ///
+    /// The key is returned as a PKCS#8 document, so it can be stored,
+    /// then loaded using [`from_pkcs8`](Self::from_pkcs8).
+    pub fn generate_pkcs8() -> Result<Vec<u8>> {
+        let document = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new())?;
+        Ok(document.as_ref().to_vec())
+    }
+
+    /// Load a key from a PKCS#8 document.
+    pub fn from_pkcs8(pkcs8: &[u8]) -> Result<Self> {
+        Ed25519KeyPair::from_pkcs8(pkcs8)
+            .map(Self)
+            .map_err(|_| CryptoError::SigningKey)
+    }
+
+    /// The public key of the writer.
+    pub fn writer(&self) -> WriterKey {
+        let mut bytes = [0; 32];
+        bytes.copy_from_slice(self.0.public_key().as_ref());
+        WriterKey(bytes)
+    }
+
+    pub(crate) fn sign(&self, message: &[u8]) -> Vec<u8> {
+        self.0.sign(message).as_ref().to_vec()
+    }
+}
@@ -84,6 +84,10 @@ mod private {
             root_object_id(&self.master_key)
         }
 
+        fn backup_root_object_id(&self, slot: usize) -> Result<ObjectId> {
+            backup_root_object_id(&self.master_key, slot)
+        }
+
         fn open_root(&self, sealed: SealedHeader) -> Result<OpenHeader> {
             let mut buf = sealed.0;
 
@@ -96,7 +100,7 @@ mod private {
 
             let _ = aead
                 .open_in_place(nonce, aead::Aad::empty(), &mut buf[..HEADER_CYPHERTEXT])
-                .map_err(CryptoError::from)?;
+                .map_err(|_| CryptoError::Authentication)?;
 
             Ok(OpenHeader(buf))
         }
@@ -251,7 +255,7 @@ mod private {
             target: &'buf mut [u8],
             source: &[u8],
             chunk_ptr: &ChunkPointer,
-        ) -> &'buf mut [u8] {
+        ) -> Result<&'buf mut [u8]> {
             let chunk = chunk_ptr.as_raw();
             let size = chunk.size as usize;
             let cyphertext_size = size + chunk.tag.len();
@@ -261,7 +265,10 @@ mod private {
             let start = chunk.offs as usize;
             let end = start + size;
 
-            target[..size].copy_from_slice(&source[start..end]);
+            // a truncated object can't contain a valid chunk
+            let source = source.get(start..end).ok_or(CryptoError::Authentication)?;
+
+            target[..size].copy_from_slice(source);
             target[size..cyphertext_size].copy_from_slice(&chunk.tag);
 
             let aead = get_aead(chunk.key.into());
@@ -270,9 +277,9 @@ mod private {
                 aead::Aad::from(&chunk.object),
                 &mut target[..cyphertext_size],
             )
-            .unwrap();
+            .map_err(|_| CryptoError::Authentication)?;
 
-            &mut target[..size]
+            Ok(&mut target[..size])
         }
 
         #[inline]
@@ -298,6 +305,13 @@ pub(super) fn root_object_id(master_key: &RawKey) -> Result<ObjectId> {
         .map(|k| ObjectId::from_bytes(k.expose_secret()))
 }
 
+pub(super) fn backup_root_object_id(master_key: &RawKey, slot: usize) -> Result<ObjectId> {
+    derive_subkey(master_key, "zerostash.com 2022 backup root object id").map(|k| {
+        let id = blake3::keyed_hash(k.expose_secret(), &(slot as u64).to_le_bytes());
+        ObjectId::from_bytes(id.as_bytes())
+    })
+}
+
 fn seal_header(master_key: &RawKey, open: OpenHeader) -> Result<SealedHeader> {
     let mut output = open.0;
     let random = SystemRandom::new();
@@ -403,7 +417,9 @@ mod test {
         obj.write(&encrypted).unwrap();
 
         let mut decrypted = vec![0; size + cp.as_raw().tag.len()];
-        crypto.decrypt_chunk(&mut decrypted, obj.as_ref(), &cp);
+        crypto
+            .decrypt_chunk(&mut decrypted, obj.as_ref(), &cp)
+            .unwrap();
 
         assert_eq!(&decrypted[..size], cleartext.as_ref());
     }
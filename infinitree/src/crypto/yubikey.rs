@@ -4,7 +4,10 @@
 //! provides all the utilities to program a Yubikey.
 //!
 //! See the documentation for [`YubikeyCR`] for additional details.
-use super::{symmetric::Symmetric, *};
+use super::{
+    symmetric::{backup_root_object_id, Symmetric},
+    *,
+};
 use crate::ObjectId;
 use ring::aead;
 use secrecy::{ExposeSecret, SecretString};
@@ -116,7 +119,7 @@ mod private {
 
             let _ = aead
                 .open_in_place(nonce, aead::Aad::empty(), &mut sealed[..HEADER_CYPHERTEXT])
-                .map_err(CryptoError::from)?;
+                .map_err(|_| CryptoError::Authentication)?;
 
             Ok(OpenHeader(sealed))
         }
@@ -162,6 +165,10 @@ mod private {
         fn root_object_id(&self) -> Result<ObjectId> {
             super::symmetric::root_object_id(&self.master_key)
         }
+
+        fn backup_root_object_id(&self, slot: usize) -> Result<ObjectId> {
+            backup_root_object_id(&self.master_key, slot)
+        }
     }
 }
 
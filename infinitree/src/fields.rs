@@ -31,11 +31,18 @@
 //! in the [`index`](super) module.
 
 use crate::{
-    index::{FieldReader, TransactionList},
-    object::{self, AEADReader, Pool},
+    chunks::visit_pointers,
+    index::{read_record, FieldReader, FieldWriter, Transaction, TransactionList},
+    object::{self, AEADReader, DeserializeStream, Pool},
+    ChunkPointer,
 };
 use serde::{de::DeserializeOwned, Serialize};
-use std::{cmp::Eq, hash::Hash, sync::Arc};
+use std::{
+    cmp::Eq,
+    collections::{HashMap, HashSet},
+    hash::Hash,
+    sync::Arc,
+};
 
 /// Marker trait for values that can be serialized and used as a
 /// value for an index field
@@ -68,6 +75,13 @@ pub use query::*;
 mod serialized;
 pub use serialized::Serialized;
 
+mod merge;
+pub use merge::{Conflict, Resolution};
+
+mod journal;
+pub use journal::JournalLog;
+pub(crate) use journal::{JournalEntry, JournalSlot, TypedJournal};
+
 mod versioned;
 pub use versioned::list::LinkedList;
 pub use versioned::map::VersionedMap;
@@ -81,7 +95,7 @@ pub(crate) use strategy::Strategy;
 pub use strategy::{LocalField, SparseField};
 
 pub mod intent;
-pub use intent::{Intent, Load, Query, Store};
+pub use intent::{Intent, Journal, Load, Merge, Query, Refresh, Revert, Squash, Store, Walk};
 
 /// Query an index field, but do not automatically load it into memory
 ///
@@ -122,8 +136,59 @@ pub trait Collection {
     /// Load the full record, and return it
     fn load(from: Self::Serialized, object: &mut dyn object::Reader) -> Self::Item;
 
+    /// Load the full record, or return an error if the data it refers
+    /// to can't be read from `object`.
+    ///
+    /// This is used to [`Walk`] the values of a collection without
+    /// panicking on a damaged tree. The default implementation calls
+    /// [`load`](Self::load), so collections that read from `object`
+    /// should implement this, too.
+    fn try_load(
+        from: Self::Serialized,
+        object: &mut dyn object::Reader,
+    ) -> object::Result<Self::Item> {
+        Ok(Self::load(from, object))
+    }
+
     /// Store the deserialized record in the collection
     fn insert(&mut self, record: Self::Item);
+
+    /// Store a record that is newer than anything in the collection,
+    /// replacing the existing record with the same key.
+    ///
+    /// This is used to [`Refresh`] a collection that has already
+    /// been loaded. The default implementation calls
+    /// [`insert`](Self::insert).
+    fn upsert(&mut self, record: Self::Item) {
+        self.insert(record)
+    }
+
+    /// Remove all records from the collection.
+    ///
+    /// A [`Snapshot`](depth::Snapshot) collection is cleared before
+    /// a newer snapshot is loaded by [`Refresh`], so this needs to be
+    /// implemented for them. The default implementation does
+    /// nothing.
+    fn clear(&mut self) {}
+
+    /// A record that removes the key of `record` from the collection
+    /// when it's loaded on top of the existing history.
+    ///
+    /// This is used to [`Revert`] an
+    /// [`Incremental`](depth::Incremental) collection. The default
+    /// implementation returns `None`, which means records can't be
+    /// removed once they have been committed.
+    fn removal(_record: Self::Serialized) -> Option<Self::Serialized> {
+        None
+    }
+
+    /// Set to `true` if a record replaces all older records with the
+    /// same key, like in a map.
+    ///
+    /// This allows [`Squash`] to only keep the newest record for
+    /// each key of an [`Incremental`](depth::Incremental) collection.
+    /// The default is `false`, which keeps every record.
+    const UNIQUE_KEYS: bool = false;
 }
 
 impl<T> Query for T
@@ -149,6 +214,408 @@ where
     }
 }
 
+impl<T> Walk for T
+where
+    T: Collection,
+    T::Serialized: Serialize,
+    T::Item: Serialize,
+{
+    fn walk(
+        &mut self,
+        pool: Pool<AEADReader>,
+        transaction_list: TransactionList,
+        values: bool,
+        visitor: &mut dyn FnMut(&ChunkPointer),
+    ) -> anyhow::Result<()> {
+        let mut reader = pool.lease()?;
+        for (_, _, stream) in transaction_list {
+            let mut records = DeserializeStream::new(stream.open_reader(pool.lease()?));
+
+            while let Some(record) = read_record::<T::Serialized>(&mut records)? {
+                visit_pointers(&record, visitor)?;
+
+                // sparse fields may reference further objects in
+                // their values, so we have to look at those, too
+                if values {
+                    visit_pointers(&T::try_load(record, &mut *reader)?, visitor)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl<T> Squash for T
+where
+    T: Collection,
+    T::Key: Serialize,
+    T::Serialized: Serialize + Send,
+{
+    fn squash(
+        &mut self,
+        pool: Pool<AEADReader>,
+        transaction_list: TransactionList,
+        full_history: bool,
+        mut transaction: &mut dyn Transaction,
+    ) -> anyhow::Result<()> {
+        if !(T::Depth::INCREMENTAL && T::UNIQUE_KEYS) {
+            for mut records in T::Depth::resolve(pool, transaction_list) {
+                while let Some(record) = read_record::<T::Serialized>(&mut records)? {
+                    transaction.write_next(record);
+                }
+            }
+
+            return Ok(());
+        }
+
+        // the history is read newest first, so only the first record
+        // of every key is needed
+        let mut seen = HashSet::new();
+        for mut records in T::Depth::resolve(pool, transaction_list) {
+            while let Some(record) = read_record::<T::Serialized>(&mut records)? {
+                if !seen.insert(crate::serialize_to_vec(T::key(&record))?) {
+                    continue;
+                }
+
+                let encoded = crate::serialize_to_vec(&record)?;
+
+                // a removal has to be kept to hide the older records
+                // of the key, if there are any
+                if full_history
+                    && T::removal(record)
+                        .map(|removal| crate::serialize_to_vec(&removal))
+                        .transpose()?
+                        .is_some_and(|removal| removal == encoded)
+                {
+                    continue;
+                }
+
+                transaction.write_all(&encoded)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl<T> Merge for T
+where
+    T: Collection,
+    T::Key: Serialize + 'static,
+    T::Serialized: Serialize,
+    T::Item: Serialize + 'static,
+{
+    fn merge(
+        &mut self,
+        pool: Pool<AEADReader>,
+        base: TransactionList,
+        ours: TransactionList,
+        theirs: TransactionList,
+        resolver: &mut dyn FnMut(&Conflict<'_>) -> Resolution,
+        transaction: &mut dyn Transaction,
+    ) -> anyhow::Result<bool> {
+        if theirs.is_empty() {
+            return Ok(false);
+        }
+
+        // snapshots can only be merged as a whole
+        if !T::Depth::INCREMENTAL {
+            let theirs = encoded_records::<T>(pool.clone(), theirs, |_| true)?;
+
+            if !ours.is_empty() {
+                let ours = encoded_records::<T>(pool.clone(), ours, |_| true)?;
+                if ours == theirs {
+                    return Ok(false);
+                }
+
+                let base = match base.is_empty() {
+                    true => None,
+                    false => Some(encoded_records::<T>(pool.clone(), base, |_| true)?),
+                };
+
+                let mut reader = pool.lease()?;
+                let mut load_all = |records: &[(Vec<u8>, Vec<u8>)]| {
+                    records
+                        .iter()
+                        .map(|(_, record)| {
+                            Ok(T::load(
+                                crate::deserialize_from_slice(record)?,
+                                &mut *reader,
+                            ))
+                        })
+                        .collect::<anyhow::Result<Vec<T::Item>>>()
+                };
+
+                let base_items = base.as_deref().map(&mut load_all).transpose()?;
+                let ours_items = load_all(&ours)?;
+                let theirs_items = load_all(&theirs)?;
+                if resolver(&Conflict::new(
+                    base_items.as_ref(),
+                    &ours_items,
+                    &theirs_items,
+                )) == Resolution::Ours
+                {
+                    return Ok(false);
+                }
+            }
+
+            for (_, record) in theirs {
+                transaction.write_all(&record)?;
+            }
+            return Ok(true);
+        }
+
+        // without unique keys, every record is an item that has been
+        // added, so theirs are all added, except the ones that were
+        // already there at the common ancestor
+        if !T::UNIQUE_KEYS {
+            let mut shared = HashMap::<Vec<u8>, usize>::new();
+            for (_, record) in encoded_records::<T>(pool.clone(), base, |_| true)? {
+                *shared.entry(record).or_default() += 1;
+            }
+
+            let mut changed = false;
+            for (_, record) in encoded_records::<T>(pool, theirs, |_| true)? {
+                match shared.get_mut(&record) {
+                    Some(count) if *count > 0 => *count -= 1,
+                    _ => {
+                        transaction.write_all(&record)?;
+                        changed = true;
+                    }
+                }
+            }
+
+            return Ok(changed);
+        }
+
+        let theirs = encoded_records::<T>(pool.clone(), theirs, |_| true)?;
+        let ours = latest_records(encoded_records::<T>(pool.clone(), ours, |_| true)?);
+
+        let mut reader = pool.lease()?;
+        let mut load = |record: &[u8]| -> anyhow::Result<T::Item> {
+            Ok(T::load(
+                crate::deserialize_from_slice(record)?,
+                &mut *reader,
+            ))
+        };
+
+        // the same key can be changed many times, but only the latest
+        // change on each side matters
+        let mut seen = HashSet::new();
+        let mut conflicts = vec![];
+        let mut keep_ours = HashSet::new();
+        for (key, record) in theirs.iter() {
+            if !seen.insert(key) {
+                continue;
+            }
+
+            let Some(ours) = ours.get(key) else {
+                continue;
+            };
+
+            if ours == record
+                || crate::serialize_to_vec(&load(ours)?)?
+                    == crate::serialize_to_vec(&load(record)?)?
+            {
+                // both sides made the same change
+                keep_ours.insert(key.clone());
+            } else {
+                conflicts.push((key, record));
+            }
+        }
+
+        if !conflicts.is_empty() {
+            let base = latest_records(encoded_records::<T>(pool, base, |key| {
+                conflicts.iter().any(|(k, _)| *k == key)
+            })?);
+
+            for (key, record) in conflicts {
+                let serialized: T::Serialized = crate::deserialize_from_slice(record)?;
+                let base_item = base.get(key).map(|b| load(b)).transpose()?;
+                let ours_item = load(&ours[key])?;
+                let theirs_item = load(record)?;
+
+                // only one side may have changed the value after all,
+                // e.g. when a previous merge has restored it
+                let encode = |item: &T::Item| crate::serialize_to_vec(item);
+                let base_encoded = base_item.as_ref().map(encode).transpose()?;
+                let resolution = if base_encoded == Some(encode(&theirs_item)?) {
+                    Resolution::Ours
+                } else if base_encoded == Some(encode(&ours_item)?) {
+                    Resolution::Theirs
+                } else {
+                    resolver(
+                        &Conflict::new(base_item.as_ref(), &ours_item, &theirs_item)
+                            .with_key(T::key(&serialized)),
+                    )
+                };
+
+                if resolution == Resolution::Ours {
+                    keep_ours.insert(key.clone());
+                }
+            }
+        }
+
+        let mut changed = false;
+        for (key, record) in theirs.iter() {
+            if !keep_ours.contains(key) {
+                transaction.write_all(record)?;
+                changed = true;
+            }
+        }
+
+        Ok(changed)
+    }
+}
+
+impl<T> Refresh for T
+where
+    T: Collection,
+{
+    fn refresh(
+        &mut self,
+        pool: Pool<AEADReader>,
+        transaction_list: TransactionList,
+    ) -> anyhow::Result<()> {
+        // incremental changes are replayed oldest first, so the
+        // newest record for a key wins, while a snapshot replaces
+        // everything that's been loaded
+        let transactions = if T::Depth::INCREMENTAL {
+            transaction_list.into_iter().rev().collect::<Vec<_>>()
+        } else {
+            transaction_list.into_iter().take(1).collect()
+        };
+
+        let mut reader = pool.lease()?;
+        let mut items = vec![];
+        for (_, _, stream) in transactions {
+            let mut records = DeserializeStream::new(stream.open_reader(pool.lease()?));
+
+            while let Some(record) = read_record::<T::Serialized>(&mut records)? {
+                items.push(T::load(record, &mut *reader));
+            }
+        }
+
+        if !T::Depth::INCREMENTAL && !items.is_empty() {
+            self.clear();
+        }
+
+        for item in items {
+            self.upsert(item);
+        }
+
+        Ok(())
+    }
+}
+
+impl<T> Revert for T
+where
+    T: Collection,
+    T::Key: Serialize,
+    T::Serialized: Serialize,
+{
+    fn revert(
+        &mut self,
+        pool: Pool<AEADReader>,
+        current: TransactionList,
+        target: TransactionList,
+        transaction: &mut dyn Transaction,
+    ) -> anyhow::Result<bool> {
+        let target = encoded_records::<T>(pool.clone(), target, |_| true)?;
+        let current = encoded_records::<T>(pool, current, |_| true)?;
+
+        // snapshots are restored as a whole
+        if !T::Depth::INCREMENTAL {
+            if target == current {
+                return Ok(false);
+            }
+
+            for (_, record) in target {
+                transaction.write_all(&record)?;
+            }
+            return Ok(true);
+        }
+
+        let mut changed = false;
+        let latest = latest_records(current.clone());
+
+        // restore the records that have changed since
+        let mut seen = HashSet::new();
+        for (key, record) in target.iter() {
+            if seen.insert(key) && latest.get(key) != Some(record) {
+                transaction.write_all(record)?;
+                changed = true;
+            }
+        }
+
+        // then remove the ones that have been added
+        for (key, record) in current.iter() {
+            if !seen.insert(key) {
+                continue;
+            }
+
+            let Some(removal) = T::removal(crate::deserialize_from_slice(record)?) else {
+                anyhow::bail!("records can't be removed from the field");
+            };
+
+            let removal = crate::serialize_to_vec(&removal)?;
+            if &removal != record {
+                transaction.write_all(&removal)?;
+                changed = true;
+            }
+        }
+
+        Ok(changed)
+    }
+}
+
+/// Read all records from the history of a field, as `(key, record)`
+/// pairs, both serialized.
+fn encoded_records<T>(
+    pool: Pool<AEADReader>,
+    transaction_list: TransactionList,
+    filter: impl Fn(&Vec<u8>) -> bool,
+) -> anyhow::Result<Vec<(Vec<u8>, Vec<u8>)>>
+where
+    T: Collection,
+    T::Key: Serialize,
+    T::Serialized: Serialize,
+{
+    let mut encoded = vec![];
+    for mut records in T::Depth::resolve(pool, transaction_list) {
+        while let Some(record) = read_record::<T::Serialized>(&mut records)? {
+            let key = crate::serialize_to_vec(T::key(&record))?;
+            if filter(&key) {
+                encoded.push((key, crate::serialize_to_vec(&record)?));
+            }
+        }
+    }
+
+    Ok(encoded)
+}
+
+/// Call `f` with every entry written into a journal, in order.
+fn replay_records<T: DeserializeOwned>(
+    mut records: &[u8],
+    mut f: impl FnMut(JournalEntry<T>),
+) -> anyhow::Result<()> {
+    while !records.is_empty() {
+        f(rmp_serde::decode::from_read(&mut records)?);
+    }
+
+    Ok(())
+}
+
+/// Keep only the most recent record for each key.
+fn latest_records(records: Vec<(Vec<u8>, Vec<u8>)>) -> HashMap<Vec<u8>, Vec<u8>> {
+    let mut latest = HashMap::new();
+    for (key, record) in records {
+        latest.entry(key).or_insert(record);
+    }
+    latest
+}
+
 impl<T: Collection> Collection for LocalField<T> {
     type Depth = T::Depth;
 
@@ -169,4 +636,18 @@ impl<T: Collection> Collection for LocalField<T> {
     fn insert(&mut self, record: Self::Item) {
         self.field.insert(record)
     }
+
+    fn upsert(&mut self, record: Self::Item) {
+        self.field.upsert(record)
+    }
+
+    fn removal(record: Self::Serialized) -> Option<Self::Serialized> {
+        T::removal(record)
+    }
+
+    fn clear(&mut self) {
+        self.field.clear()
+    }
+
+    const UNIQUE_KEYS: bool = T::UNIQUE_KEYS;
 }
@@ -14,6 +14,10 @@ use crate::index::TransactionList;
 use crate::object::{AEADReader, DeserializeStream, Pool};
 
 pub trait Depth: sealed::Sealed {
+    /// `true` if loading needs every transaction in the history, not
+    /// just the most recent one.
+    const INCREMENTAL: bool;
+
     fn resolve(
         index: Pool<AEADReader>,
         transactions: TransactionList,
@@ -48,6 +52,8 @@ fn full_history(
 }
 
 impl Depth for Incremental {
+    const INCREMENTAL: bool = true;
+
     #[inline(always)]
     fn resolve(
         index: Pool<AEADReader>,
@@ -58,6 +64,8 @@ impl Depth for Incremental {
 }
 
 impl Depth for Snapshot {
+    const INCREMENTAL: bool = false;
+
     #[inline(always)]
     fn resolve(
         index: Pool<AEADReader>,
@@ -1,9 +1,10 @@
 //! Intent to execute some operation on an [`Index`](crate::Index) field
 
-use super::{query::QueryAction, LocalField};
+use super::{query::QueryAction, Conflict, JournalLog, LocalField, Resolution};
 use crate::{
     index::{Transaction, TransactionList},
     object::{self, AEADReader, Pool},
+    ChunkPointer,
 };
 
 /// A wrapper to allow working with trait objects and `impl Trait`
@@ -51,6 +52,66 @@ impl<T: Load + 'static> From<Intent<Box<T>>> for Intent<Box<dyn Load>> {
     }
 }
 
+impl<T: Walk + 'static> From<Intent<Box<T>>> for Intent<Box<dyn Walk>> {
+    #[inline(always)]
+    fn from(a: Intent<Box<T>>) -> Self {
+        Intent {
+            name: a.name,
+            strategy: a.strategy,
+        }
+    }
+}
+
+impl<T: Squash + 'static> From<Intent<Box<T>>> for Intent<Box<dyn Squash>> {
+    #[inline(always)]
+    fn from(a: Intent<Box<T>>) -> Self {
+        Intent {
+            name: a.name,
+            strategy: a.strategy,
+        }
+    }
+}
+
+impl<T: Merge + 'static> From<Intent<Box<T>>> for Intent<Box<dyn Merge>> {
+    #[inline(always)]
+    fn from(a: Intent<Box<T>>) -> Self {
+        Intent {
+            name: a.name,
+            strategy: a.strategy,
+        }
+    }
+}
+
+impl<T: Refresh + 'static> From<Intent<Box<T>>> for Intent<Box<dyn Refresh>> {
+    #[inline(always)]
+    fn from(a: Intent<Box<T>>) -> Self {
+        Intent {
+            name: a.name,
+            strategy: a.strategy,
+        }
+    }
+}
+
+impl<T: Revert + 'static> From<Intent<Box<T>>> for Intent<Box<dyn Revert>> {
+    #[inline(always)]
+    fn from(a: Intent<Box<T>>) -> Self {
+        Intent {
+            name: a.name,
+            strategy: a.strategy,
+        }
+    }
+}
+
+impl<T: Journal + 'static> From<Intent<Box<T>>> for Intent<Box<dyn Journal>> {
+    #[inline(always)]
+    fn from(a: Intent<Box<T>>) -> Self {
+        Intent {
+            name: a.name,
+            strategy: a.strategy,
+        }
+    }
+}
+
 /// Store data into the index.
 ///
 /// This trait is usually implemented on a type that also implements
@@ -137,3 +198,204 @@ pub trait Query {
         predicate: impl Fn(&Self::Key) -> QueryAction,
     );
 }
+
+/// Find all references to the object pool in the index field.
+///
+/// This trait is usually implemented on a type that also implements
+/// [`Strategy`](super::strategy::Strategy), and _not_ on the field directly.
+///
+/// `Walk` has a blanket implementation for all types that implement
+/// [`Collection`](super::Collection), which will discover every
+/// [`ChunkPointer`] in both the serialized records and the loaded
+/// items.
+pub trait Walk {
+    /// Call `visitor` with every [`ChunkPointer`] that is referenced
+    /// by the records in `transaction_list`.
+    ///
+    /// Unlike [`Load`], this will visit every transaction in the
+    /// list, regardless of the field's [`Depth`](super::depth::Depth).
+    ///
+    /// If `values` is `true`, values that are stored outside of the
+    /// records, like the ones in a
+    /// [`SparseField`](super::SparseField), are read to find the
+    /// pointers in them, too.
+    ///
+    /// Returns an error if any of the records can't be decoded, or a
+    /// value can't be read.
+    fn walk(
+        &mut self,
+        pool: Pool<AEADReader>,
+        transaction_list: TransactionList,
+        values: bool,
+        visitor: &mut dyn FnMut(&ChunkPointer),
+    ) -> anyhow::Result<()>;
+}
+
+/// Collapse a range of transactions of an index field into one.
+///
+/// This trait is usually implemented on a type that also implements
+/// [`Strategy`](super::strategy::Strategy), and _not_ on the field directly.
+///
+/// `Squash` has a blanket implementation for all types that implement
+/// [`Collection`](super::Collection).
+pub trait Squash {
+    /// Write a single transaction into `transaction` that, when
+    /// loaded, is equivalent to loading all of `transaction_list`.
+    ///
+    /// If `full_history` is `true`, `transaction_list` starts at the
+    /// first commit of the tree, so there's nothing older the result
+    /// is loaded on top of.
+    ///
+    /// Fields with [`Snapshot`](super::depth::Snapshot) depth will
+    /// only keep the most recent transaction.
+    /// [`Incremental`](super::depth::Incremental) fields only keep
+    /// the newest record for each key if their
+    /// [`UNIQUE_KEYS`](super::Collection::UNIQUE_KEYS) is set, and
+    /// drop the removed keys if there's no older history. Otherwise
+    /// every record is retained, newest first.
+    fn squash(
+        &mut self,
+        pool: Pool<AEADReader>,
+        transaction_list: TransactionList,
+        full_history: bool,
+        transaction: &mut dyn Transaction,
+    ) -> anyhow::Result<()>;
+}
+
+/// Combine the changes made to an index field on two diverged
+/// histories.
+///
+/// This trait is usually implemented on a type that also implements
+/// [`Strategy`](super::strategy::Strategy), and _not_ on the field directly.
+///
+/// `Merge` has a blanket implementation for all types that implement
+/// [`Collection`](super::Collection).
+pub trait Merge {
+    /// Write the changes in `theirs` into `transaction`, so that
+    /// loading it on top of the history of `ours` results in the
+    /// merged state of the field.
+    ///
+    /// `ours` and `theirs` contain the transactions made on each
+    /// side since their common ancestor, while `base` is the full
+    /// history of the common ancestor. All lists are newest first.
+    ///
+    /// If both sides changed the same part of the field differently,
+    /// `resolver` decides which change to keep.
+    ///
+    /// Returns `true` if `transaction` needs to be recorded in the
+    /// merge commit.
+    fn merge(
+        &mut self,
+        pool: Pool<AEADReader>,
+        base: TransactionList,
+        ours: TransactionList,
+        theirs: TransactionList,
+        resolver: &mut dyn FnMut(&Conflict<'_>) -> Resolution,
+        transaction: &mut dyn Transaction,
+    ) -> anyhow::Result<bool>;
+}
+
+/// Apply new transactions to an index field that has already been
+/// loaded.
+///
+/// This trait is usually implemented on a type that also implements
+/// [`Strategy`](super::strategy::Strategy), and _not_ on the field directly.
+///
+/// `Refresh` has a blanket implementation for all types that
+/// implement [`Collection`](super::Collection).
+pub trait Refresh {
+    /// Bring the loaded field up to date with `transaction_list`,
+    /// which only contains transactions that are newer than the ones
+    /// already loaded, newest first.
+    ///
+    /// Returns an error if any of the records can't be decoded, in
+    /// which case the field is left unchanged.
+    fn refresh(
+        &mut self,
+        pool: Pool<AEADReader>,
+        transaction_list: TransactionList,
+    ) -> anyhow::Result<()>;
+}
+
+/// Restore an index field to the state it had at an earlier commit.
+///
+/// This trait is usually implemented on a type that also implements
+/// [`Strategy`](super::strategy::Strategy), and _not_ on the field directly.
+///
+/// `Revert` has a blanket implementation for all types that implement
+/// [`Collection`](super::Collection).
+pub trait Revert {
+    /// Write the changes into `transaction` that, when loaded on top
+    /// of `current`, result in the state of the field in `target`.
+    ///
+    /// Both lists contain the full history to load, newest first.
+    ///
+    /// Returns `true` if `transaction` needs to be recorded, or an
+    /// error if the field can't be restored.
+    fn revert(
+        &mut self,
+        pool: Pool<AEADReader>,
+        current: TransactionList,
+        target: TransactionList,
+        transaction: &mut dyn Transaction,
+    ) -> anyhow::Result<bool>;
+}
+
+/// Keep the changes made to an index field since the last commit in a
+/// local journal, so they can be restored after a restart.
+///
+/// This trait is usually implemented on a type that also implements
+/// [`Strategy`](super::strategy::Strategy), and _not_ on the field directly.
+///
+/// Unlike [`Store`], journaling doesn't commit the changes, and
+/// values are always written into the journal, regardless of the
+/// strategy.
+pub trait Journal {
+    /// Write the changes made since the last commit into
+    /// `transaction`.
+    ///
+    /// This is used to start a new journal, and the changes made
+    /// afterwards are written into the [attached](Self::attach) log.
+    fn journal(&mut self, transaction: &mut dyn Transaction) -> anyhow::Result<()>;
+
+    /// Write every change made to the field from now on into `log`,
+    /// or stop logging changes if it's `None`.
+    ///
+    /// The log is shared by the clones of the field, and changes
+    /// should be written as they are made.
+    fn attach(&mut self, log: Option<JournalLog>);
+
+    /// Returns `false` if the field can't track its changes, and
+    /// doesn't write them into the attached log.
+    ///
+    /// The whole [`journal`](Self::journal) of these fields is
+    /// written into the log whenever it's synced instead.
+    fn logs_changes(&self) -> bool {
+        true
+    }
+
+    /// Apply the `records` written by [`journal`](Self::journal), and
+    /// into the attached log, as changes that are not yet committed.
+    ///
+    /// `records` contains everything that's been logged for the
+    /// field, in the order it was written.
+    fn replay(&mut self, records: &[u8]) -> anyhow::Result<()>;
+}
+
+impl<T: Journal> Journal for LocalField<T> {
+    fn journal(&mut self, transaction: &mut dyn Transaction) -> anyhow::Result<()> {
+        self.field.journal(transaction)
+    }
+
+    fn attach(&mut self, log: Option<JournalLog>) {
+        self.field.attach(log)
+    }
+
+    fn logs_changes(&self) -> bool {
+        self.field.logs_changes()
+    }
+
+    fn replay(&mut self, records: &[u8]) -> anyhow::Result<()> {
+        self.field.replay(records)
+    }
+}
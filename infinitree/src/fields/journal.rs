@@ -0,0 +1,100 @@
+//! Log the changes of index fields as they are made
+use parking_lot::RwLock;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+/// Receives the changes made to a field that is
+/// [attached](super::Journal::attach) to a write-ahead log.
+///
+/// Calling `clone()` creates a reference to the same log.
+#[derive(Clone)]
+pub struct JournalLog(Arc<LogFn>);
+
+type LogFn = dyn Fn(anyhow::Result<&[u8]>) + Send + Sync;
+
+impl JournalLog {
+    /// Write the serialized records of the field using `log`.
+    ///
+    /// Records that can't be serialized are passed to `log` as
+    /// errors.
+    pub fn new(log: impl Fn(anyhow::Result<&[u8]>) + Send + Sync + 'static) -> Self {
+        JournalLog(Arc::new(log))
+    }
+
+    /// Serialize `record`, and add it to the log.
+    ///
+    /// The records are read back by [`Journal::replay`](super::Journal::replay)
+    /// in the same order.
+    pub fn write(&self, record: impl Serialize) {
+        match crate::serialize_to_vec(&record) {
+            Ok(records) => (self.0)(Ok(&records)),
+            Err(error) => (self.0)(Err(error.into())),
+        }
+    }
+}
+
+/// A record in the journal of one of the built-in fields.
+#[derive(Serialize, Deserialize)]
+pub(crate) enum JournalEntry<T> {
+    /// A change to the field.
+    Change(T),
+
+    /// The uncommitted changes logged before this have been thrown
+    /// away. Fields that are stored as a whole on every commit are
+    /// cleared.
+    Reset,
+}
+
+/// The log a field is attached to, shared by its clones.
+#[derive(Clone, Default)]
+pub(crate) struct JournalSlot(Arc<RwLock<Option<JournalLog>>>);
+
+impl JournalSlot {
+    pub(crate) fn attach(&self, log: Option<JournalLog>) {
+        *self.0.write() = log;
+    }
+
+    /// Add the record returned by `entry` to the log, if the field is
+    /// attached to one.
+    pub(crate) fn write<T: Serialize>(&self, entry: impl FnOnce() -> JournalEntry<T>) {
+        if let Some(log) = self.0.read().as_ref() {
+            log.write(entry());
+        }
+    }
+}
+
+/// The log a field is attached to, for fields that can only
+/// serialize their values once they're attached.
+pub(crate) struct TypedJournal<T>(Arc<RwLock<Option<TypedLog<T>>>>);
+
+type TypedLog<T> = Box<dyn Fn(JournalEntry<&T>) + Send + Sync>;
+
+impl<T> Clone for TypedJournal<T> {
+    fn clone(&self) -> Self {
+        TypedJournal(self.0.clone())
+    }
+}
+
+impl<T> Default for TypedJournal<T> {
+    fn default() -> Self {
+        TypedJournal(Arc::default())
+    }
+}
+
+impl<T> TypedJournal<T> {
+    pub(crate) fn attach(&self, log: Option<JournalLog>)
+    where
+        T: Serialize,
+    {
+        *self.0.write() = log.map(|log| -> TypedLog<T> {
+            Box::new(move |entry: JournalEntry<&T>| log.write(entry))
+        });
+    }
+
+    /// Add `entry` to the log, if the field is attached to one.
+    pub(crate) fn write(&self, entry: JournalEntry<&T>) {
+        if let Some(log) = self.0.read().as_ref() {
+            log(entry);
+        }
+    }
+}
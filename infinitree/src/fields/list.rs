@@ -1,5 +1,6 @@
 use super::{
-    depth::Snapshot, Collection, Intent, Load, LocalField, SparseField, Store, Strategy, Value,
+    depth::Snapshot, replay_records, Collection, Intent, Journal, JournalEntry, JournalLog, Load,
+    LocalField, Merge, Refresh, Revert, SparseField, Squash, Store, Strategy, Value, Walk,
 };
 use crate::{
     index::{FieldWriter, Transaction},
@@ -44,6 +45,44 @@ where
     fn insert(&mut self, record: Self::Item) {
         self.field.write().push(record);
     }
+
+    fn clear(&mut self) {
+        self.field.write().clear();
+    }
+}
+
+impl<T> Journal for LocalField<List<T>>
+where
+    T: Value,
+{
+    fn journal(&mut self, mut transaction: &mut dyn Transaction) -> anyhow::Result<()> {
+        // the whole list is stored on every commit
+        transaction.write_next(JournalEntry::Change(&*self.field.read()));
+        Ok(())
+    }
+
+    fn attach(&mut self, _log: Option<JournalLog>) {
+        // there's no change tracking, so the list is logged on sync
+    }
+
+    fn logs_changes(&self) -> bool {
+        false
+    }
+
+    fn replay(&mut self, records: &[u8]) -> anyhow::Result<()> {
+        let mut latest = None;
+        replay_records(records, |entry: JournalEntry<Vec<T>>| {
+            if let JournalEntry::Change(list) = entry {
+                latest = Some(list);
+            }
+        })?;
+
+        if let Some(list) = latest {
+            *self.field.write() = list;
+        }
+
+        Ok(())
+    }
 }
 
 impl<T> Store for SparseField<List<T>>
@@ -82,6 +121,13 @@ where
     }
 
     fn load(from: Self::Serialized, object: &mut dyn object::Reader) -> Self::Item {
+        Self::try_load(from, object).unwrap()
+    }
+
+    fn try_load(
+        from: Self::Serialized,
+        object: &mut dyn object::Reader,
+    ) -> object::Result<Self::Item> {
         object::serializer::read(
             object,
             |x| {
@@ -91,12 +137,36 @@ where
             },
             from,
         )
-        .unwrap()
     }
 
     fn insert(&mut self, record: Self::Item) {
         self.field.write().push(record);
     }
+
+    fn clear(&mut self) {
+        self.field.write().clear();
+    }
+}
+
+impl<T> Journal for SparseField<List<T>>
+where
+    T: Value,
+{
+    fn journal(&mut self, transaction: &mut dyn Transaction) -> anyhow::Result<()> {
+        LocalField::for_field(&self.field).journal(transaction)
+    }
+
+    fn attach(&mut self, log: Option<JournalLog>) {
+        LocalField::for_field(&self.field).attach(log)
+    }
+
+    fn logs_changes(&self) -> bool {
+        false
+    }
+
+    fn replay(&mut self, records: &[u8]) -> anyhow::Result<()> {
+        LocalField::for_field(&self.field).replay(records)
+    }
 }
 
 impl<T> crate::Index for List<T>
@@ -116,6 +186,47 @@ where
             Box::new(LocalField::for_field(self)),
         )])
     }
+
+    fn walk_all(&self) -> anyhow::Result<Vec<Intent<Box<dyn Walk>>>> {
+        Ok(vec![Intent::new(
+            "root",
+            Box::new(LocalField::for_field(self)),
+        )])
+    }
+
+    fn squash_all(&self) -> anyhow::Result<Vec<Intent<Box<dyn Squash>>>> {
+        Ok(vec![Intent::new(
+            "root",
+            Box::new(LocalField::for_field(self)),
+        )])
+    }
+
+    fn merge_all(&self) -> anyhow::Result<Vec<Intent<Box<dyn Merge>>>> {
+        Ok(vec![Intent::new(
+            "root",
+            Box::new(LocalField::for_field(self)),
+        )])
+    }
+
+    fn refresh_all(&self) -> anyhow::Result<Vec<Intent<Box<dyn Refresh>>>> {
+        Ok(vec![Intent::new(
+            "root",
+            Box::new(LocalField::for_field(self)),
+        )])
+    }
+    fn revert_all(&self) -> anyhow::Result<Vec<Intent<Box<dyn Revert>>>> {
+        Ok(vec![Intent::new(
+            "root",
+            Box::new(LocalField::for_field(self)),
+        )])
+    }
+
+    fn journal_all(&self) -> anyhow::Result<Vec<Intent<Box<dyn Journal>>>> {
+        Ok(vec![Intent::new(
+            "root",
+            Box::new(LocalField::for_field(self)),
+        )])
+    }
 }
 
 #[cfg(test)]
@@ -1,9 +1,12 @@
-use super::{Collection, Intent, Key, Load, LocalField, SparseField, Store, Strategy, Value};
+use super::{
+    replay_records, Collection, Intent, Journal, JournalEntry, JournalLog, JournalSlot, Key, Load,
+    LocalField, Merge, Refresh, Revert, SparseField, Squash, Store, Strategy, Value, Walk,
+};
 use crate::{
     index::{FieldWriter, Transaction},
     object::{self, serializer::SizedPointer, ObjectError},
 };
-use scc::HashMap;
+use scc::{hash_map::Entry, HashMap};
 use std::{borrow::Borrow, hash::Hash, sync::Arc};
 
 /// Multithreaded hash map that is always saved atomically
@@ -14,7 +17,7 @@ use std::{borrow::Borrow, hash::Hash, sync::Arc};
 /// For tracking incremental changes to your data structure, look at
 /// [`VersionedMap`](crate::fields::VersionedMap)
 #[derive(Clone, Default)]
-pub struct Map<K: 'static + Key, V: 'static + Value>(Arc<HashMap<K, Arc<V>>>);
+pub struct Map<K: 'static + Key, V: 'static + Value>(Arc<HashMap<K, Arc<V>>>, JournalSlot);
 
 impl<K, V> Map<K, V>
 where
@@ -28,7 +31,11 @@ where
             Some(existing) => existing,
             None => {
                 let new: Arc<_> = value.into();
-                let _ = self.0.insert(key, new.clone());
+                if let Entry::Vacant(entry) = self.0.entry(key) {
+                    let entry = entry.insert_entry(new.clone());
+                    self.1
+                        .write(|| JournalEntry::Change((entry.key(), Some(entry.get()))));
+                }
                 new
             }
         }
@@ -42,7 +49,11 @@ where
         K: Borrow<Q>,
         Q: Hash + Eq + ?Sized,
     {
-        self.0.update(key, fun)
+        self.0.update(key, |key, value| {
+            let result = fun(key, value);
+            self.1.write(|| JournalEntry::Change((key, Some(&*value))));
+            result
+        })
     }
 
     /// Call the given function to insert a value if it doesn't exist.
@@ -76,7 +87,11 @@ where
         K: Borrow<Q>,
         Q: Hash + Eq + ?Sized,
     {
-        self.0.remove(key);
+        if let Some(entry) = self.0.get(key) {
+            self.1
+                .write(|| JournalEntry::Change((entry.key(), None::<V>)));
+            let _ = entry.remove_entry();
+        }
     }
 
     /// Returns if there's an addition for the specified key.
@@ -149,7 +164,52 @@ where
     }
 
     fn insert(&mut self, record: Self::Item) {
-        self.field.insert(record.0, record.1);
+        let _ = self.field.0.insert(record.0, record.1.into());
+    }
+
+    fn clear(&mut self) {
+        self.field.0.clear();
+    }
+}
+
+impl<K, V> Journal for Map<K, V>
+where
+    K: Key,
+    V: Value,
+{
+    fn journal(&mut self, mut transaction: &mut dyn Transaction) -> anyhow::Result<()> {
+        // the whole map is stored on every commit
+        transaction.write_next(JournalEntry::<()>::Reset);
+        self.for_each(|k, v| {
+            transaction.write_next(JournalEntry::Change((k, Some(v))));
+        });
+
+        Ok(())
+    }
+
+    fn attach(&mut self, log: Option<JournalLog>) {
+        self.1.attach(log);
+    }
+
+    fn replay(&mut self, records: &[u8]) -> anyhow::Result<()> {
+        let mut entries = vec![];
+        replay_records(records, |entry: JournalEntry<(K, Option<V>)>| {
+            entries.push(entry)
+        })?;
+
+        for entry in entries {
+            match entry {
+                JournalEntry::Change((key, Some(value))) => {
+                    self.0.upsert(key, value.into());
+                }
+                JournalEntry::Change((key, None)) => {
+                    self.0.remove(&key);
+                }
+                JournalEntry::Reset => self.0.clear(),
+            }
+        }
+
+        Ok(())
     }
 }
 
@@ -190,6 +250,13 @@ where
     }
 
     fn load(from: Self::Serialized, object: &mut dyn object::Reader) -> Self::Item {
+        Self::try_load(from, object).unwrap()
+    }
+
+    fn try_load(
+        from: Self::Serialized,
+        object: &mut dyn object::Reader,
+    ) -> object::Result<Self::Item> {
         let (key, ptr) = from;
 
         let value = object::serializer::read(
@@ -200,14 +267,35 @@ where
                 })
             },
             ptr,
-        )
-        .unwrap();
+        )?;
 
-        (key, value)
+        Ok((key, value))
     }
 
     fn insert(&mut self, record: Self::Item) {
-        self.field.insert(record.0, record.1);
+        let _ = self.field.0.insert(record.0, record.1.into());
+    }
+
+    fn clear(&mut self) {
+        self.field.0.clear();
+    }
+}
+
+impl<K, V> Journal for SparseField<Map<K, V>>
+where
+    K: Key,
+    V: Value,
+{
+    fn journal(&mut self, transaction: &mut dyn Transaction) -> anyhow::Result<()> {
+        self.field.journal(transaction)
+    }
+
+    fn attach(&mut self, log: Option<JournalLog>) {
+        self.field.attach(log)
+    }
+
+    fn replay(&mut self, records: &[u8]) -> anyhow::Result<()> {
+        self.field.replay(records)
     }
 }
 
@@ -229,6 +317,47 @@ where
             Box::new(LocalField::for_field(self)),
         )])
     }
+
+    fn walk_all(&self) -> anyhow::Result<Vec<Intent<Box<dyn Walk>>>> {
+        Ok(vec![Intent::new(
+            "root",
+            Box::new(LocalField::for_field(self)),
+        )])
+    }
+
+    fn squash_all(&self) -> anyhow::Result<Vec<Intent<Box<dyn Squash>>>> {
+        Ok(vec![Intent::new(
+            "root",
+            Box::new(LocalField::for_field(self)),
+        )])
+    }
+
+    fn merge_all(&self) -> anyhow::Result<Vec<Intent<Box<dyn Merge>>>> {
+        Ok(vec![Intent::new(
+            "root",
+            Box::new(LocalField::for_field(self)),
+        )])
+    }
+
+    fn refresh_all(&self) -> anyhow::Result<Vec<Intent<Box<dyn Refresh>>>> {
+        Ok(vec![Intent::new(
+            "root",
+            Box::new(LocalField::for_field(self)),
+        )])
+    }
+    fn revert_all(&self) -> anyhow::Result<Vec<Intent<Box<dyn Revert>>>> {
+        Ok(vec![Intent::new(
+            "root",
+            Box::new(LocalField::for_field(self)),
+        )])
+    }
+
+    fn journal_all(&self) -> anyhow::Result<Vec<Intent<Box<dyn Journal>>>> {
+        Ok(vec![Intent::new(
+            "root",
+            Box::new(LocalField::for_field(self)),
+        )])
+    }
 }
 
 #[cfg(test)]
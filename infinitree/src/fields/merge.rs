@@ -0,0 +1,68 @@
+//! Types to resolve conflicts when merging diverged histories.
+use std::any::Any;
+
+/// A part of an index field that has been changed differently on both
+/// sides of a merge.
+///
+/// For incremental collections, such as
+/// [`VersionedMap`](super::VersionedMap), a conflict is about a single
+/// key, and each side holds the loaded item, e.g. `(K, Option<Arc<V>>)`
+/// for a `VersionedMap<K, V>`, where a `None` value marks a removal.
+///
+/// Snapshot fields conflict as a whole. For a
+/// [`Serialized<T>`](super::Serialized) field, each side holds a `T`,
+/// while other collections hold a `Vec` of their loaded items, e.g.
+/// `Vec<T>` for a [`List<T>`](super::List).
+///
+/// The values can be accessed by downcasting to the right type.
+pub struct Conflict<'a> {
+    key: Option<&'a dyn Any>,
+    base: Option<&'a dyn Any>,
+    ours: Option<&'a dyn Any>,
+    theirs: Option<&'a dyn Any>,
+}
+
+impl<'a> Conflict<'a> {
+    pub(crate) fn new<V: Any>(base: Option<&'a V>, ours: &'a V, theirs: &'a V) -> Self {
+        Self {
+            key: None,
+            base: base.map(|base| -> &dyn Any { base }),
+            ours: Some(ours),
+            theirs: Some(theirs),
+        }
+    }
+
+    pub(crate) fn with_key<K: Any>(mut self, key: &'a K) -> Self {
+        self.key = Some(key);
+        self
+    }
+
+    /// The key both sides changed, if the field has keys.
+    pub fn key<T: 'static>(&self) -> Option<&'a T> {
+        self.key?.downcast_ref()
+    }
+
+    /// The value at the common ancestor, if there was one.
+    pub fn base<T: 'static>(&self) -> Option<&'a T> {
+        self.base?.downcast_ref()
+    }
+
+    /// The value on the side that's being merged into.
+    pub fn ours<T: 'static>(&self) -> Option<&'a T> {
+        self.ours?.downcast_ref()
+    }
+
+    /// The value on the side that's being merged.
+    pub fn theirs<T: 'static>(&self) -> Option<&'a T> {
+        self.theirs?.downcast_ref()
+    }
+}
+
+/// Decides which side of a [`Conflict`] to keep.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Resolution {
+    /// Keep the change made on the side that's being merged into.
+    Ours,
+    /// Keep the change made on the side that's being merged.
+    Theirs,
+}
@@ -1,10 +1,13 @@
 use super::{
     depth::{Depth, Snapshot},
-    Load, LocalField, Store,
+    replay_records, Conflict, Journal, JournalEntry, JournalLog, Load, LocalField, Merge, Refresh,
+    Resolution, Revert, Squash, Store, Walk,
 };
 use crate::{
-    index::{FieldReader, FieldWriter, Transaction},
-    object::{self, AEADReader, Pool},
+    chunks::visit_pointers,
+    index::{read_record, FieldReader, FieldWriter, Transaction, TransactionList},
+    object::{self, AEADReader, DeserializeStream, Pool},
+    ChunkPointer,
 };
 use parking_lot::RwLock;
 use serde::{de::DeserializeOwned, Serialize};
@@ -60,13 +63,193 @@ impl<T> Load for LocalField<Serialized<T>>
 where
     T: DeserializeOwned,
 {
-    fn load(&mut self, pool: Pool<AEADReader>, transaction_list: crate::index::TransactionList) {
+    fn load(&mut self, pool: Pool<AEADReader>, transaction_list: TransactionList) {
         for mut transaction in Snapshot::resolve(pool, transaction_list) {
             *self.field.write() = transaction.read_next().unwrap();
         }
     }
 }
 
+impl<T> Walk for LocalField<Serialized<T>>
+where
+    T: Serialize + DeserializeOwned,
+{
+    fn walk(
+        &mut self,
+        pool: Pool<AEADReader>,
+        transaction_list: TransactionList,
+        _values: bool,
+        visitor: &mut dyn FnMut(&ChunkPointer),
+    ) -> anyhow::Result<()> {
+        for (_, _, stream) in transaction_list {
+            let mut records = DeserializeStream::new(stream.open_reader(pool.lease()?));
+
+            if let Some(value) = read_record::<T>(&mut records)? {
+                visit_pointers(&value, visitor)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl<T> Squash for LocalField<Serialized<T>>
+where
+    T: Serialize + DeserializeOwned + Send,
+{
+    fn squash(
+        &mut self,
+        pool: Pool<AEADReader>,
+        transaction_list: TransactionList,
+        _full_history: bool,
+        mut transaction: &mut dyn Transaction,
+    ) -> anyhow::Result<()> {
+        // only the latest version is ever loaded
+        if let Some((_, _, stream)) = transaction_list.into_iter().next() {
+            let mut records = DeserializeStream::new(stream.open_reader(pool.lease()?));
+
+            if let Some(value) = read_record::<T>(&mut records)? {
+                transaction.write_next(value);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl<T> Merge for LocalField<Serialized<T>>
+where
+    T: Serialize + DeserializeOwned + Send + 'static,
+{
+    fn merge(
+        &mut self,
+        pool: Pool<AEADReader>,
+        base: TransactionList,
+        ours: TransactionList,
+        theirs: TransactionList,
+        resolver: &mut dyn FnMut(&Conflict<'_>) -> Resolution,
+        mut transaction: &mut dyn Transaction,
+    ) -> anyhow::Result<bool> {
+        let latest = |transaction_list: TransactionList| -> anyhow::Result<Option<T>> {
+            match transaction_list.into_iter().next() {
+                Some((_, _, stream)) => read_record::<T>(&mut DeserializeStream::new(
+                    stream.open_reader(pool.lease()?),
+                )),
+                None => Ok(None),
+            }
+        };
+
+        let Some(theirs) = latest(theirs)? else {
+            return Ok(false);
+        };
+
+        if let Some(ours) = latest(ours)? {
+            if crate::serialize_to_vec(&ours)? == crate::serialize_to_vec(&theirs)? {
+                return Ok(false);
+            }
+
+            let base = latest(base)?;
+            if resolver(&Conflict::new(base.as_ref(), &ours, &theirs)) == Resolution::Ours {
+                return Ok(false);
+            }
+        }
+
+        transaction.write_next(theirs);
+        Ok(true)
+    }
+}
+
+impl<T> Refresh for LocalField<Serialized<T>>
+where
+    T: DeserializeOwned,
+{
+    fn refresh(
+        &mut self,
+        pool: Pool<AEADReader>,
+        transaction_list: TransactionList,
+    ) -> anyhow::Result<()> {
+        // the latest version replaces the loaded one
+        if let Some((_, _, stream)) = transaction_list.into_iter().next() {
+            let mut records = DeserializeStream::new(stream.open_reader(pool.lease()?));
+
+            if let Some(value) = read_record::<T>(&mut records)? {
+                *self.field.write() = value;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl<T> Revert for LocalField<Serialized<T>>
+where
+    T: Serialize + DeserializeOwned + Send,
+{
+    fn revert(
+        &mut self,
+        pool: Pool<AEADReader>,
+        current: TransactionList,
+        target: TransactionList,
+        mut transaction: &mut dyn Transaction,
+    ) -> anyhow::Result<bool> {
+        let latest = |transaction_list: TransactionList| -> anyhow::Result<Option<T>> {
+            match transaction_list.into_iter().next() {
+                Some((_, _, stream)) => read_record::<T>(&mut DeserializeStream::new(
+                    stream.open_reader(pool.lease()?),
+                )),
+                None => Ok(None),
+            }
+        };
+
+        let Some(target) = latest(target)? else {
+            anyhow::bail!("the field has no value to restore");
+        };
+
+        if let Some(current) = latest(current)? {
+            if crate::serialize_to_vec(&current)? == crate::serialize_to_vec(&target)? {
+                return Ok(false);
+            }
+        }
+
+        transaction.write_next(target);
+        Ok(true)
+    }
+}
+
+impl<T> Journal for LocalField<Serialized<T>>
+where
+    T: Serialize + DeserializeOwned + Sync,
+{
+    fn journal(&mut self, mut transaction: &mut dyn Transaction) -> anyhow::Result<()> {
+        // there's no change tracking, so the value is always stored
+        transaction.write_next(JournalEntry::Change(&*self.field.read()));
+        Ok(())
+    }
+
+    fn attach(&mut self, _log: Option<JournalLog>) {
+        // there's no change tracking, so the value is logged on sync
+    }
+
+    fn logs_changes(&self) -> bool {
+        false
+    }
+
+    fn replay(&mut self, records: &[u8]) -> anyhow::Result<()> {
+        let mut latest = None;
+        replay_records(records, |entry: JournalEntry<T>| {
+            if let JournalEntry::Change(value) = entry {
+                latest = Some(value);
+            }
+        })?;
+
+        if let Some(value) = latest {
+            *self.field.write() = value;
+        }
+
+        Ok(())
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::Serialized;
@@ -1,8 +1,9 @@
 //! A concurrent, incremental linked list implementation
 use crate::{
     fields::{
-        depth::Incremental, Collection, Intent, Load, LocalField, SparseField, Store, Strategy,
-        Value,
+        depth::Incremental, replay_records, Collection, Intent, Journal, JournalEntry, JournalLog,
+        Load, LocalField, Merge, Refresh, Revert, SparseField, Squash, Store, Strategy,
+        TypedJournal, Value, Walk,
     },
     index::{FieldWriter, Transaction},
     object::{self, serializer::SizedPointer, ObjectError},
@@ -95,12 +96,14 @@ impl<T: 'static> Default for LinkedListInner<T> {
 #[derive(Clone)]
 pub struct LinkedList<T: 'static> {
     inner: Shared<LinkedListInner<T>>,
+    journal: TypedJournal<T>,
 }
 
 impl<T: 'static> Default for LinkedList<T> {
     fn default() -> Self {
         Self {
             inner: Shared::new(LinkedListInner::default()),
+            journal: TypedJournal::default(),
         }
     }
 }
@@ -120,7 +123,14 @@ impl<T: 'static> LinkedList<T> {
     ///
     /// ```
     pub fn push(&self, value: impl Into<Arc<T>>) {
-        let node = Shared::new(Node(AtomicShared::default(), value.into()));
+        let value = value.into();
+        self.journal.write(JournalEntry::Change(&value));
+        self.append(value);
+    }
+
+    /// Add a new item to the list without logging it.
+    fn append(&self, value: Arc<T>) {
+        let node = Shared::new(Node(AtomicShared::default(), value));
         let barrier = Guard::new();
 
         let _ = self
@@ -302,6 +312,7 @@ impl<T: 'static> LinkedList<T> {
             .previous_commit_last
             .swap((None, Tag::None), Ordering::SeqCst);
         self.inner.last.swap((None, Tag::None), Ordering::SeqCst);
+        self.journal.write(JournalEntry::Reset);
     }
 
     /// Move the commit pointer to the last item in the list
@@ -337,6 +348,7 @@ impl<T: 'static> LinkedList<T> {
         self.inner
             .commit_start
             .swap((None, Tag::None), Ordering::SeqCst);
+        self.journal.write(JournalEntry::Reset);
     }
 
     pub fn iter(&self) -> impl Iterator<Item = Arc<T>> {
@@ -349,6 +361,19 @@ impl<T: 'static> LinkedList<T> {
                 .get_shared(),
         }
     }
+
+    /// Iterate over the items that have been added since the last
+    /// commit.
+    fn iter_uncommitted(&self) -> impl Iterator<Item = Arc<T>> {
+        let barrier = Guard::new();
+        NodeIter {
+            current: self
+                .inner
+                .commit_start
+                .load(Ordering::Acquire, &barrier)
+                .get_shared(),
+        }
+    }
 }
 
 impl<T> Store for LocalField<LinkedList<T>>
@@ -382,7 +407,38 @@ where
     }
 
     fn insert(&mut self, record: Self::Item) {
-        self.field.push(record);
+        self.field.append(record.into());
+    }
+}
+
+impl<T> Journal for LocalField<LinkedList<T>>
+where
+    T: Value,
+{
+    fn journal(&mut self, mut transaction: &mut dyn Transaction) -> anyhow::Result<()> {
+        for v in self.field.iter_uncommitted() {
+            transaction.write_next(JournalEntry::Change(v));
+        }
+
+        Ok(())
+    }
+
+    fn attach(&mut self, log: Option<JournalLog>) {
+        self.field.journal.attach(log);
+    }
+
+    fn replay(&mut self, records: &[u8]) -> anyhow::Result<()> {
+        let mut items = vec![];
+        replay_records(records, |entry: JournalEntry<T>| match entry {
+            JournalEntry::Change(v) => items.push(v),
+            JournalEntry::Reset => items.clear(),
+        })?;
+
+        for v in items {
+            self.field.append(v.into());
+        }
+
+        Ok(())
     }
 }
 
@@ -424,6 +480,13 @@ where
     }
 
     fn load(from: Self::Serialized, object: &mut dyn object::Reader) -> Self::Item {
+        Self::try_load(from, object).unwrap()
+    }
+
+    fn try_load(
+        from: Self::Serialized,
+        object: &mut dyn object::Reader,
+    ) -> object::Result<Self::Item> {
         object::serializer::read(
             object,
             |x| {
@@ -433,11 +496,27 @@ where
             },
             from,
         )
-        .unwrap()
     }
 
     fn insert(&mut self, record: Self::Item) {
-        self.field.push(record);
+        self.field.append(record.into());
+    }
+}
+
+impl<T> Journal for SparseField<LinkedList<T>>
+where
+    T: Value + Clone,
+{
+    fn journal(&mut self, transaction: &mut dyn Transaction) -> anyhow::Result<()> {
+        LocalField::for_field(&self.field).journal(transaction)
+    }
+
+    fn attach(&mut self, log: Option<JournalLog>) {
+        LocalField::for_field(&self.field).attach(log)
+    }
+
+    fn replay(&mut self, records: &[u8]) -> anyhow::Result<()> {
+        LocalField::for_field(&self.field).replay(records)
     }
 }
 
@@ -458,6 +537,47 @@ where
             Box::new(LocalField::for_field(self)),
         )])
     }
+
+    fn walk_all(&self) -> anyhow::Result<Vec<Intent<Box<dyn Walk>>>> {
+        Ok(vec![Intent::new(
+            "root",
+            Box::new(LocalField::for_field(self)),
+        )])
+    }
+
+    fn squash_all(&self) -> anyhow::Result<Vec<Intent<Box<dyn Squash>>>> {
+        Ok(vec![Intent::new(
+            "root",
+            Box::new(LocalField::for_field(self)),
+        )])
+    }
+
+    fn merge_all(&self) -> anyhow::Result<Vec<Intent<Box<dyn Merge>>>> {
+        Ok(vec![Intent::new(
+            "root",
+            Box::new(LocalField::for_field(self)),
+        )])
+    }
+
+    fn refresh_all(&self) -> anyhow::Result<Vec<Intent<Box<dyn Refresh>>>> {
+        Ok(vec![Intent::new(
+            "root",
+            Box::new(LocalField::for_field(self)),
+        )])
+    }
+    fn revert_all(&self) -> anyhow::Result<Vec<Intent<Box<dyn Revert>>>> {
+        Ok(vec![Intent::new(
+            "root",
+            Box::new(LocalField::for_field(self)),
+        )])
+    }
+
+    fn journal_all(&self) -> anyhow::Result<Vec<Intent<Box<dyn Journal>>>> {
+        Ok(vec![Intent::new(
+            "root",
+            Box::new(LocalField::for_field(self)),
+        )])
+    }
 }
 
 #[cfg(test)]
@@ -2,8 +2,9 @@
 use super::{store, Action, RawAction};
 use crate::{
     fields::{
-        depth::Incremental, Collection, Intent, Key, Load, LocalField, SparseField, Store,
-        Strategy, Value,
+        depth::Incremental, replay_records, Collection, Intent, Journal, JournalEntry, JournalLog,
+        JournalSlot, Key, Load, LocalField, Merge, Refresh, Revert, SparseField, Squash, Store,
+        Strategy, Value, Walk,
     },
     index::{FieldWriter, Transaction},
     object::{self, serializer::SizedPointer, ObjectError},
@@ -25,6 +26,7 @@ where
 {
     current: Arc<HashMap<K, Action<V>>>,
     base: Arc<HashMap<K, Action<V>>>,
+    journal: JournalSlot,
 }
 
 impl<K, V> Clone for VersionedMap<K, V>
@@ -36,6 +38,7 @@ where
         VersionedMap {
             base: self.base.clone(),
             current: self.current.clone(),
+            journal: self.journal.clone(),
         }
     }
 }
@@ -49,6 +52,7 @@ where
         VersionedMap {
             base: Arc::default(),
             current: Arc::default(),
+            journal: JournalSlot::default(),
         }
     }
 }
@@ -99,13 +103,13 @@ where
     pub fn insert_with<T: Into<Arc<V>>, F: FnOnce() -> T>(&self, key: K, new: F) -> Arc<V> {
         match self.get(&key) {
             Some(v) => v,
-            None => self
-                .current
-                .entry(key)
-                .or_default()
-                .get_mut()
-                .get_or_insert_with(|| new().into())
-                .clone(),
+            None => {
+                let mut entry = self.current.entry(key).or_default();
+                let value = entry.get_mut().get_or_insert_with(|| new().into()).clone();
+                self.journal
+                    .write(|| JournalEntry::Change((entry.key(), Some(&value))));
+                value
+            }
         }
     }
 
@@ -137,9 +141,11 @@ where
         match self.get(&key) {
             Some(existing) => {
                 let mut entry = self.current.entry(key).or_default();
-                let current = entry.get_mut();
-                *current = Some(update(existing.clone()).into());
-                current.clone()
+                let current = Some(update(existing.clone()).into());
+                *entry.get_mut() = current.clone();
+                self.journal
+                    .write(|| JournalEntry::Change((entry.key(), &current)));
+                current
             }
             None => None,
         }
@@ -189,7 +195,10 @@ where
     #[inline(always)]
     pub fn remove(&self, key: K) {
         if self.contains(&key) {
-            self.current.entry(key).or_default().insert(None);
+            let mut entry = self.current.entry(key).or_default();
+            entry.insert(None);
+            self.journal
+                .write(|| JournalEntry::Change((entry.key(), None::<V>)));
         }
     }
 
@@ -293,6 +302,8 @@ where
 
             if !retain {
                 *self.current.entry(key.clone()).or_default().get_mut() = None;
+                self.journal
+                    .write(|| JournalEntry::Change((key, None::<V>)));
             }
 
             current = entry.next();
@@ -311,6 +322,8 @@ where
                 Arc::as_ref(entry.get().as_ref().expect("checked above")),
             ) {
                 *entry.get_mut() = None;
+                self.journal
+                    .write(|| JournalEntry::Change((entry.key(), None::<V>)));
             }
 
             current = entry.next();
@@ -422,6 +435,7 @@ where
     pub fn clear(&self) {
         self.base.clear();
         self.current.clear();
+        self.journal.write(|| JournalEntry::<()>::Reset);
     }
 
     /// Roll back all modification since the last commit
@@ -467,6 +481,7 @@ where
     #[inline(always)]
     pub fn rollback(&self) {
         self.current.clear();
+        self.journal.write(|| JournalEntry::<()>::Reset);
     }
 
     /// True if the number of additions to the map is zero
@@ -508,6 +523,18 @@ where
         // 2. do not restore a removed key
         let _ = self.base.insert(record.0, record.1);
     }
+
+    #[inline(always)]
+    fn upsert(&mut self, record: Self::Item) {
+        self.base.upsert(record.0, record.1);
+    }
+
+    #[inline(always)]
+    fn removal(record: Self::Serialized) -> Option<Self::Serialized> {
+        Some((record.0, None))
+    }
+
+    const UNIQUE_KEYS: bool = true;
 }
 
 impl<K, V> Store for VersionedMap<K, V>
@@ -527,6 +554,40 @@ where
     }
 }
 
+impl<K, V> Journal for VersionedMap<K, V>
+where
+    K: Key + Clone,
+    V: Value,
+{
+    fn journal(&mut self, mut transaction: &mut dyn Transaction) -> anyhow::Result<()> {
+        let mut current = self.current.first_entry();
+        while let Some(e) = current {
+            transaction.write_next(JournalEntry::Change((e.key(), e.get())));
+            current = e.next();
+        }
+
+        Ok(())
+    }
+
+    fn attach(&mut self, log: Option<JournalLog>) {
+        self.journal.attach(log);
+    }
+
+    fn replay(&mut self, records: &[u8]) -> anyhow::Result<()> {
+        let mut changes = vec![];
+        replay_records(records, |entry: JournalEntry<(K, Action<V>)>| match entry {
+            JournalEntry::Change(change) => changes.push(change),
+            JournalEntry::Reset => changes.clear(),
+        })?;
+
+        for (key, value) in changes {
+            self.current.upsert(key, value);
+        }
+
+        Ok(())
+    }
+}
+
 impl<K, V> Collection for SparseField<VersionedMap<K, V>>
 where
     K: Key,
@@ -544,6 +605,13 @@ where
 
     #[inline(always)]
     fn load(from: Self::Serialized, object: &mut dyn object::Reader) -> Self::Item {
+        Self::try_load(from, object).unwrap()
+    }
+
+    fn try_load(
+        from: Self::Serialized,
+        object: &mut dyn object::Reader,
+    ) -> object::Result<Self::Item> {
         let value = match from.1 {
             Some(ptr) => {
                 let value: V = object::serializer::read(
@@ -554,15 +622,14 @@ where
                         })
                     },
                     ptr,
-                )
-                .unwrap();
+                )?;
 
                 store(value)
             }
             None => None,
         };
 
-        (from.0, value)
+        Ok((from.0, value))
     }
 
     #[inline(always)]
@@ -574,6 +641,18 @@ where
         // 2. do not restore a removed key
         let _ = self.field.base.insert(record.0, record.1);
     }
+
+    #[inline(always)]
+    fn upsert(&mut self, record: Self::Item) {
+        self.field.base.upsert(record.0, record.1);
+    }
+
+    #[inline(always)]
+    fn removal(record: Self::Serialized) -> Option<Self::Serialized> {
+        Some((record.0, None))
+    }
+
+    const UNIQUE_KEYS: bool = true;
 }
 
 impl<K, V> Store for SparseField<VersionedMap<K, V>>
@@ -608,6 +687,24 @@ where
     }
 }
 
+impl<K, V> Journal for SparseField<VersionedMap<K, V>>
+where
+    K: Key + Clone,
+    V: Value,
+{
+    fn journal(&mut self, transaction: &mut dyn Transaction) -> anyhow::Result<()> {
+        self.field.journal(transaction)
+    }
+
+    fn attach(&mut self, log: Option<JournalLog>) {
+        self.field.attach(log)
+    }
+
+    fn replay(&mut self, records: &[u8]) -> anyhow::Result<()> {
+        self.field.replay(records)
+    }
+}
+
 impl<K, V> crate::Index for VersionedMap<K, V>
 where
     K: Key + Clone,
@@ -626,6 +723,47 @@ where
             Box::new(LocalField::for_field(self)),
         )])
     }
+
+    fn walk_all(&self) -> anyhow::Result<Vec<Intent<Box<dyn Walk>>>> {
+        Ok(vec![Intent::new(
+            "root",
+            Box::new(LocalField::for_field(self)),
+        )])
+    }
+
+    fn squash_all(&self) -> anyhow::Result<Vec<Intent<Box<dyn Squash>>>> {
+        Ok(vec![Intent::new(
+            "root",
+            Box::new(LocalField::for_field(self)),
+        )])
+    }
+
+    fn merge_all(&self) -> anyhow::Result<Vec<Intent<Box<dyn Merge>>>> {
+        Ok(vec![Intent::new(
+            "root",
+            Box::new(LocalField::for_field(self)),
+        )])
+    }
+
+    fn refresh_all(&self) -> anyhow::Result<Vec<Intent<Box<dyn Refresh>>>> {
+        Ok(vec![Intent::new(
+            "root",
+            Box::new(LocalField::for_field(self)),
+        )])
+    }
+    fn revert_all(&self) -> anyhow::Result<Vec<Intent<Box<dyn Revert>>>> {
+        Ok(vec![Intent::new(
+            "root",
+            Box::new(LocalField::for_field(self)),
+        )])
+    }
+
+    fn journal_all(&self) -> anyhow::Result<Vec<Intent<Box<dyn Journal>>>> {
+        Ok(vec![Intent::new(
+            "root",
+            Box::new(LocalField::for_field(self)),
+        )])
+    }
 }
 
 #[cfg(test)]
@@ -56,10 +56,11 @@
 
 use crate::{
     fields::*,
-    object::{AEADReader, BufferedSink, Pool, Stream, Writer},
+    object::{AEADReader, BufferedSink, DeserializeStream, Pool, Stream, Writer},
     tree::CommitId,
 };
 use serde::{de::DeserializeOwned, Serialize};
+use std::io;
 
 pub(crate) type Field = String;
 pub(crate) type TransactionPointer = (CommitId, Field, Stream);
@@ -68,8 +69,8 @@ pub(crate) type TransactionPointer = (CommitId, Field, Stream);
 pub(crate) type TransactionList = Vec<TransactionPointer>;
 
 /// Marker trait for contiguous write operations.
-pub trait Transaction: Send + Sync + std::io::Write {}
-impl<T> Transaction for T where T: Send + Sync + std::io::Write {}
+pub trait Transaction: Send + Sync + io::Write {}
+impl<T> Transaction for T where T: Send + Sync + io::Write {}
 
 /// Any structure that is usable as an Index
 ///
@@ -77,6 +78,13 @@ impl<T> Transaction for T where T: Send + Sync + std::io::Write {}
 /// [`load_all`][Index::load_all] are automatically generated if the
 /// [`derive@crate::Index`] macro is used to derive this trait.
 ///
+/// The rest of the functions enable maintenance operations on the
+/// tree, such as garbage collection or merging. The derive macro
+/// only generates them when they are listed in the attribute of the
+/// struct, e.g. `#[infinitree(walk, merge)]`, as every field needs to
+/// support them. Operations that need a function the index doesn't
+/// implement return an error.
+///
 /// Generally an index will allow you to work with its fields
 /// independently and in-memory, and the functions of this trait will
 /// only help accessing backing storage. The [`Intent`] instances wrap
@@ -91,6 +99,51 @@ pub trait Index: Send + Sync {
     ///
     /// You should normally use the [`Index`](derive@crate::Index) derive macro to generate this.
     fn load_all(&self) -> anyhow::Result<Vec<Intent<Box<dyn Load>>>>;
+
+    /// Find the chunks referenced by each field, so that
+    /// [garbage collection](crate::Infinitree::gc) and
+    /// [verification](crate::Infinitree::verify) know which objects
+    /// are in use.
+    fn walk_all(&self) -> anyhow::Result<Vec<Intent<Box<dyn Walk>>>> {
+        unsupported("walk_all")
+    }
+
+    /// Collapse the history of each field when the commits of a tree
+    /// are [squashed](crate::Infinitree::squash).
+    fn squash_all(&self) -> anyhow::Result<Vec<Intent<Box<dyn Squash>>>> {
+        unsupported("squash_all")
+    }
+
+    /// Combine the changes of each field made on two diverged
+    /// histories when [merging](crate::Infinitree::merge).
+    fn merge_all(&self) -> anyhow::Result<Vec<Intent<Box<dyn Merge>>>> {
+        unsupported("merge_all")
+    }
+
+    /// Apply the commits of other writers to each loaded field on
+    /// [refresh](crate::Infinitree::refresh).
+    fn refresh_all(&self) -> anyhow::Result<Vec<Intent<Box<dyn Refresh>>>> {
+        unsupported("refresh_all")
+    }
+
+    /// Restore each field to an earlier commit on
+    /// [revert](crate::Infinitree::revert_to).
+    fn revert_all(&self) -> anyhow::Result<Vec<Intent<Box<dyn Revert>>>> {
+        unsupported("revert_all")
+    }
+
+    /// Record the changes of each field in the
+    /// [write-ahead log](crate::Infinitree::open_write_ahead_log).
+    fn journal_all(&self) -> anyhow::Result<Vec<Intent<Box<dyn Journal>>>> {
+        unsupported("journal_all")
+    }
+}
+
+fn unsupported<T>(operation: &str) -> anyhow::Result<T> {
+    Err(anyhow::anyhow!(
+        "the index doesn't implement `{operation}`, enable it with `#[infinitree({})]`",
+        operation.trim_end_matches("_all")
+    ))
 }
 
 /// Allows serializing individual records of an infinite collection.
@@ -101,7 +154,7 @@ pub trait FieldWriter: Send {
 
 impl<T> FieldWriter for T
 where
-    T: std::io::Write + Send,
+    T: io::Write + Send,
 {
     fn write_next(&mut self, obj: impl Serialize + Send) {
         crate::serialize_to_writer(self, &obj).unwrap();
@@ -123,6 +176,24 @@ where
     }
 }
 
+/// Read the next record from a stream, or `None` if the stream has ended.
+///
+/// Unlike [`FieldReader::read_next`], this will only signal the end of
+/// the stream on a clean EOF, and return an error for corrupt data.
+pub(crate) fn read_record<T: DeserializeOwned>(
+    stream: &mut DeserializeStream,
+) -> anyhow::Result<Option<T>> {
+    match T::deserialize(stream) {
+        Ok(record) => Ok(Some(record)),
+        Err(rmp_serde::decode::Error::InvalidMarkerRead(e))
+            if e.kind() == io::ErrorKind::UnexpectedEof =>
+        {
+            Ok(None)
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
 impl<T> IndexExt for T where T: Index {}
 
 /// This is just a convenience layer to handle direct operations on an index
@@ -3,6 +3,7 @@
 use crate::{
     backends::BackendError,
     compress::{CompressError, DecompressError},
+    crypto::CryptoError,
     BLOCK_SIZE,
 };
 
@@ -38,6 +39,11 @@ pub enum ObjectError {
         #[from]
         source: BackendError,
     },
+    #[error("Crypto error: {source}")]
+    Crypto {
+        #[from]
+        source: CryptoError,
+    },
     #[error("Compress failed")]
     Compress {
         #[from]
@@ -47,6 +47,11 @@ impl Stream {
         self.0.is_empty()
     }
 
+    /// The chunks that make up the stream, in order.
+    pub(crate) fn chunks(&self) -> &[ChunkPointer] {
+        &self.0
+    }
+
     /// List of objects that the Stream spans.
     ///
     /// Note these may not _exclusively_ contain this particular
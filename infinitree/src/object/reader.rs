@@ -75,7 +75,7 @@ impl AEADReader {
         pointer: &ChunkPointer,
     ) -> Result<&'target [u8]> {
         let cryptbuf: &mut [u8] = self.buffer.as_mut();
-        let buf = self.crypto.decrypt_chunk(cryptbuf, source, pointer);
+        let buf = self.crypto.decrypt_chunk(cryptbuf, source, pointer)?;
         let size = compress::decompress_into(buf, target)?;
 
         Ok(&target[..size])
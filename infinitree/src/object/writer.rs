@@ -98,13 +98,15 @@ impl AEADWriter {
         self.object.set_id(id);
         self.write_head(head);
         self.finalize()?;
-        self.backend.write_object(&self.object)?;
+
+        // the root is not retried when the writer is dropped
+        let written = self.backend.write_object(&self.object);
 
         self.rewrite.clear();
         reset_id(&mut self.object, &self.random);
         self.object.seek(SeekFrom::Start(self.mode.skip()))?;
 
-        Ok(())
+        Ok(written?)
     }
 
     fn finalize(&mut self) -> Result<()> {
@@ -5,13 +5,29 @@ use crate::{
     crypto::ICryptoOps,
     fields::{depth::Depth, Collection, Intent, KeyCachingIterator, Load, Query, QueryAction},
     index::{self, Index, IndexExt, TransactionList},
-    object::{AEADReader, AEADWriter, BlockBuffer, BufferedSink, Pool, PoolRef},
+    object::{AEADReader, AEADWriter, BlockBuffer, BufferedSink, Pool, PoolRef, Stream},
     Backend, Key,
 };
-use anyhow::{Context, Result};
-use parking_lot::RwLock;
+use anyhow::{bail, Context, Result};
+use parking_lot::{Mutex, RwLock};
 use serde::{de::DeserializeOwned, Serialize};
-use std::{collections::HashMap, ops::Deref, sync::Arc, time::SystemTime};
+use std::{
+    collections::{HashMap, HashSet},
+    ops::Deref,
+    sync::Arc,
+    time::SystemTime,
+};
+
+mod archive;
+pub use archive::*;
+
+#[cfg(feature = "async")]
+mod asynchronous;
+#[cfg(feature = "async")]
+pub use asynchronous::*;
+
+mod autocommit;
+pub use autocommit::*;
 
 mod commit;
 pub use commit::*;
@@ -20,6 +36,50 @@ mod root;
 pub(crate) use root::*;
 
 mod sealed_root;
+pub use sealed_root::{ConflictError, RecoveredRoot};
+
+mod diff;
+pub use diff::*;
+
+mod gc;
+pub use gc::*;
+
+mod hooks;
+pub use hooks::*;
+
+mod merge;
+pub use merge::*;
+
+mod prune;
+pub use prune::*;
+
+mod view;
+pub use view::*;
+
+mod refresh;
+
+mod refs;
+pub use refs::*;
+
+mod replicate;
+pub use replicate::*;
+
+mod retention;
+pub use retention::*;
+
+mod revert;
+
+mod signing;
+pub use signing::*;
+
+mod verify;
+pub use verify::*;
+
+mod wal;
+pub use wal::*;
+
+mod watch;
+pub use watch::*;
 
 /// Allows changing commit behaviour.
 pub enum CommitMode {
@@ -48,7 +108,27 @@ where
     backend: Arc<dyn Backend>,
 
     /// These are the generations we're currently working on.
-    commit_filter: RwLock<CommitFilter>,
+    commit_filter: RwLock<CommitFilter<CustomData>>,
+
+    /// New commits will be added to this branch.
+    branch: RwLock<Option<String>>,
+
+    /// Fields that have been loaded, and are kept up to date by
+    /// [`refresh`](Self::refresh).
+    loaded: RwLock<HashSet<index::Field>>,
+
+    /// Called before and after recording a commit.
+    hooks: RwLock<Hooks<CustomData>>,
+
+    /// Signing key for new commits, and the check for existing ones.
+    signatures: RwLock<Signatures>,
+
+    /// Local log of the changes that haven't been committed yet.
+    wal: Mutex<Option<Arc<WriteAheadLog>>>,
+
+    /// Changes that the index has stored for a commit that failed,
+    /// which are added to the next commit.
+    pending: Mutex<Vec<(index::Field, Stream)>>,
 
     /// Pool for object readers
     reader_pool: Pool<AEADReader>,
@@ -116,6 +196,12 @@ where
             backend: backend.clone(),
             index: I::default().into(),
             commit_filter: Default::default(),
+            branch: Default::default(),
+            loaded: Default::default(),
+            hooks: Default::default(),
+            signatures: Default::default(),
+            wal: Default::default(),
+            pending: Default::default(),
         })
     }
 }
@@ -129,6 +215,11 @@ where
     /// This persists currently in-memory data, and also records the
     /// commit with `message` to the log.
     ///
+    /// If another writer has committed to the tree since it was
+    /// opened, the commit fails with a [`ConflictError`] instead of
+    /// overwriting their changes. The changes of a failed commit are
+    /// added to the next one.
+    ///
     /// # Examples
     ///
     /// Any commit message works that implements [`ToString`].
@@ -155,7 +246,7 @@ where
         let metadata = CommitMetadata {
             time: SystemTime::now(),
             message: message.into().into(),
-            previous: self.root.commit_list.read().last().map(|c| c.id),
+            previous: self.head(),
             ..Default::default()
         };
 
@@ -185,6 +276,12 @@ where
             index: index.into(),
             root: RootIndex::uninitialized(key),
             commit_filter: Default::default(),
+            branch: Default::default(),
+            loaded: Default::default(),
+            hooks: Default::default(),
+            signatures: Default::default(),
+            wal: Default::default(),
+            pending: Default::default(),
             reader_pool: Pool::with_constructor(0, move || {
                 AEADReader::new(backend.clone(), chunk_key.clone())
             }),
@@ -196,7 +293,7 @@ where
     /// Will return an error if the operation is not supported by
     /// either the new or the old key.
     pub fn reseal(&self) -> Result<()> {
-        sealed_root::commit(&self.root, self.backend.clone())?;
+        self.commit_root()?;
         Ok(())
     }
 
@@ -205,10 +302,21 @@ where
         self.root.commit_list.read()
     }
 
+    /// If the root object of the tree was missing or damaged when it
+    /// was opened, returns the commit that has been recovered from
+    /// the newest backup of the root.
+    ///
+    /// Commits made after the recovered one are lost, but the data
+    /// they reference may still be in the backend. The next commit
+    /// replaces the damaged root object.
+    pub fn recovered_root(&self) -> Option<&RecoveredRoot> {
+        self.root.recovered.as_ref()
+    }
+
     /// Only run persistence query operations
     /// ([`query`][Infinitree::query], [`load`][Infinitree::load],
     /// [`iter`][Infinitree::iter]) on the selected generations.
-    pub fn filter_commits(&self, version: CommitFilter) {
+    pub fn filter_commits(&self, version: CommitFilter<CustomData>) {
         *self.commit_filter.write() = version;
     }
 
@@ -224,8 +332,9 @@ where
         let metadata = CommitMetadata {
             time: SystemTime::now(),
             message: message.into().into(),
-            previous: self.root.commit_list.read().last().map(|c| c.id),
+            previous: self.head(),
             custom_data,
+            merged: vec![],
         };
 
         self.commit_with_metadata(metadata, mode)
@@ -239,45 +348,164 @@ where
         metadata: CommitMetadata<CustomData>,
         mode: CommitMode,
     ) -> Result<Option<Arc<Commit<CustomData>>>> {
+        let metadata = metadata.rounded()?;
         let mut object = self.chunk_writer()?;
         let mut sink = BufferedSink::new(self.chunk_writer()?);
 
-        let (id, changeset) = self.index.write().commit(
+        // keep the log from being reopened until the commit is done
+        let wal = self.wal.lock();
+
+        let index = self.index.write();
+        let (mut id, mut changeset) = index.commit(
             &mut sink,
             &mut object,
             crate::serialize_to_vec(&metadata)?,
             self.root.key.chunk_key()?,
         )?;
 
+        // the index won't store the changes of a failed commit again,
+        // so they have to be carried over, newest first
+        let pending = std::mem::take(&mut *self.pending.lock());
+        if !pending.is_empty() {
+            changeset.retain(|(_, stream)| !stream.is_empty());
+            changeset.extend(pending);
+            id = self.commit_id(&metadata, &changeset)?;
+        }
+
         if let CommitMode::OnlyOnChange = mode {
             if changeset.iter().all(|(_, stream)| stream.is_empty()) {
                 return Ok(self.last_commit());
             }
         }
 
+        // changes made from now on are logged after the mark
+        if let Err(error) = self.wal_committing(wal.as_deref(), id) {
+            *self.pending.lock() = changeset;
+            return Err(error);
+        }
+        drop(index);
+
+        // the changes of a vetoed commit are still in the index, so
+        // they're kept for the next commit, and in the log
+        if let Err(error) = self.hooks.read().pre_commit(&metadata, &changeset) {
+            *self.pending.lock() = changeset;
+            return Err(error);
+        }
+
+        if let Err(error) = self.record_commit(id, metadata, changeset.clone()) {
+            *self.pending.lock() = changeset;
+            return Err(error);
+        }
+        self.wal_restart(wal.as_deref(), &self.index.write());
+
+        Ok(self.last_commit())
+    }
+
+    /// Add a new commit to the history, and persist the root index.
+    ///
+    /// The pre-commit hooks need to be run before this. If the root
+    /// index can't be persisted, the commit is removed from the
+    /// history again.
+    fn record_commit(
+        &self,
+        id: CommitId,
+        metadata: CommitMetadata<CustomData>,
+        changeset: Vec<(index::Field, Stream)>,
+    ) -> Result<()> {
+        let signature = self.sign(&id, &metadata)?;
+        let commit = Arc::new(Commit {
+            id,
+            metadata,
+            signature,
+        });
+
         // scope for rewriting history. this is critical, the log is locked.
         {
             let mut tr_log = self.root.transaction_log.write();
             let size = tr_log.len() + changeset.len();
             let history = std::mem::replace(&mut *tr_log, Vec::with_capacity(size));
 
-            tr_log.extend(changeset.into_iter().map(|(field, oid)| (id, field, oid)));
+            tr_log.extend(
+                changeset
+                    .iter()
+                    .cloned()
+                    .map(|(field, oid)| (id, field, oid)),
+            );
             tr_log.extend(history);
 
             // record the commit
+            self.root.commit_list.write().push(commit.clone());
+        }
+
+        let branch = self.branch.read().clone();
+        let previous_refs = {
+            let mut refs = self.root.refs.write();
+            let previous = refs.clone();
+            match &branch {
+                Some(branch) => {
+                    refs.branches.insert(branch.clone(), id);
+                }
+                None => refs.main = Some(id),
+            }
+            previous
+        };
+
+        if let Err(error) = self.commit_root() {
             self.root
-                .commit_list
+                .transaction_log
                 .write()
-                .push(Commit { id, metadata }.into());
+                .retain(|(commit, _, _)| commit != &id);
+            self.root.commit_list.write().retain(|c| c.id != id);
+            *self.root.refs.write() = previous_refs;
+
+            return Err(error);
         }
 
-        sealed_root::commit(&self.root, self.backend.clone())?;
+        self.hooks.read().post_commit(&commit, &changeset);
 
-        Ok(self.last_commit())
+        Ok(())
+    }
+
+    /// Persist the root index.
+    ///
+    /// Returns a [`ConflictError`] if another writer has changed the
+    /// tree since it was opened.
+    fn commit_root(&self) -> Result<()> {
+        self.ensure_writable()?;
+        match sealed_root::commit(&self.root, self.backend.clone()) {
+            Err(sealed_root::Error::Conflict { source }) => Err(source.into()),
+            result => Ok(result?),
+        }
+    }
+
+    /// Calculate the id of a commit the same way
+    /// [`commit_with_metadata`](Self::commit_with_metadata) does.
+    fn commit_id(
+        &self,
+        metadata: &CommitMetadata<CustomData>,
+        changeset: &[(index::Field, Stream)],
+    ) -> Result<CommitId> {
+        let mut hashed_data = crate::serialize_to_vec(metadata)?;
+        hashed_data.extend(crate::serialize_to_vec(changeset)?);
+
+        Ok(CommitId::from_bytes(
+            self.root.key.chunk_key()?.hash(&hashed_data),
+        ))
+    }
+
+    /// Fail early if the tree was opened with a
+    /// [`WriteProtected`](crate::crypto::WriteProtected) key.
+    fn ensure_writable(&self) -> Result<()> {
+        if self.root.key.write_protected() {
+            bail!("the tree was opened with a write-protected key");
+        }
+
+        Ok(())
     }
 
     /// Return a handle for an internal object writer.
     fn chunk_writer(&self) -> Result<AEADWriter> {
+        self.ensure_writable()?;
         Ok(AEADWriter::new(
             self.backend.clone(),
             self.root.key.chunk_key()?,
@@ -298,6 +526,7 @@ where
     /// anything written using an ObjectWriter **must** be less than
     /// about 4MB.
     pub fn storage_writer(&self) -> Result<AEADWriter> {
+        self.ensure_writable()?;
         Ok(AEADWriter::for_storage(
             self.backend.clone(),
             self.root.key.storage_key()?,
@@ -336,9 +565,13 @@ where
 
     /// Load into memory all fields for the selected version ranges
     pub fn load_all(&self) -> Result<()> {
-        self.index
+        let mut index = self.index.write();
+        index.load_all_from(&self.filter_generations(), &self.reader_pool)?;
+
+        self.loaded
             .write()
-            .load_all_from(&self.filter_generations(), &self.reader_pool)
+            .extend(index.load_all()?.into_iter().map(|field| field.name));
+        Ok(())
     }
 
     /// Load the field for the selected generation set
@@ -349,6 +582,7 @@ where
         field
             .strategy
             .load(self.reader_pool.clone(), commits_for_field);
+        self.loaded.write().insert(field.name);
 
         Ok(())
     }
@@ -414,23 +648,63 @@ where
             .map(|c| (c.id, c))
             .collect::<HashMap<_, _>>();
 
+        self.resolve_commit_filter(&self.commit_filter.read(), &commits)
+    }
+
+    fn resolve_commit_filter(
+        &self,
+        filter: &CommitFilter<CustomData>,
+        commits: &HashMap<CommitId, Arc<Commit<CustomData>>>,
+    ) -> Option<Vec<CommitId>> {
         let mut list = vec![];
-        let mut next = match &*self.commit_filter.read() {
+        let mut next = match filter {
             // If we're just looking for a single commit, job's done
-            CommitFilter::Single(id) => return Some(vec![commits.get(id)?.id]),
+            CommitFilter::Single(id) => {
+                let commit = commits.get(id)?;
+                if !self.accepts(commit) {
+                    return Some(vec![]);
+                }
+                return Some(vec![commit.id]);
+            }
+
+            CommitFilter::Matching(inner, predicate) => {
+                let mut list = self.resolve_commit_filter(inner, commits)?;
+                list.retain(|id| predicate(&commits[id]));
+                return Some(list);
+            }
+            CommitFilter::Excluding(inner, excluded) => {
+                let mut list = self.resolve_commit_filter(inner, commits)?;
+                list.retain(|id| !excluded.contains(id));
+                return Some(list);
+            }
 
-            CommitFilter::All => clist.last(),
+            CommitFilter::All | CommitFilter::AsOf(_) | CommitFilter::Between(_, _) => {
+                commits.get(&self.head()?)
+            }
             CommitFilter::UpTo(id) => commits.get(id),
+            CommitFilter::Branch(name) => commits.get(self.root.refs.read().branches.get(name)?),
+            CommitFilter::Tag(name) => commits.get(self.root.refs.read().tags.get(name)?),
             CommitFilter::Range(_start, end) => commits.get(end),
         };
 
-        let filter = self.commit_filter.read();
         while let Some(current) = next {
-            list.push(current.id);
+            // the clock of other writers may be off, so don't assume
+            // that the time is monotonic along the chain
+            let selected = match filter {
+                CommitFilter::AsOf(time) => current.metadata.time <= *time,
+                CommitFilter::Between(start, end) => {
+                    *start <= current.metadata.time && current.metadata.time <= *end
+                }
+                _ => true,
+            };
 
-            next = match &*filter {
+            // commits that fail the signature check are never loaded
+            if selected && self.accepts(current) {
+                list.push(current.id);
+            }
+
+            next = match filter {
                 CommitFilter::Range(start, _end) if &current.id == start => return Some(list),
-                CommitFilter::Single(_) => unreachable!(),
 
                 _ => current
                     .metadata
@@ -494,13 +768,13 @@ where
 
 #[cfg(test)]
 mod tests {
-    use super::Infinitree;
+    use super::{ConflictError, Infinitree};
     use crate::{
         backends::test::InMemoryBackend,
         crypto::UsernamePassword,
         fields::{QueryAction, VersionedMap},
     };
-    use std::sync::Arc;
+    use std::{sync::Arc, time::SystemTime};
 
     fn key() -> UsernamePassword {
         UsernamePassword::with_credentials("username".to_string(), "password".to_string()).unwrap()
@@ -615,4 +889,112 @@ mod tests {
             assert_eq!(tree.index().get("a"), Some("2".to_string().into()));
         }
     }
+
+    #[test]
+    fn commit_filter_resolution_time() {
+        let backend = test_tree_with_multiple_commits();
+
+        {
+            let tree = Infinitree::<VersionedMap<String, String>>::open(backend, key()).unwrap();
+
+            let first = tree.commit_list().first().unwrap().metadata.time;
+            tree.filter_commits(super::CommitFilter::AsOf(first));
+            tree.load_all().unwrap();
+            assert_eq!(tree.index().get("a"), Some("1".to_string().into()));
+
+            let last = tree.commit_list().last().unwrap().metadata.time;
+            tree.filter_commits(super::CommitFilter::Between(last, SystemTime::now()));
+            assert_eq!(tree.filter_generations().len(), 1);
+        }
+    }
+
+    #[test]
+    fn commit_filter_resolution_predicate() {
+        let backend = test_tree_with_multiple_commits();
+
+        {
+            let tree = Infinitree::<VersionedMap<String, String>>::open(backend, key()).unwrap();
+
+            tree.filter_commits(super::CommitFilter::Matching(
+                Box::new(super::CommitFilter::All),
+                Arc::new(|commit| commit.metadata.previous.is_none()),
+            ));
+            tree.load_all().unwrap();
+            assert_eq!(tree.index().get("a"), Some("1".to_string().into()));
+
+            let last = tree.commit_list().last().unwrap().id;
+            tree.filter_commits(super::CommitFilter::Excluding(
+                Box::new(super::CommitFilter::All),
+                [last].into(),
+            ));
+            assert!(tree
+                .filter_generations()
+                .iter()
+                .all(|(id, _, _)| id != &last));
+        }
+    }
+
+    #[test]
+    fn concurrent_writers_conflict() {
+        let backend = test_tree_with_multiple_commits();
+
+        let first =
+            Infinitree::<VersionedMap<String, String>>::open(backend.clone(), key()).unwrap();
+        let second =
+            Infinitree::<VersionedMap<String, String>>::open(backend.clone(), key()).unwrap();
+
+        first.index().insert("b".to_string(), "1".to_string());
+        first.commit(None).unwrap().unwrap();
+
+        second.index().insert("c".to_string(), "1".to_string());
+        let err = second.commit(None).unwrap_err();
+        assert!(err.downcast_ref::<ConflictError>().is_some());
+
+        // a new tree can't overwrite an existing one either
+        let empty =
+            Infinitree::<VersionedMap<String, String>>::empty(backend.clone(), key()).unwrap();
+        empty.index().insert("d".to_string(), "1".to_string());
+        assert!(empty.commit(None).is_err());
+
+        // the first writer can carry on, and the others can retry
+        // after reloading
+        first.index().insert("e".to_string(), "1".to_string());
+        first.commit(None).unwrap().unwrap();
+
+        let second = Infinitree::<VersionedMap<String, String>>::open(backend, key()).unwrap();
+        assert_eq!(second.commit_list().len(), 4);
+        second.index().insert("c".to_string(), "1".to_string());
+        second.commit(None).unwrap().unwrap();
+    }
+
+    #[test]
+    fn commit_after_conflict() {
+        let backend = test_tree_with_multiple_commits();
+
+        let first =
+            Infinitree::<VersionedMap<String, String>>::open(backend.clone(), key()).unwrap();
+        let second =
+            Infinitree::<VersionedMap<String, String>>::open(backend.clone(), key()).unwrap();
+
+        first.index().insert("b".to_string(), "1".to_string());
+        let theirs = first.commit(None).unwrap().unwrap();
+
+        second.index().insert("c".to_string(), "1".to_string());
+        let known = second.commit_list().len();
+        let err = second.commit(None).unwrap_err();
+        assert!(err.downcast_ref::<ConflictError>().is_some());
+        assert_eq!(second.commit_list().len(), known);
+
+        // the failed commit is not in the way of catching up, and its
+        // changes go into the next one
+        assert_eq!(second.refresh().unwrap().len(), 1);
+        let ours = second.commit(None).unwrap().unwrap();
+        assert_eq!(ours.metadata.previous, Some(theirs.id));
+
+        let tree = Infinitree::<VersionedMap<String, String>>::open(backend, key()).unwrap();
+        assert_eq!(tree.commit_list().len(), known + 2);
+        tree.load_all().unwrap();
+        assert_eq!(tree.index().get("b"), Some("1".to_string().into()));
+        assert_eq!(tree.index().get("c"), Some("1".to_string().into()));
+    }
 }
@@ -0,0 +1,390 @@
+use super::{replicate::exists, sealed_root, CommitFilter, CommitId, Infinitree};
+use crate::{
+    backends::Backend,
+    crypto::Key,
+    index::{Index, TransactionList},
+    object::{BlockBuffer, WriteObject},
+    Digest, ObjectId, BLOCK_SIZE,
+};
+use anyhow::{bail, Context, Result};
+use serde::{
+    de::{DeserializeOwned, IgnoredAny},
+    Deserialize, Deserializer, Serialize,
+};
+use std::{
+    collections::{HashMap, HashSet},
+    io::{Read, Write},
+    sync::Arc,
+};
+
+const MAGIC: &[u8; 20] = b"infinitree-archive\0\x01";
+
+/// The objects contained in an archive.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct ArchiveContents {
+    /// The root object of the tree.
+    pub root: ObjectId,
+
+    /// All objects in the archive, including the root object.
+    pub objects: Vec<ObjectId>,
+
+    /// Objects that are not in the archive, but need to be present in
+    /// the backend it's imported into.
+    ///
+    /// This is empty, unless some commits have been left out of the
+    /// archive.
+    pub required: Vec<ObjectId>,
+
+    /// The history of the archived tree, in the order the commits
+    /// were added.
+    pub commits: Vec<CommitId>,
+}
+
+/// Precedes the contents of every object in the archive.
+#[derive(Serialize, Deserialize)]
+struct Entry {
+    id: ObjectId,
+    len: usize,
+    hash: Digest,
+}
+
+impl<I: Index, CustomData> Infinitree<I, CustomData>
+where
+    CustomData: Serialize + DeserializeOwned + Send + Sync,
+{
+    /// Pack the objects of the tree into a single archive, which can
+    /// be unpacked into any backend using [`import`].
+    ///
+    /// The archive contains the root object, the objects of the index,
+    /// and the storage objects referenced by the index, exactly as
+    /// they are stored in the backend, so the contents stay encrypted.
+    ///
+    /// Only the objects of the commits selected by `commits` are
+    /// included, along with the current root. Use
+    /// [`CommitFilter::All`] to export the whole history, or e.g.
+    /// [`CommitFilter::Range`] to ship the commits since the last
+    /// export. Importing an archive that leaves out some commits,
+    /// including ones on other branches, needs a backend that already
+    /// has their objects, e.g. by importing an earlier archive first.
+    ///
+    /// Only committed changes are exported.
+    pub fn export(
+        &self,
+        commits: CommitFilter<CustomData>,
+        mut writer: impl Write,
+    ) -> Result<ArchiveContents> {
+        let root = self.root.key.root_object_id()?;
+        let mut objects = self.live_objects()?;
+
+        let (selected, history) = {
+            let clist = self.commit_list();
+            let graph = clist
+                .iter()
+                .cloned()
+                .map(|c| (c.id, c))
+                .collect::<HashMap<_, _>>();
+            let selected = self
+                .resolve_commit_filter(&commits, &graph)
+                .context("the commit filter doesn't match any commit")?
+                .into_iter()
+                .collect::<HashSet<_>>();
+
+            (selected, clist.iter().map(|c| c.id).collect::<Vec<_>>())
+        };
+
+        let excluded = self
+            .root
+            .transaction_log
+            .read()
+            .iter()
+            .filter(|(id, _, _)| !selected.contains(id))
+            .cloned()
+            .collect::<TransactionList>();
+        let mut required = self.referenced_objects(&excluded)?;
+
+        // the root, its backups, and the objects of the root index
+        // are rewritten on every commit
+        let backups = sealed_root::backup_root_ids(&self.root.key)?;
+        required.remove(&root);
+        for id in self.root.objects.read().iter().chain(backups.iter()) {
+            required.remove(id);
+        }
+        objects.retain(|id| !required.contains(id));
+        objects.remove(&root);
+
+        let backups = backups
+            .into_iter()
+            .filter(|id| objects.remove(id))
+            .collect::<Vec<_>>();
+
+        let mut contents = ArchiveContents {
+            root,
+            objects: objects.into_iter().collect(),
+            required: required.into_iter().collect(),
+            commits: history,
+        };
+        contents
+            .objects
+            .sort_unstable_by(|a, b| a.as_ref().cmp(b.as_ref()));
+        contents
+            .required
+            .sort_unstable_by(|a, b| a.as_ref().cmp(b.as_ref()));
+
+        // the backups can be opened just like the root, so they go
+        // last, after everything they refer to
+        contents.objects.extend(backups);
+
+        // the root goes last, so a partial import can be detected
+        contents.objects.push(root);
+
+        writer.write_all(MAGIC)?;
+        crate::serialize_to_writer(&mut writer, &contents)?;
+
+        for id in contents.objects.iter() {
+            let object = match id == &root {
+                true => self.backend.read_fresh(id)?,
+                false => self.backend.read_object(id)?,
+            };
+            let data = object.as_inner();
+
+            let entry = Entry {
+                id: *id,
+                len: data.len(),
+                hash: *blake3::hash(data).as_bytes(),
+            };
+            crate::serialize_to_writer(&mut writer, &entry)?;
+            writer.write_all(data)?;
+        }
+
+        writer.flush()?;
+        Ok(contents)
+    }
+}
+
+/// Unpack an archive created by [`Infinitree::export`] into
+/// `backend`.
+///
+/// Before anything is written, all objects
+/// [`required`](ArchiveContents::required) by the archive need to be
+/// present in `backend`. The contents of every object are checked
+/// against the hash recorded in the archive, and the root object is
+/// only written once all other objects have been imported.
+///
+/// The objects are copied without decrypting them, but `key` is
+/// needed to read the tree that's already in `backend`, if any. The
+/// archive is only imported if its history extends the history of
+/// that tree, so an older or diverged archive doesn't overwrite newer
+/// commits.
+pub fn import(
+    backend: Arc<dyn Backend>,
+    key: impl Into<Key>,
+    mut reader: impl Read,
+) -> Result<ArchiveContents> {
+    let mut magic = [0; MAGIC.len()];
+    reader.read_exact(&mut magic)?;
+    if &magic != MAGIC {
+        bail!("not an archive, or unsupported archive version");
+    }
+
+    let contents: ArchiveContents = rmp_serde::decode::from_read(&mut reader)?;
+    if contents.objects.last() != Some(&contents.root) {
+        bail!("invalid archive: the root object is missing");
+    }
+
+    check_history(backend.clone(), key.into(), &contents.commits)?;
+
+    for id in contents.required.iter() {
+        if !exists(backend.as_ref(), id)? {
+            bail!("required object is missing: {id:?}");
+        }
+    }
+
+    let mut seen = HashSet::new();
+    let mut root = None;
+    for id in contents.objects.iter() {
+        let entry: Entry = rmp_serde::decode::from_read(&mut reader)
+            .with_context(|| format!("archive is truncated before object {id:?}"))?;
+        if &entry.id != id || !seen.insert(entry.id) {
+            bail!("invalid archive: unexpected object {:?}", entry.id);
+        }
+        if entry.len > BLOCK_SIZE {
+            bail!("invalid archive: object {id:?} is too large");
+        }
+
+        let mut object = WriteObject::default();
+        object.set_id(entry.id);
+        reader
+            .read_exact(&mut object.as_inner_mut()[..entry.len])
+            .with_context(|| format!("archive is truncated in object {id:?}"))?;
+
+        if blake3::hash(&object.as_inner()[..entry.len]).as_bytes() != &entry.hash {
+            bail!("object {id:?} is damaged");
+        }
+
+        if id == &contents.root {
+            root = Some(object);
+        } else {
+            backend.write_object(&object)?;
+        }
+    }
+
+    backend.write_object(&root.expect("the root is checked above"))?;
+    backend.sync()?;
+
+    Ok(contents)
+}
+
+/// Make sure that the history of the tree in `backend`, if there's
+/// one, is a prefix of `commits`.
+fn check_history(backend: Arc<dyn Backend>, key: Key, commits: &[CommitId]) -> Result<()> {
+    let mut roots = sealed_root::backup_root_ids(&key)?;
+    roots.push(key.root_object_id()?);
+
+    let mut empty = true;
+    for id in roots.iter() {
+        empty &= !exists(backend.as_ref(), id)?;
+    }
+    if empty {
+        return Ok(());
+    }
+
+    let existing = sealed_root::open::<AnyData>(BlockBuffer::default(), backend, key)
+        .context("the tree in the destination can't be opened")?;
+    let existing = existing.commit_list.read();
+    if existing.len() > commits.len()
+        || existing
+            .iter()
+            .zip(commits.iter())
+            .any(|(ours, theirs)| &ours.id != theirs)
+    {
+        bail!("the archive doesn't extend the history of the tree in the destination");
+    }
+
+    Ok(())
+}
+
+/// Custom data of any type, which is skipped when reading the commits
+/// of a tree.
+#[derive(Serialize)]
+struct AnyData;
+
+impl<'de> Deserialize<'de> for AnyData {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        IgnoredAny::deserialize(deserializer).map(|_| AnyData)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::import;
+    use crate::{
+        backends::test::InMemoryBackend,
+        crypto::UsernamePassword,
+        fields::VersionedMap,
+        object::{Reader, Writer},
+        tree::{CommitFilter, VerifyMode},
+        ChunkPointer, Index, Infinitree, BLOCK_SIZE,
+    };
+
+    #[derive(Index, Default)]
+    #[infinitree(walk)]
+    struct Files {
+        files: VersionedMap<String, ChunkPointer>,
+    }
+
+    fn key() -> UsernamePassword {
+        UsernamePassword::with_credentials(
+            "archive_user".to_string(),
+            "archive_password".to_string(),
+        )
+        .unwrap()
+    }
+
+    fn add_file(tree: &Infinitree<Files>, name: &str) {
+        let mut writer = tree.storage_writer().unwrap();
+        let ptr = writer.write(name.as_bytes()).unwrap();
+        writer.flush().unwrap();
+
+        tree.index().files.insert(name.into(), ptr);
+        tree.commit(name.to_string()).unwrap();
+    }
+
+    fn assert_files(backend: std::sync::Arc<InMemoryBackend>, names: &[&str]) {
+        let tree = Infinitree::<Files>::open(backend, key()).unwrap();
+        tree.load_all().unwrap();
+        assert!(tree.verify(VerifyMode::Full).unwrap().is_ok());
+
+        let mut reader = tree.storage_reader().unwrap();
+        let mut buf = vec![0; BLOCK_SIZE];
+        assert_eq!(tree.index().files.len(), names.len());
+        for name in names {
+            let ptr = tree.index().files.get(*name).unwrap();
+            assert_eq!(reader.read_chunk(&ptr, &mut buf).unwrap(), name.as_bytes());
+        }
+    }
+
+    #[test]
+    fn export_then_import() {
+        let source = InMemoryBackend::shared();
+        let tree = Infinitree::<Files>::empty(source.clone(), key()).unwrap();
+        add_file(&tree, "first");
+
+        let mut full = vec![];
+        tree.export(CommitFilter::All, &mut full).unwrap();
+
+        add_file(&tree, "second");
+        let second = tree.commit_list()[1].id;
+        let mut incremental = vec![];
+        let contents = tree
+            .export(CommitFilter::Range(second, second), &mut incremental)
+            .unwrap();
+        assert!(!contents.required.is_empty());
+
+        // an incremental archive needs the previous objects
+        assert!(import(InMemoryBackend::shared(), key(), incremental.as_slice()).is_err());
+
+        let target = InMemoryBackend::shared();
+        import(target.clone(), key(), full.as_slice()).unwrap();
+        assert_files(target.clone(), &["first"]);
+
+        import(target.clone(), key(), incremental.as_slice()).unwrap();
+        assert_files(target, &["first", "second"]);
+
+        // damage the contents of the last object
+        let len = full.len();
+        full[len - 1] ^= 1;
+        assert!(import(InMemoryBackend::shared(), key(), full.as_slice()).is_err());
+    }
+
+    #[test]
+    fn import_keeps_newer_history() {
+        let source = InMemoryBackend::shared();
+        let tree = Infinitree::<Files>::empty(source, key()).unwrap();
+        add_file(&tree, "first");
+
+        let mut old = vec![];
+        tree.export(CommitFilter::All, &mut old).unwrap();
+
+        let target = InMemoryBackend::shared();
+        import(target.clone(), key(), old.as_slice()).unwrap();
+        // importing the same history again is fine
+        import(target.clone(), key(), old.as_slice()).unwrap();
+
+        add_file(&tree, "second");
+        let mut new = vec![];
+        tree.export(CommitFilter::All, &mut new).unwrap();
+        import(target.clone(), key(), new.as_slice()).unwrap();
+
+        // an older archive doesn't overwrite the newer commits
+        assert!(import(target.clone(), key(), old.as_slice()).is_err());
+        assert_files(target.clone(), &["first", "second"]);
+
+        // neither does one with a diverged history
+        let diverged = Infinitree::<Files>::empty(InMemoryBackend::shared(), key()).unwrap();
+        add_file(&diverged, "other");
+        let mut other = vec![];
+        diverged.export(CommitFilter::All, &mut other).unwrap();
+        assert!(import(target.clone(), key(), other.as_slice()).is_err());
+        assert_files(target, &["first", "second"]);
+    }
+}
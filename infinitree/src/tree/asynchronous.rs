@@ -0,0 +1,247 @@
+use super::{Commit, Infinitree, Message};
+use crate::{
+    backends::{AsyncBackend, BlockOn},
+    fields::{Collection, Intent, Load, QueryAction},
+    Index, Key,
+};
+use anyhow::Result;
+use serde::{de::DeserializeOwned, Serialize};
+use std::{ops::Deref, sync::Arc};
+use tokio::{runtime::Handle, task};
+
+/// A handle to an [`Infinitree`] that can be used from async code.
+///
+/// Operations that read or write the backend are moved to the
+/// blocking thread pool of the Tokio runtime, so they can be awaited
+/// from any runtime, including a current-thread one.
+///
+/// The underlying tree is available through
+/// [`tree`](Self::tree), and its blocking methods should only be
+/// called from a blocking context, e.g. inside
+/// [`tokio::task::spawn_blocking`].
+///
+/// Cloning the handle is cheap, and all clones refer to the same
+/// tree.
+pub struct AsyncInfinitree<I, CustomData = ()>
+where
+    I: Send + Sync + 'static,
+    CustomData: Serialize + DeserializeOwned + Send + Sync + 'static,
+{
+    // only taken when the handle is dropped
+    tree: Option<Arc<Infinitree<I, CustomData>>>,
+}
+
+impl<I, CustomData> Clone for AsyncInfinitree<I, CustomData>
+where
+    I: Send + Sync + 'static,
+    CustomData: Serialize + DeserializeOwned + Send + Sync + 'static,
+{
+    fn clone(&self) -> Self {
+        Self {
+            tree: self.tree.clone(),
+        }
+    }
+}
+
+impl<I, CustomData> Drop for AsyncInfinitree<I, CustomData>
+where
+    I: Send + Sync + 'static,
+    CustomData: Serialize + DeserializeOwned + Send + Sync + 'static,
+{
+    fn drop(&mut self) {
+        // dropping the tree syncs the backend, which must not block
+        // the runtime
+        if let (Some(tree), Ok(handle)) = (self.tree.take(), Handle::try_current()) {
+            if Arc::strong_count(&tree) == 1 {
+                handle.spawn_blocking(move || drop(tree));
+            }
+        }
+    }
+}
+
+impl<I, CustomData> From<Infinitree<I, CustomData>> for AsyncInfinitree<I, CustomData>
+where
+    I: Send + Sync + 'static,
+    CustomData: Serialize + DeserializeOwned + Send + Sync + 'static,
+{
+    fn from(tree: Infinitree<I, CustomData>) -> Self {
+        Self {
+            tree: Some(Arc::new(tree)),
+        }
+    }
+}
+
+impl<I, CustomData> AsyncInfinitree<I, CustomData>
+where
+    I: Index + Default + Send + Sync + 'static,
+    CustomData: Serialize + DeserializeOwned + Send + Sync + 'static,
+{
+    /// Initialize an empty index and tree with no version history.
+    ///
+    /// See [`Infinitree::empty`].
+    pub async fn empty(backend: Arc<dyn AsyncBackend>, key: impl Into<Key>) -> Result<Self> {
+        let backend = BlockOn::new(backend);
+        let key = key.into();
+
+        let tree = task::spawn_blocking(move || Infinitree::empty(backend, key)).await??;
+        Ok(tree.into())
+    }
+
+    /// Load all version information from the tree.
+    ///
+    /// See [`Infinitree::open`].
+    pub async fn open(backend: Arc<dyn AsyncBackend>, key: impl Into<Key>) -> Result<Self> {
+        let backend = BlockOn::new(backend);
+        let key = key.into();
+
+        let tree = task::spawn_blocking(move || Infinitree::open(backend, key)).await??;
+        Ok(tree.into())
+    }
+}
+
+impl<I, CustomData> AsyncInfinitree<I, CustomData>
+where
+    I: Index + Send + Sync + 'static,
+    CustomData: Serialize + DeserializeOwned + Send + Sync + Default + 'static,
+{
+    /// Create a commit if there are changes in the index.
+    ///
+    /// See [`Infinitree::commit`].
+    pub async fn commit(
+        &self,
+        message: impl Into<Message>,
+    ) -> Result<Option<Arc<Commit<CustomData>>>> {
+        let message = message.into();
+        self.spawn(move |tree| tree.commit(message)).await
+    }
+}
+
+impl<I, CustomData> AsyncInfinitree<I, CustomData>
+where
+    I: Index + Send + Sync + 'static,
+    CustomData: Serialize + DeserializeOwned + Send + Sync + 'static,
+{
+    /// The underlying tree.
+    pub fn tree(&self) -> &Arc<Infinitree<I, CustomData>> {
+        self.tree.as_ref().expect("only taken on drop")
+    }
+
+    /// Return an immutable reference to the internal index.
+    ///
+    /// See [`Infinitree::index`].
+    pub fn index(&self) -> impl Deref<Target = I> + '_ {
+        self.tree().index()
+    }
+
+    /// Load into memory all fields for the selected version ranges.
+    pub async fn load_all(&self) -> Result<()> {
+        self.spawn(|tree| tree.load_all()).await
+    }
+
+    /// Load the field for the selected generation set.
+    pub async fn load<T: Load + Send + 'static>(&self, field: Intent<Box<T>>) -> Result<()> {
+        self.spawn(move |tree| tree.load(field)).await
+    }
+
+    /// Pick up the commits that have been made since the tree was
+    /// opened or last refreshed.
+    ///
+    /// See [`Infinitree::refresh`].
+    pub async fn refresh(&self) -> Result<Vec<Arc<Commit<CustomData>>>> {
+        self.spawn(|tree| tree.refresh()).await
+    }
+
+    /// Collect the items of `field` where `pred` returns
+    /// [`QueryAction::Take`].
+    ///
+    /// This is the async version of [`Infinitree::iter`], but the
+    /// items are returned all at once.
+    pub async fn collect<K, O, Q>(
+        &self,
+        field: Intent<Box<Q>>,
+        pred: impl Fn(&K) -> QueryAction + Send + Sync + 'static,
+    ) -> Result<Vec<O>>
+    where
+        for<'de> Q::Serialized: serde::Deserialize<'de>,
+        Q: Collection<Key = K, Item = O> + Send + Sync + 'static,
+        K: Eq + std::hash::Hash + Clone + Send + Sync + 'static,
+        O: Send + 'static,
+    {
+        self.spawn(move |tree| Ok(tree.iter(field, pred)?.collect()))
+            .await
+    }
+
+    /// Wait for all pending writes to finish in the backend.
+    ///
+    /// The last handle to the tree syncs the backend in the
+    /// background when dropped, so use this to make sure everything
+    /// has been written.
+    pub async fn sync(&self) -> Result<()> {
+        self.spawn(|tree| Ok(tree.backend().sync()?)).await
+    }
+
+    /// Run `f` with the tree in the blocking thread pool.
+    async fn spawn<T: Send + 'static>(
+        &self,
+        f: impl FnOnce(&Infinitree<I, CustomData>) -> Result<T> + Send + 'static,
+    ) -> Result<T> {
+        let tree = self.tree().clone();
+        task::spawn_blocking(move || f(&tree)).await?
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::AsyncInfinitree;
+    use crate::{
+        backends::{test::InMemoryBackend, SpawnBlocking},
+        crypto::UsernamePassword,
+        fields::{QueryAction, VersionedMap},
+        Index,
+    };
+
+    #[derive(Index, Default)]
+    struct Counters {
+        map: VersionedMap<usize, usize>,
+    }
+
+    fn key() -> UsernamePassword {
+        UsernamePassword::with_credentials("async_user".to_string(), "async_password".to_string())
+            .unwrap()
+    }
+
+    #[test]
+    fn current_thread_runtime() {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        let backend = SpawnBlocking::new(InMemoryBackend::shared());
+
+        runtime.block_on(async {
+            let tree = AsyncInfinitree::<Counters>::empty(backend.clone(), key())
+                .await
+                .unwrap();
+            for i in 0..3 {
+                tree.index().map.insert(i, i);
+            }
+            tree.commit("first").await.unwrap();
+            tree.sync().await.unwrap();
+            drop(tree);
+
+            let tree = AsyncInfinitree::<Counters>::open(backend.clone(), key())
+                .await
+                .unwrap();
+            let items = tree
+                .collect(tree.index().map(), |k| match k {
+                    0 => QueryAction::Skip,
+                    _ => QueryAction::Take,
+                })
+                .await
+                .unwrap();
+            assert_eq!(items.len(), 2);
+
+            tree.load_all().await.unwrap();
+            assert_eq!(tree.index().map.len(), 3);
+        });
+    }
+}
@@ -0,0 +1,386 @@
+use super::Infinitree;
+use crate::index::Index;
+use anyhow::{Context, Result};
+use parking_lot::{Condvar, Mutex};
+use serde::{de::DeserializeOwned, Serialize};
+use std::{
+    mem::take,
+    sync::Arc,
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+/// Decides when an [`AutoCommit`] driver commits the changes of a
+/// tree.
+///
+/// A commit is made when _any_ of the limits is reached:
+///
+///  * the number of mutations recorded since the last commit,
+///  * the time since the first mutation that hasn't been committed,
+///  * the estimated size of the changes that haven't been committed.
+///
+/// An empty policy only commits when the driver is
+/// [flushed](AutoCommit::flush) or shut down.
+///
+/// # Examples
+///
+/// ```no_run
+/// use infinitree::{*, crypto::UsernamePassword, fields::VersionedMap, backends::Directory, tree::{AutoCommit, AutoCommitPolicy}};
+/// use std::{sync::Arc, time::Duration};
+///
+/// let tree = Arc::new(Infinitree::<VersionedMap<String, String>>::open(
+///     Directory::new("/storage").unwrap(),
+///     UsernamePassword::with_credentials("username".to_string(),
+///                                        "password".to_string()).unwrap()
+/// ).unwrap());
+///
+/// let policy = AutoCommitPolicy::new()
+///     .after_mutations(1000)
+///     .after_interval(Duration::from_secs(30))
+///     .after_dirty_bytes(16 * 1024 * 1024);
+///
+/// let autocommit = AutoCommit::new(tree.clone(), policy).unwrap();
+///
+/// let value = "value".to_string();
+/// tree.index().insert("key".into(), value.clone());
+/// autocommit.record(value.len());
+///
+/// autocommit.shutdown().unwrap();
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct AutoCommitPolicy {
+    mutations: Option<usize>,
+    interval: Option<Duration>,
+    dirty_bytes: Option<usize>,
+}
+
+impl AutoCommitPolicy {
+    /// Create an empty policy.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Commit after `n` mutations.
+    pub fn after_mutations(mut self, n: usize) -> Self {
+        self.mutations = Some(n.max(1));
+        self
+    }
+
+    /// Commit once `interval` has passed since the first mutation
+    /// that hasn't been committed.
+    pub fn after_interval(mut self, interval: Duration) -> Self {
+        self.interval = Some(interval);
+        self
+    }
+
+    /// Commit once the changes are estimated to be at least `bytes`
+    /// large.
+    pub fn after_dirty_bytes(mut self, bytes: usize) -> Self {
+        self.dirty_bytes = Some(bytes.max(1));
+        self
+    }
+
+    /// Returns what makes a commit due, if anything.
+    fn due(&self, state: &State) -> Option<&'static str> {
+        if state.mutations == 0 {
+            return None;
+        }
+
+        if self.mutations.is_some_and(|n| state.mutations >= n) {
+            return Some("mutations");
+        }
+        if self.dirty_bytes.is_some_and(|n| state.dirty_bytes >= n) {
+            return Some("dirty bytes");
+        }
+        if self.deadline(state).is_some_and(|at| at <= Instant::now()) {
+            return Some("interval");
+        }
+
+        None
+    }
+
+    /// The time the changes are due because of the interval.
+    fn deadline(&self, state: &State) -> Option<Instant> {
+        Some(state.first_change? + self.interval?)
+    }
+
+    /// Returns `true` if new changes have to wait for the commit
+    /// that's running, because twice the limit has piled up.
+    fn overloaded(&self, state: &State) -> bool {
+        state.committing
+            && (self.mutations.is_some_and(|n| state.mutations >= 2 * n)
+                || self.dirty_bytes.is_some_and(|n| state.dirty_bytes >= 2 * n))
+    }
+}
+
+#[derive(Default)]
+struct State {
+    mutations: usize,
+    dirty_bytes: usize,
+    first_change: Option<Instant>,
+
+    committing: bool,
+    flush: bool,
+    shutdown: bool,
+
+    /// The number of commits that have been started and finished,
+    /// so [`AutoCommit::flush`] can wait for the next one.
+    started: u64,
+    finished: u64,
+
+    error: Option<anyhow::Error>,
+}
+
+struct Shared {
+    policy: AutoCommitPolicy,
+    state: Mutex<State>,
+    changed: Condvar,
+}
+
+/// Commits the changes of a tree from a background thread, following
+/// an [`AutoCommitPolicy`].
+///
+/// The tree can't tell when its index is changed, so every mutation
+/// needs to be [recorded](Self::record) with the driver.
+///
+/// Commits are made with
+/// [`CommitMode::OnlyOnChange`](super::CommitMode::OnlyOnChange) and
+/// a generated message. If a commit fails, the error is returned by
+/// the next [`flush`](Self::flush) or [`shutdown`](Self::shutdown),
+/// and the driver keeps going.
+///
+/// Dropping the driver commits the remaining changes, but ignores any
+/// errors. Use [`shutdown`](Self::shutdown) to see them.
+pub struct AutoCommit<I, CustomData = ()>
+where
+    CustomData: Serialize + DeserializeOwned + Send + Sync + 'static,
+{
+    tree: Arc<Infinitree<I, CustomData>>,
+    shared: Arc<Shared>,
+    worker: Option<JoinHandle<()>>,
+}
+
+impl<I, CustomData> AutoCommit<I, CustomData>
+where
+    I: Index + 'static,
+    CustomData: Serialize + DeserializeOwned + Send + Sync + Default + 'static,
+{
+    /// Start committing the changes of `tree` following `policy`.
+    pub fn new(tree: Arc<Infinitree<I, CustomData>>, policy: AutoCommitPolicy) -> Result<Self> {
+        let shared = Arc::new(Shared {
+            policy,
+            state: Default::default(),
+            changed: Condvar::new(),
+        });
+
+        let worker = {
+            let tree = tree.clone();
+            let shared = shared.clone();
+
+            thread::Builder::new()
+                .name("infinitree-autocommit".into())
+                .spawn(move || run(&tree, &shared))?
+        };
+
+        Ok(Self {
+            tree,
+            shared,
+            worker: Some(worker),
+        })
+    }
+}
+
+impl<I, CustomData> AutoCommit<I, CustomData>
+where
+    CustomData: Serialize + DeserializeOwned + Send + Sync + 'static,
+{
+    /// The tree that's being committed.
+    pub fn tree(&self) -> &Arc<Infinitree<I, CustomData>> {
+        &self.tree
+    }
+
+    /// Record a mutation of the index that changed about `bytes`.
+    ///
+    /// If a commit is running and twice the limit of the policy has
+    /// piled up since it started, this blocks until the commit is
+    /// done.
+    pub fn record(&self, bytes: usize) {
+        let mut state = self.shared.state.lock();
+        while self.shared.policy.overloaded(&state) {
+            self.shared.changed.wait(&mut state);
+        }
+
+        let first = state.first_change.is_none();
+        state.mutations += 1;
+        state.dirty_bytes += bytes;
+        state.first_change.get_or_insert_with(Instant::now);
+
+        // wake the worker to start the timer, or commit
+        if first || self.shared.policy.due(&state).is_some() {
+            self.shared.changed.notify_all();
+        }
+    }
+
+    /// Commit the recorded changes now, and wait for it to finish.
+    ///
+    /// Returns the error of the last commit that failed since the
+    /// last call, if any.
+    pub fn flush(&self) -> Result<()> {
+        let mut state = self.shared.state.lock();
+        let target = state.started + 1;
+        state.flush = true;
+        self.shared.changed.notify_all();
+
+        while state.finished < target {
+            self.shared.changed.wait(&mut state);
+        }
+
+        match state.error.take() {
+            Some(error) => Err(error),
+            None => Ok(()),
+        }
+    }
+
+    /// Commit the recorded changes, and stop the background thread.
+    ///
+    /// Returns the error of the last commit that failed, if any.
+    pub fn shutdown(mut self) -> Result<()> {
+        self.stop()
+    }
+
+    fn stop(&mut self) -> Result<()> {
+        let Some(worker) = self.worker.take() else {
+            return Ok(());
+        };
+
+        self.shared.state.lock().shutdown = true;
+        self.shared.changed.notify_all();
+
+        let panicked = worker.join().is_err();
+        let error = self.shared.state.lock().error.take();
+        match error {
+            Some(error) => Err(error),
+            None if panicked => Err(anyhow::anyhow!("the autocommit thread has panicked")),
+            None => Ok(()),
+        }
+    }
+}
+
+impl<I, CustomData> Drop for AutoCommit<I, CustomData>
+where
+    CustomData: Serialize + DeserializeOwned + Send + Sync + 'static,
+{
+    fn drop(&mut self) {
+        let _ = self.stop();
+    }
+}
+
+/// Commit the changes of `tree` whenever they're due, until shut
+/// down.
+fn run<I, CustomData>(tree: &Infinitree<I, CustomData>, shared: &Shared)
+where
+    I: Index,
+    CustomData: Serialize + DeserializeOwned + Send + Sync + Default,
+{
+    loop {
+        let mut state = shared.state.lock();
+        let reason = loop {
+            if state.shutdown {
+                break "shutdown";
+            }
+            if state.flush {
+                break "flush";
+            }
+            if let Some(reason) = shared.policy.due(&state) {
+                break reason;
+            }
+
+            match shared.policy.deadline(&state) {
+                Some(at) => {
+                    shared.changed.wait_until(&mut state, at);
+                }
+                None => shared.changed.wait(&mut state),
+            }
+        };
+
+        let stop = state.shutdown;
+        let mutations = take(&mut state.mutations);
+        let dirty_bytes = take(&mut state.dirty_bytes);
+        state.first_change = None;
+        state.flush = false;
+        state.committing = true;
+        state.started += 1;
+        drop(state);
+
+        let result = tree
+            .commit(format!(
+                "autocommit: {mutations} changes, {dirty_bytes} bytes ({reason})"
+            ))
+            .context("autocommit failed");
+
+        let mut state = shared.state.lock();
+        state.committing = false;
+        state.finished = state.started;
+        if let Err(error) = result {
+            state.error = Some(error);
+        }
+        shared.changed.notify_all();
+
+        if stop {
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{AutoCommit, AutoCommitPolicy};
+    use crate::{backends::test::InMemoryBackend, crypto::UsernamePassword, fields::VersionedMap};
+    use std::{sync::Arc, time::Duration};
+
+    type Tree = crate::Infinitree<VersionedMap<usize, usize>>;
+
+    fn key() -> UsernamePassword {
+        UsernamePassword::with_credentials(
+            "autocommit_user".to_string(),
+            "autocommit_password".to_string(),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn commit_after_mutations() {
+        let backend = InMemoryBackend::shared();
+        let tree = Arc::new(Tree::empty(backend.clone(), key()).unwrap());
+        let autocommit = AutoCommit::new(
+            tree.clone(),
+            AutoCommitPolicy::new()
+                .after_mutations(2)
+                .after_interval(Duration::from_secs(3600)),
+        )
+        .unwrap();
+
+        for i in 0..2 {
+            tree.index().insert(i, i);
+            autocommit.record(8);
+        }
+
+        // nothing's left to commit after the policy kicked in
+        autocommit.flush().unwrap();
+        assert_eq!(tree.commit_list().len(), 1);
+
+        // the rest is committed on shutdown
+        tree.index().insert(2, 2);
+        autocommit.record(8);
+        autocommit.shutdown().unwrap();
+        assert_eq!(tree.commit_list().len(), 2);
+
+        let message = tree.commit_list()[0].metadata.message.clone().unwrap();
+        assert!(message.contains("2 changes"));
+
+        drop(tree);
+        let tree = Tree::open(backend, key()).unwrap();
+        tree.load_all().unwrap();
+        assert_eq!(tree.index().len(), 3);
+    }
+}
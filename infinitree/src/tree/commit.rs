@@ -1,7 +1,7 @@
-use crate::Id;
-use serde::{Deserialize, Serialize};
+use crate::{crypto::WriterKey, Id};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use serde_with::serde_as;
-use std::{sync::Arc, time::SystemTime};
+use std::{collections::HashSet, sync::Arc, time::SystemTime};
 
 /// The list of commits already recorded
 pub type CommitList<CustomData> = Vec<Arc<Commit<CustomData>>>;
@@ -21,6 +21,22 @@ where
 
     /// Metadata associated with the commit
     pub metadata: CommitMetadata<CustomData>,
+
+    /// The signature of the writer that made the commit, if any.
+    #[serde(default)]
+    pub signature: Option<CommitSignature>,
+}
+
+/// An Ed25519 signature over the id and metadata of a [`Commit`].
+#[serde_as]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct CommitSignature {
+    /// The writer that signed the commit.
+    pub writer: WriterKey,
+
+    /// The signature itself.
+    #[serde_as(as = "serde_with::Bytes")]
+    pub signature: Vec<u8>,
 }
 
 /// Hashed metadata of a [`Commit`] that are included in its
@@ -43,6 +59,38 @@ where
 
     /// Any machine-readable data you may need to store
     pub custom_data: CustomData,
+
+    /// Further parents of a merge commit.
+    ///
+    /// A merge commit contains all changes merged from these
+    /// commits, so following [`previous`](Self::previous) is enough
+    /// to load the state of the tree.
+    ///
+    /// Left out when empty, so that the ids of commits that were made
+    /// before merges were supported stay the same.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub merged: Vec<CommitId>,
+}
+
+impl<CustomData: Serialize> CommitMetadata<CustomData> {
+    /// All parents of the commit, starting with
+    /// [`previous`](Self::previous).
+    pub fn parents(&self) -> impl Iterator<Item = &CommitId> {
+        self.previous.iter().chain(self.merged.iter())
+    }
+}
+
+impl<CustomData: Serialize + DeserializeOwned> CommitMetadata<CustomData> {
+    /// Round the metadata to what will be read back after storing it.
+    ///
+    /// The commit time loses precision when serialized, so the id of
+    /// a commit needs to be calculated from the rounded metadata to
+    /// remain verifiable once the tree is opened again.
+    pub(crate) fn rounded(self) -> anyhow::Result<Self> {
+        Ok(crate::deserialize_from_slice(&crate::serialize_to_vec(
+            &self,
+        )?)?)
+    }
 }
 
 impl<CustomData: Serialize + Default> Default for CommitMetadata<CustomData> {
@@ -52,14 +100,26 @@ impl<CustomData: Serialize + Default> Default for CommitMetadata<CustomData> {
             previous: None,
             message: None,
             custom_data: CustomData::default(),
+            merged: vec![],
         }
     }
 }
 
+/// A predicate to select commits with
+/// [`CommitFilter::Matching`].
+pub type CommitPredicate<CustomData> = Arc<dyn Fn(&Commit<CustomData>) -> bool + Send + Sync>;
+
 /// Enum to navigate the versions that are available in an Infinitree
-pub enum CommitFilter {
+pub enum CommitFilter<CustomData = ()>
+where
+    CustomData: Serialize,
+{
     /// On querying, all versions will be crawled. This is the
     /// default.
+    ///
+    /// If a branch is checked out using
+    /// [`Infinitree::checkout`](super::Infinitree::checkout), only
+    /// the history of the branch is crawled.
     All,
 
     /// Only a single generation will be looked at during querying.
@@ -72,9 +132,48 @@ pub enum CommitFilter {
     /// The first parameter **must** be earlier generation than the
     /// second.
     Range(CommitId, CommitId),
+
+    /// All generations up to and including the head of the branch.
+    Branch(String),
+
+    /// All generations up to and including the tagged commit.
+    Tag(String),
+
+    /// The state of the tree at the given time.
+    ///
+    /// Works like [`All`](Self::All), but generations committed after
+    /// the given time are left out.
+    AsOf(SystemTime),
+
+    /// Only use generations that were committed between the two
+    /// given times, inclusive.
+    ///
+    /// Works like [`All`](Self::All), but generations committed
+    /// outside of the interval are left out.
+    Between(SystemTime, SystemTime),
+
+    /// Only use the generations selected by the filter that match
+    /// the predicate.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use infinitree::tree::CommitFilter;
+    /// use std::sync::Arc;
+    ///
+    /// let filter = CommitFilter::<()>::Matching(
+    ///     Box::new(CommitFilter::All),
+    ///     Arc::new(|commit| commit.metadata.message.as_deref() != Some("wip")),
+    /// );
+    /// ```
+    Matching(Box<CommitFilter<CustomData>>, CommitPredicate<CustomData>),
+
+    /// Use the generations selected by the filter, except the given
+    /// ones.
+    Excluding(Box<CommitFilter<CustomData>>, HashSet<CommitId>),
 }
 
-impl Default for CommitFilter {
+impl<CustomData: Serialize> Default for CommitFilter<CustomData> {
     fn default() -> Self {
         Self::All
     }
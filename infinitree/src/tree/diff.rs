@@ -0,0 +1,403 @@
+use super::{merge::first_parents, CommitId, Infinitree};
+use crate::{
+    fields::{depth::Depth, Collection, Intent},
+    index::{read_record, Index, TransactionList},
+    object::{AEADReader, DeserializeStream, Pool, PoolRef},
+};
+use anyhow::{bail, Result};
+use serde::{de::DeserializeOwned, Serialize};
+use std::{
+    collections::{hash_map::IntoIter, HashMap, HashSet},
+    hash::Hash,
+    marker::PhantomData,
+    sync::Arc,
+};
+
+/// A change to a single key of a
+/// [`VersionedMap`](crate::fields::VersionedMap) between two commits.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Change<K, V> {
+    /// The key has been inserted with a value.
+    Added(K, Arc<V>),
+    /// The key has been removed. This is the last value it had.
+    Removed(K, Arc<V>),
+    /// The value of the key has changed from the first value to the
+    /// second one.
+    Modified(K, Arc<V>, Arc<V>),
+}
+
+impl<K, V> Change<K, V> {
+    /// The key that has changed.
+    pub fn key(&self) -> &K {
+        match self {
+            Change::Added(key, _) | Change::Removed(key, _) | Change::Modified(key, _, _) => key,
+        }
+    }
+}
+
+impl<I: Index, CustomData> Infinitree<I, CustomData>
+where
+    CustomData: Serialize + DeserializeOwned + Send + Sync,
+{
+    /// List the keys of a [`VersionedMap`](crate::fields::VersionedMap)
+    /// field that have been added, removed, or modified between the
+    /// states at `from` and `to`.
+    ///
+    /// The returned iterator walks the generations lazily, so the map
+    /// is never loaded into memory. The generations that are only in
+    /// the history of `from` are read when this is called. The ones
+    /// that are only in the history of `to` are read as the iterator
+    /// advances, and the shared history is only searched at the end,
+    /// for the previous values of keys that have changed.
+    ///
+    /// Keys that have changed on both sides are yielded first, most
+    /// recently changed first, followed by the rest once the shared
+    /// history has been searched. Keys that have been changed back to
+    /// their value at `from` are not reported. Once an error is
+    /// returned, the iterator is finished.
+    ///
+    /// The commits don't need to be on the same branch, but an error
+    /// is returned if either of them is not found.
+    pub fn diff<K, V, Q>(
+        &self,
+        field: Intent<Box<Q>>,
+        from: &CommitId,
+        to: &CommitId,
+    ) -> Result<impl Iterator<Item = Result<Change<K, V>>>>
+    where
+        Q: Collection<Key = K, Item = (K, Option<Arc<V>>)>,
+        K: Eq + Hash + Clone,
+        V: Serialize,
+    {
+        let (old, new, shared) = {
+            let commits = self.commit_list();
+            let graph = commits.iter().map(|c| (c.id, c)).collect::<HashMap<_, _>>();
+            if !graph.contains_key(from) || !graph.contains_key(to) {
+                bail!("commit not found");
+            }
+
+            let old = first_parents(&graph, Some(*from), &HashSet::new());
+            let new = first_parents(&graph, Some(*to), &HashSet::new());
+            let shared = old.intersection(&new).copied().collect::<HashSet<_>>();
+
+            (&old - &shared, &new - &shared, shared)
+        };
+
+        let select = |commits: &HashSet<CommitId>| {
+            self.root
+                .transaction_log
+                .read()
+                .iter()
+                .filter(|(id, name, _)| name == &field.name && commits.contains(id))
+                .cloned()
+                .collect::<TransactionList>()
+        };
+
+        let mut reader = self.chunk_reader()?;
+        let mut old_values = HashMap::new();
+        let mut records = Records::<Q>::new(self.reader_pool.clone(), select(&old));
+        while let Some(record) = records.next_record()? {
+            if !old_values.contains_key(Q::key(&record)) {
+                let (key, value) = Q::load(record, &mut *reader);
+                old_values.insert(key, value);
+            }
+        }
+
+        Ok(Diff {
+            new: Records::<Q>::new(self.reader_pool.clone(), select(&new)),
+            shared: Records::new(self.reader_pool.clone(), select(&shared)),
+            reader,
+            old: old_values,
+            seen: Some(HashSet::new()),
+            pending: HashMap::new(),
+            unchanged: None,
+            failed: false,
+        })
+    }
+}
+
+/// Walk the records of a field in a list of transactions, most
+/// recent first.
+struct Records<Q: Collection> {
+    streams: Box<dyn Iterator<Item = DeserializeStream> + Sync + Send>,
+    current: Option<DeserializeStream>,
+    _fieldtype: PhantomData<Q>,
+}
+
+impl<Q: Collection> Records<Q> {
+    fn new(pool: Pool<AEADReader>, transaction_list: TransactionList) -> Self {
+        Self {
+            streams: Q::Depth::resolve(pool, transaction_list),
+            current: None,
+            _fieldtype: PhantomData,
+        }
+    }
+
+    fn next_record(&mut self) -> Result<Option<Q::Serialized>> {
+        loop {
+            if let Some(stream) = self.current.as_mut() {
+                if let Some(record) = read_record::<Q::Serialized>(stream)? {
+                    return Ok(Some(record));
+                }
+            }
+
+            match self.streams.next() {
+                Some(stream) => self.current = Some(stream),
+                None => return Ok(None),
+            }
+        }
+    }
+}
+
+/// A key whose value on one side of the diff is in the shared
+/// history.
+enum Pending<V> {
+    /// The value at `to` is known.
+    After(Option<Arc<V>>),
+    /// The value at `from` is known.
+    Before(Option<Arc<V>>),
+}
+
+struct Diff<Q: Collection, K, V> {
+    new: Records<Q>,
+    shared: Records<Q>,
+    reader: PoolRef<AEADReader>,
+    /// The latest values at `from` outside the shared history.
+    old: HashMap<K, Option<Arc<V>>>,
+    /// The keys found on the `to` side, until it's been walked.
+    seen: Option<HashSet<K>>,
+    pending: HashMap<K, Pending<V>>,
+    /// The keys that are not in the shared history.
+    unchanged: Option<IntoIter<K, Pending<V>>>,
+    failed: bool,
+}
+
+impl<Q, K, V> Diff<Q, K, V>
+where
+    Q: Collection<Key = K, Item = (K, Option<Arc<V>>)>,
+    K: Eq + Hash + Clone,
+    V: Serialize,
+{
+    fn next_change(&mut self) -> Result<Option<Change<K, V>>> {
+        if let Some(seen) = self.seen.as_mut() {
+            while let Some(record) = self.new.next_record()? {
+                if !seen.insert(Q::key(&record).clone()) {
+                    continue;
+                }
+
+                let (key, after) = Q::load(record, &mut *self.reader);
+                match self.old.remove(&key) {
+                    Some(before) => {
+                        if let Some(change) = change(key, before, after)? {
+                            return Ok(Some(change));
+                        }
+                    }
+                    None => {
+                        self.pending.insert(key, Pending::After(after));
+                    }
+                }
+            }
+
+            // keys that only changed on one side still need the value
+            // they had in the shared history
+            self.seen = None;
+            for (key, before) in self.old.drain() {
+                self.pending.insert(key, Pending::Before(before));
+            }
+        }
+
+        while self.unchanged.is_none() && !self.pending.is_empty() {
+            let Some(record) = self.shared.next_record()? else {
+                break;
+            };
+            if !self.pending.contains_key(Q::key(&record)) {
+                continue;
+            }
+
+            let (key, value) = Q::load(record, &mut *self.reader);
+            let change = match self.pending.remove(&key) {
+                Some(Pending::After(after)) => change(key, value, after)?,
+                Some(Pending::Before(before)) => change(key, before, value)?,
+                None => None,
+            };
+            if change.is_some() {
+                return Ok(change);
+            }
+        }
+
+        let unchanged = self
+            .unchanged
+            .get_or_insert_with(|| std::mem::take(&mut self.pending).into_iter());
+        for (key, pending) in unchanged {
+            let change = match pending {
+                Pending::After(after) => change(key, None, after)?,
+                Pending::Before(before) => change(key, before, None)?,
+            };
+            if change.is_some() {
+                return Ok(change);
+            }
+        }
+
+        Ok(None)
+    }
+}
+
+impl<Q, K, V> Iterator for Diff<Q, K, V>
+where
+    Q: Collection<Key = K, Item = (K, Option<Arc<V>>)>,
+    K: Eq + Hash + Clone,
+    V: Serialize,
+{
+    type Item = Result<Change<K, V>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+
+        let next = self.next_change().transpose();
+        self.failed = matches!(next, Some(Err(_)));
+        next
+    }
+}
+
+/// Compare the values of `key` at the two sides of the diff.
+fn change<K, V: Serialize>(
+    key: K,
+    before: Option<Arc<V>>,
+    after: Option<Arc<V>>,
+) -> Result<Option<Change<K, V>>> {
+    Ok(match (before, after) {
+        (None, Some(value)) => Some(Change::Added(key, value)),
+        (Some(value), None) => Some(Change::Removed(key, value)),
+        (Some(before), Some(after))
+            if crate::serialize_to_vec(&before)? != crate::serialize_to_vec(&after)? =>
+        {
+            Some(Change::Modified(key, before, after))
+        }
+        _ => None,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::Change;
+    use crate::{
+        backends::test::InMemoryBackend, crypto::UsernamePassword, fields::VersionedMap, Index,
+        Infinitree,
+    };
+
+    #[derive(Index, Default)]
+    struct State {
+        map: VersionedMap<usize, usize>,
+    }
+
+    fn key() -> UsernamePassword {
+        UsernamePassword::with_credentials("diff_user".to_string(), "diff_password".to_string())
+            .unwrap()
+    }
+
+    #[test]
+    fn diff_commits() {
+        let backend = InMemoryBackend::shared();
+
+        {
+            let tree = Infinitree::<State>::empty(backend.clone(), key()).unwrap();
+            for i in 0..4 {
+                tree.index().map.insert(i, i);
+            }
+            tree.commit("first").unwrap();
+
+            tree.index().map.update_with(0, |_| 10);
+            tree.index().map.update_with(1, |_| 10);
+            tree.index().map.remove(2);
+            tree.commit("second").unwrap();
+
+            // changed back to the original value
+            tree.index().map.update_with(1, |_| 1);
+            tree.index().map.insert(4, 4);
+            tree.commit("third").unwrap();
+        }
+
+        let tree = Infinitree::<State>::open(backend, key()).unwrap();
+        let (first, last) = {
+            let commits = tree.commit_list();
+            (commits[0].id, commits[2].id)
+        };
+
+        let mut changes = tree
+            .diff(tree.index().map(), &first, &last)
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        changes.sort_by_key(|c| *c.key());
+        assert_eq!(
+            changes,
+            vec![
+                Change::Modified(0, 0.into(), 10.into()),
+                Change::Removed(2, 2.into()),
+                Change::Added(4, 4.into()),
+            ]
+        );
+
+        let mut changes = tree
+            .diff(tree.index().map(), &last, &first)
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        changes.sort_by_key(|c| *c.key());
+        assert_eq!(
+            changes,
+            vec![
+                Change::Modified(0, 10.into(), 0.into()),
+                Change::Added(2, 2.into()),
+                Change::Removed(4, 4.into()),
+            ]
+        );
+
+        assert_eq!(
+            tree.diff(tree.index().map(), &last, &last).unwrap().count(),
+            0
+        );
+        assert!(tree.index().map.is_empty());
+    }
+
+    #[test]
+    fn diff_branches() {
+        let backend = InMemoryBackend::shared();
+        let tree = Infinitree::<State>::empty(backend, key()).unwrap();
+        for i in 0..3 {
+            tree.index().map.insert(i, i);
+        }
+        let base = tree.commit("base").unwrap().unwrap().id;
+        tree.create_branch("feature", base).unwrap();
+
+        tree.index().map.update_with(0, |_| 10);
+        tree.index().map.update_with(1, |_| 10);
+        let main = tree.commit("main").unwrap().unwrap().id;
+
+        tree.checkout(Some("feature".into()));
+        tree.index().map.update_with(0, |_| 20);
+        tree.index().map.remove(2);
+        let feature = tree.commit("feature").unwrap().unwrap().id;
+
+        let mut changes = tree.diff(tree.index().map(), &main, &feature).unwrap();
+
+        // changed on both branches, so it's found before the shared
+        // history is searched
+        assert_eq!(
+            changes.next().unwrap().unwrap(),
+            Change::Modified(0, 10.into(), 20.into())
+        );
+
+        let mut rest = changes.collect::<Result<Vec<_>, _>>().unwrap();
+        rest.sort_by_key(|c| *c.key());
+        assert_eq!(
+            rest,
+            vec![
+                Change::Modified(1, 10.into(), 1.into()),
+                Change::Removed(2, 2.into()),
+            ]
+        );
+    }
+}
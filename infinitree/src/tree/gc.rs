@@ -0,0 +1,339 @@
+use super::{sealed_root, Infinitree};
+use crate::{
+    index::{Index, TransactionList},
+    object::BlockBuffer,
+    ObjectId,
+};
+use anyhow::{bail, Result};
+use serde::{de::DeserializeOwned, Serialize};
+use std::collections::HashSet;
+
+/// Controls whether [`Infinitree::gc`] will modify the backend.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GcMode {
+    /// Only report the objects that would be deleted.
+    DryRun,
+    /// Delete all unreferenced objects from the backend.
+    Delete,
+}
+
+/// The result of a garbage collection run.
+#[derive(Clone, Debug, Default)]
+pub struct GcReport {
+    /// The number of objects that are reachable from the current
+    /// root.
+    pub live: usize,
+
+    /// Objects in the backend that are not reachable from the current
+    /// root.
+    ///
+    /// In [`GcMode::Delete`] mode, these objects have been removed
+    /// from the backend.
+    pub unreferenced: Vec<ObjectId>,
+}
+
+impl<I: Index, CustomData> Infinitree<I, CustomData>
+where
+    CustomData: Serialize + DeserializeOwned + Send + Sync,
+{
+    /// Find, and optionally delete, all objects in the backend that
+    /// are not referenced by any commit in the tree.
+    ///
+    /// The backups of the root are kept, along with everything they
+    /// refer to, so history that has been pruned recently is only
+    /// released after a few more commits.
+    ///
+    /// Every version of every field in the index will be read in
+    /// order to discover the references, so this may be an expensive
+    /// operation on large trees.
+    ///
+    /// The backend needs to support
+    /// [`list_objects`](crate::Backend::list_objects).
+    ///
+    /// # Caveats
+    ///
+    /// Garbage collection assumes that the backend exclusively
+    /// stores objects of this tree. Objects written through a
+    /// [`storage_writer`](Self::storage_writer) are only retained if
+    /// a committed index field references them, which means data
+    /// that has been written, but not yet committed, _will_ be
+    /// deleted.
+    ///
+    /// Returns an error if the transaction log references a field that
+    /// is not part of the `Index`, or a value of a
+    /// [`SparseField`](crate::fields::SparseField) can't be read, as
+    /// we can't safely determine what objects they need.
+    ///
+    /// In [`GcMode::Delete`] mode, nothing is deleted and a
+    /// [`ConflictError`](super::ConflictError) is returned if another
+    /// writer has committed to the tree since it was opened or
+    /// refreshed, as their commits may need the objects that look
+    /// unreferenced.
+    pub fn gc(&self, mode: GcMode) -> Result<GcReport> {
+        if let GcMode::Delete = mode {
+            self.ensure_writable()?;
+        }
+
+        // commits of this process wait until we're done, so the live
+        // set can't go stale under us
+        let root_head = self.root.root_head.read();
+        let live = self.live_objects()?;
+
+        let mut unreferenced = vec![];
+        for object in self.backend.list_objects()? {
+            let object = object?;
+            if !live.contains(&object.id) {
+                unreferenced.push(object.id);
+            }
+        }
+
+        if let GcMode::Delete = mode {
+            match sealed_root::check_unchanged(
+                &self.root,
+                root_head.as_ref(),
+                self.backend.as_ref(),
+            ) {
+                Err(sealed_root::Error::Conflict { source }) => return Err(source.into()),
+                result => result?,
+            }
+
+            self.backend.delete(&unreferenced)?;
+            self.backend.sync()?;
+        }
+
+        Ok(GcReport {
+            live: live.len(),
+            unreferenced,
+        })
+    }
+
+    /// Collect the ids of all objects that are reachable from the
+    /// root of the tree, or its backups.
+    pub(super) fn live_objects(&self) -> Result<HashSet<ObjectId>> {
+        let mut live = HashSet::new();
+        live.insert(self.root.key.root_object_id()?);
+        live.extend(self.root.objects());
+
+        let transaction_log = self.root.transaction_log.read().clone();
+        live.extend(self.referenced_objects(&transaction_log)?);
+        live.extend(self.backup_objects()?);
+
+        Ok(live)
+    }
+
+    /// Collect the ids of the backups of the root, and the objects
+    /// they need, so the tree can still be recovered from them.
+    ///
+    /// A backup can be a few commits behind, and still refer to
+    /// history that has been pruned since.
+    fn backup_objects(&self) -> Result<HashSet<ObjectId>> {
+        let commits = self
+            .commit_list()
+            .iter()
+            .map(|c| c.id)
+            .collect::<HashSet<_>>();
+
+        let mut objects = HashSet::new();
+        for id in sealed_root::backup_root_ids(&self.root.key)? {
+            let backup = match sealed_root::open_at::<CustomData>(
+                id,
+                BlockBuffer::default(),
+                self.backend.clone(),
+                self.root.key.clone(),
+            ) {
+                Ok(backup) => backup,
+                // a backup that can't be opened doesn't need anything
+                Err(error) if sealed_root::is_damaged(&error) => continue,
+                Err(error) => return Err(error.into()),
+            };
+            objects.insert(id);
+            objects.extend(backup.objects());
+
+            let pruned = backup
+                .transaction_log
+                .read()
+                .iter()
+                .filter(|(commit, _, _)| !commits.contains(commit))
+                .cloned()
+                .collect::<TransactionList>();
+            objects.extend(self.referenced_objects(&pruned)?);
+        }
+
+        Ok(objects)
+    }
+
+    /// Collect the ids of all objects that the transactions in
+    /// `transaction_log` are stored in, or their records refer to.
+    pub(super) fn referenced_objects(
+        &self,
+        transaction_log: &TransactionList,
+    ) -> Result<HashSet<ObjectId>> {
+        let mut referenced = transaction_log
+            .iter()
+            .flat_map(|(_, _, stream)| stream.objects())
+            .collect::<HashSet<_>>();
+
+        let fields = self.index.read().walk_all()?;
+
+        for (_, name, _) in transaction_log.iter() {
+            if !fields.iter().any(|f| &f.name == name) {
+                bail!("unknown field in the transaction log: {name}");
+            }
+        }
+
+        for mut field in fields {
+            let transactions = transaction_log
+                .iter()
+                .filter(|(_, name, _)| name == &field.name)
+                .cloned()
+                .collect::<Vec<_>>();
+
+            field
+                .strategy
+                .walk(self.reader_pool.clone(), transactions, true, &mut |ptr| {
+                    referenced.insert(*ptr.object_id());
+                })?;
+        }
+
+        Ok(referenced)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::GcMode;
+    use crate::{
+        backends::test::InMemoryBackend,
+        crypto::UsernamePassword,
+        fields::{Serialized, VersionedMap},
+        object::{Reader, WriteObject, Writer},
+        tree::{
+            sealed_root::{backup_root_ids, BACKUP_ROOTS, CHECKPOINT_SIZE},
+            ConflictError,
+        },
+        Backend, ChunkPointer, Index, Infinitree, BLOCK_SIZE,
+    };
+
+    #[derive(Index, Default)]
+    #[infinitree(walk, squash)]
+    struct Files {
+        files: VersionedMap<String, ChunkPointer>,
+        counter: Serialized<usize>,
+    }
+
+    fn key() -> UsernamePassword {
+        UsernamePassword::with_credentials("gc_user".to_string(), "gc_password".to_string())
+            .unwrap()
+    }
+
+    #[test]
+    fn delete_unreferenced_objects() {
+        let backend = InMemoryBackend::shared();
+
+        let (kept, orphan) = {
+            let tree = Infinitree::<Files>::empty(backend.clone(), key()).unwrap();
+
+            let mut writer = tree.storage_writer().unwrap();
+            let kept = writer.write(b"referenced").unwrap();
+            writer.flush().unwrap();
+
+            let mut writer = tree.storage_writer().unwrap();
+            let orphan = writer.write(b"forgotten").unwrap();
+            writer.flush().unwrap();
+
+            tree.index().files.insert("kept".into(), kept.clone());
+            *tree.index().counter.write() = 1;
+            tree.commit(None).unwrap();
+
+            *tree.index().counter.write() = 2;
+            tree.commit(None).unwrap();
+
+            (kept, orphan)
+        };
+
+        let tree = Infinitree::<Files>::open(backend.clone(), key()).unwrap();
+
+        let report = tree.gc(GcMode::DryRun).unwrap();
+        assert_eq!(report.unreferenced, vec![*orphan.object_id()]);
+        assert!(backend.read_object(orphan.object_id()).is_ok());
+
+        let report = tree.gc(GcMode::Delete).unwrap();
+        assert_eq!(report.unreferenced, vec![*orphan.object_id()]);
+        assert!(backend.read_object(orphan.object_id()).is_err());
+        assert!(backend.read_object(kept.object_id()).is_ok());
+        drop(tree);
+
+        let tree = Infinitree::<Files>::open(backend, key()).unwrap();
+        tree.load_all().unwrap();
+        assert_eq!(*tree.index().counter.read(), 2);
+
+        let mut buf = vec![0; BLOCK_SIZE];
+        let ptr = tree.index().files.get("kept").unwrap();
+        let mut reader = tree.storage_reader().unwrap();
+        assert_eq!(reader.read_chunk(&ptr, &mut buf).unwrap(), b"referenced");
+
+        let report = tree.gc(GcMode::DryRun).unwrap();
+        assert!(report.unreferenced.is_empty());
+    }
+
+    #[test]
+    fn keep_objects_of_concurrent_commits() {
+        let backend = InMemoryBackend::shared();
+        let tree = Infinitree::<Files>::empty(backend.clone(), key()).unwrap();
+        *tree.index().counter.write() = 1;
+        tree.commit(None).unwrap();
+
+        let collector = Infinitree::<Files>::open(backend.clone(), key()).unwrap();
+
+        let mut writer = tree.storage_writer().unwrap();
+        let ptr = writer.write(b"concurrent").unwrap();
+        writer.flush().unwrap();
+        tree.index().files.insert("new".into(), ptr.clone());
+        tree.commit(None).unwrap();
+
+        // the collector hasn't seen the new commit, so it can't tell
+        // what it needs
+        let err = collector.gc(GcMode::Delete).unwrap_err();
+        assert!(err.downcast_ref::<ConflictError>().is_some());
+        assert!(backend.read_object(ptr.object_id()).is_ok());
+        drop(collector);
+
+        let collector = Infinitree::<Files>::open(backend.clone(), key()).unwrap();
+        collector.gc(GcMode::Delete).unwrap();
+        assert!(backend.read_object(ptr.object_id()).is_ok());
+    }
+
+    #[test]
+    fn backups_survive_pruning() {
+        let backend = InMemoryBackend::shared();
+        let tree = Infinitree::<Files>::empty(backend.clone(), key()).unwrap();
+
+        // fill a few checkpoints, which are dropped by pruning
+        let message = "x".repeat(CHECKPOINT_SIZE / 4 + 1);
+        for i in 0..9 {
+            *tree.index().counter.write() = i;
+            tree.commit(message.clone()).unwrap();
+        }
+        let last = tree.commit_list().last().unwrap().id;
+
+        tree.prune(|_| false).unwrap();
+        tree.gc(GcMode::Delete).unwrap();
+
+        // recover from the backup that was written before pruning
+        let root = tree.root.key.root_object_id().unwrap();
+        let newest = *tree.root.generation.read() as usize % BACKUP_ROOTS;
+        let backups = backup_root_ids(&tree.root.key).unwrap();
+        drop(tree);
+
+        let mut damaged = WriteObject::default();
+        damaged.set_id(root);
+        backend.write_object(&damaged).unwrap();
+        backend.delete(&[backups[newest]]).unwrap();
+
+        let tree = Infinitree::<Files>::open(backend, key()).unwrap();
+        assert_eq!(tree.recovered_root().unwrap().commit, Some(last));
+        assert_eq!(tree.commit_list().len(), 9);
+        tree.load_all().unwrap();
+        assert_eq!(*tree.index().counter.read(), 8);
+    }
+}
@@ -0,0 +1,186 @@
+use super::{Commit, CommitMetadata, Infinitree};
+use crate::{index::Index, object::Stream};
+use anyhow::Result;
+use serde::{de::DeserializeOwned, Serialize};
+
+/// Called before a commit is recorded, with its metadata and the
+/// names of the fields that it changes.
+///
+/// Returning an error vetoes the commit.
+pub type PreCommitHook<CustomData> =
+    Box<dyn Fn(&CommitMetadata<CustomData>, &[String]) -> Result<()> + Send + Sync>;
+
+/// Called after a commit has been persisted, with the commit and the
+/// stream written for each changed field.
+pub type PostCommitHook<CustomData> =
+    Box<dyn Fn(&Commit<CustomData>, &[(String, Stream)]) + Send + Sync>;
+
+/// The hooks registered on a tree.
+pub(super) struct Hooks<CustomData>
+where
+    CustomData: Serialize,
+{
+    pre_commit: Vec<PreCommitHook<CustomData>>,
+    post_commit: Vec<PostCommitHook<CustomData>>,
+}
+
+impl<CustomData> Default for Hooks<CustomData>
+where
+    CustomData: Serialize,
+{
+    fn default() -> Self {
+        Self {
+            pre_commit: vec![],
+            post_commit: vec![],
+        }
+    }
+}
+
+impl<CustomData> Hooks<CustomData>
+where
+    CustomData: Serialize,
+{
+    /// Run the pre-commit hooks in the order they were added, and
+    /// stop at the first one that vetoes the commit.
+    pub(super) fn pre_commit(
+        &self,
+        metadata: &CommitMetadata<CustomData>,
+        changeset: &[(String, Stream)],
+    ) -> Result<()> {
+        if self.pre_commit.is_empty() {
+            return Ok(());
+        }
+
+        let fields = changeset
+            .iter()
+            .filter(|(_, stream)| !stream.is_empty())
+            .map(|(field, _)| field.clone())
+            .collect::<Vec<_>>();
+
+        for hook in self.pre_commit.iter() {
+            hook(metadata, &fields)?;
+        }
+
+        Ok(())
+    }
+
+    /// Run the post-commit hooks in the order they were added.
+    pub(super) fn post_commit(&self, commit: &Commit<CustomData>, changeset: &[(String, Stream)]) {
+        for hook in self.post_commit.iter() {
+            hook(commit, changeset);
+        }
+    }
+}
+
+impl<I: Index, CustomData> Infinitree<I, CustomData>
+where
+    CustomData: Serialize + DeserializeOwned + Send + Sync,
+{
+    /// Call `hook` before every commit is recorded, including merge
+    /// commits.
+    ///
+    /// The hook receives the metadata of the commit, and the names of
+    /// the fields that have changed. If it returns an error, the
+    /// commit is not recorded and the error is returned to the
+    /// caller.
+    ///
+    /// The changes have already been moved out of the index at this
+    /// point, so after a veto they are added to the next commit, just
+    /// like after a [`ConflictError`](super::ConflictError). The
+    /// write-ahead log keeps them too, until they're committed.
+    ///
+    /// Hooks may be called while the tree is locked, so they must
+    /// not call back into the tree.
+    pub fn add_pre_commit_hook(
+        &self,
+        hook: impl Fn(&CommitMetadata<CustomData>, &[String]) -> Result<()> + Send + Sync + 'static,
+    ) {
+        self.hooks.write().pre_commit.push(Box::new(hook));
+    }
+
+    /// Call `hook` after every commit has been persisted, including
+    /// merge commits.
+    ///
+    /// The hook receives the new commit, and the stream that has been
+    /// written for each field.
+    ///
+    /// Hooks may be called while the tree is locked, so they must
+    /// not call back into the tree.
+    pub fn add_post_commit_hook(
+        &self,
+        hook: impl Fn(&Commit<CustomData>, &[(String, Stream)]) + Send + Sync + 'static,
+    ) {
+        self.hooks.write().post_commit.push(Box::new(hook));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        backends::test::InMemoryBackend, crypto::UsernamePassword, fields::VersionedMap, Index,
+        Infinitree,
+    };
+    use anyhow::bail;
+    use parking_lot::Mutex;
+    use std::sync::Arc;
+
+    #[derive(Index, Default)]
+    struct Counters {
+        map: VersionedMap<usize, usize>,
+        other: VersionedMap<usize, usize>,
+    }
+
+    fn key() -> UsernamePassword {
+        UsernamePassword::with_credentials("hooks_user".to_string(), "hooks_password".to_string())
+            .unwrap()
+    }
+
+    #[test]
+    fn pre_and_post_commit() {
+        let backend = InMemoryBackend::shared();
+        let tree = Infinitree::<Counters>::empty(backend.clone(), key()).unwrap();
+
+        let changed = Arc::new(Mutex::new(vec![]));
+        tree.add_pre_commit_hook({
+            let changed = changed.clone();
+            move |metadata, fields| {
+                if metadata.message.as_deref() == Some("veto") {
+                    bail!("vetoed");
+                }
+
+                changed.lock().push(fields.to_vec());
+                Ok(())
+            }
+        });
+
+        let committed = Arc::new(Mutex::new(vec![]));
+        tree.add_post_commit_hook({
+            let committed = committed.clone();
+            move |commit, streams| committed.lock().push((commit.id, streams.len()))
+        });
+
+        tree.index().map.insert(1, 1);
+        let commit = tree.commit("first").unwrap().unwrap();
+        assert_eq!(*changed.lock(), vec![vec!["map".to_string()]]);
+        assert_eq!(*committed.lock(), vec![(commit.id, 2)]);
+
+        tree.index().other.insert(1, 1);
+        assert!(tree.commit("veto").is_err());
+        assert_eq!(tree.commit_list().len(), 1);
+        assert_eq!(committed.lock().len(), 1);
+
+        // the vetoed changes are part of the next commit
+        tree.index().map.insert(2, 2);
+        tree.commit("second").unwrap();
+        assert_eq!(
+            changed.lock()[1],
+            vec!["map".to_string(), "other".to_string()]
+        );
+        drop(tree);
+
+        let tree = Infinitree::<Counters>::open(backend, key()).unwrap();
+        tree.load_all().unwrap();
+        assert_eq!(tree.index().map.len(), 2);
+        assert_eq!(*tree.index().other.get(&1).unwrap(), 1);
+    }
+}
@@ -0,0 +1,597 @@
+use super::{Commit, CommitId, CommitMetadata, Infinitree, Message, WriteAheadLog};
+use crate::{
+    fields::{Conflict, Resolution},
+    index::{Index, TransactionList},
+    object::BufferedSink,
+};
+use anyhow::{bail, Context, Result};
+use serde::{de::DeserializeOwned, Serialize};
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    sync::Arc,
+    time::SystemTime,
+};
+
+/// Decides which change to keep when both sides of a merge changed
+/// the same part of a field.
+///
+/// This is implemented for closures of the form `FnMut(&str,
+/// &Conflict) -> Resolution`, which receive the name of the field as
+/// the first argument.
+pub trait Resolver {
+    /// Resolve `conflict` in `field`.
+    fn resolve(&mut self, field: &str, conflict: &Conflict<'_>) -> Resolution;
+}
+
+impl<F> Resolver for F
+where
+    F: FnMut(&str, &Conflict<'_>) -> Resolution,
+{
+    fn resolve(&mut self, field: &str, conflict: &Conflict<'_>) -> Resolution {
+        self(field, conflict)
+    }
+}
+
+impl<I: Index, CustomData> Infinitree<I, CustomData>
+where
+    CustomData: Serialize + DeserializeOwned + Send + Sync + Default,
+{
+    /// Merge the changes of `theirs` into the head of the tree, and
+    /// record a merge commit with `message`.
+    ///
+    /// The head is the head of the checked out branch, or the head of
+    /// the main line if there's no branch checked out.
+    ///
+    /// For full documentation, please read
+    /// [`Infinitree::merge_with_metadata`].
+    pub fn merge(
+        &self,
+        theirs: CommitId,
+        message: impl Into<Message>,
+        resolver: impl Resolver,
+    ) -> Result<Option<Arc<Commit<CustomData>>>> {
+        let metadata = CommitMetadata {
+            time: SystemTime::now(),
+            message: message.into().into(),
+            previous: Some(self.head().context("no commit to merge into")?),
+            merged: vec![theirs],
+            ..Default::default()
+        };
+
+        self.merge_with_metadata(metadata, resolver)
+    }
+
+    /// Merge the history of `other`, a replica of this tree in
+    /// another backend, into the head of the tree, and record a merge
+    /// commit with `message`.
+    ///
+    /// The commits of `other` that this tree doesn't have are added
+    /// to the history, and the objects they need are copied from the
+    /// backend of `other`. Then the last commit of `other` is merged
+    /// into the head, just like [`merge`](Self::merge) does.
+    ///
+    /// Both trees need to be opened with the same key. `other` is not
+    /// changed, so [replicate](Self::replicate) the merged tree to
+    /// bring it up to date.
+    ///
+    /// Returns `None` if there's nothing to merge. If the merge
+    /// fails, the history of the tree is left unchanged, but the
+    /// copied objects are not removed.
+    pub fn merge_from(
+        &self,
+        other: &Infinitree<I, CustomData>,
+        message: impl Into<Message>,
+        resolver: impl Resolver,
+    ) -> Result<Option<Arc<Commit<CustomData>>>> {
+        if std::ptr::eq(self, other) {
+            return Ok(None);
+        }
+        if self.root.key.root_object_id()? != other.root.key.root_object_id()? {
+            bail!("the trees are not opened with the same key");
+        }
+
+        // lock the index to keep new commits from interleaving
+        let wal = self.wal.lock();
+        let index = self.index.write();
+
+        let ours = self.head().context("no commit to merge into")?;
+        let Some(theirs) = other.commit_list().last().map(|c| c.id) else {
+            return Ok(None);
+        };
+
+        let (commits, transactions) = {
+            let known = self
+                .commit_list()
+                .iter()
+                .map(|c| c.id)
+                .collect::<HashSet<_>>();
+            let commits = other
+                .commit_list()
+                .iter()
+                .filter(|c| !known.contains(&c.id))
+                .cloned()
+                .collect::<Vec<_>>();
+            let transactions = other
+                .root
+                .transaction_log
+                .read()
+                .iter()
+                .filter(|(id, _, _)| !known.contains(id))
+                .cloned()
+                .collect::<TransactionList>();
+
+            (commits, transactions)
+        };
+
+        // the data needs to be in place before the commits refer to it
+        let objects = other.referenced_objects(&transactions)?;
+        other.copy_missing(self.backend.as_ref(), objects, &mut Default::default())?;
+        self.backend.sync()?;
+
+        // their commits are newer than anything we have, so they go
+        // on top of the log
+        let imported = commits.iter().map(|c| c.id).collect::<HashSet<_>>();
+        {
+            let mut tr_log = self.root.transaction_log.write();
+            let history = std::mem::replace(&mut *tr_log, transactions);
+            tr_log.extend(history);
+            self.root.commit_list.write().extend(commits);
+        }
+
+        let metadata = CommitMetadata {
+            time: SystemTime::now(),
+            message: message.into().into(),
+            previous: Some(ours),
+            merged: vec![theirs],
+            ..Default::default()
+        };
+
+        let merged = self.merge_locked(wal.as_deref(), &index, metadata, resolver);
+        if !matches!(merged, Ok(Some(_))) {
+            self.root
+                .transaction_log
+                .write()
+                .retain(|(id, _, _)| !imported.contains(id));
+            self.root
+                .commit_list
+                .write()
+                .retain(|c| !imported.contains(&c.id));
+        }
+
+        merged
+    }
+}
+
+impl<I: Index, CustomData> Infinitree<I, CustomData>
+where
+    CustomData: Serialize + DeserializeOwned + Send + Sync,
+{
+    /// Merge two diverged histories using manually prepared metadata.
+    ///
+    /// The commit in [`previous`](CommitMetadata::previous) ("ours")
+    /// is merged with the single commit in
+    /// [`merged`](CommitMetadata::merged) ("theirs"), starting from
+    /// their closest common ancestor.
+    ///
+    /// Incremental fields, such as
+    /// [`VersionedMap`](crate::fields::VersionedMap) and
+    /// [`LinkedList`](crate::fields::LinkedList), are merged record by
+    /// record, while snapshot fields are merged as a whole. If both
+    /// sides changed the same key to different values, `resolver`
+    /// decides which one to keep, and the same goes for snapshot fields
+    /// that both sides changed. Collections without unique keys, like
+    /// `LinkedList`, keep the items added on both sides, even if
+    /// they're equal, but items that were already there at the common
+    /// ancestor are not added again.
+    ///
+    /// The merge commit contains the changes of theirs that need to
+    /// be applied on top of ours, and the index in memory is not
+    /// changed. Reload the index to work with the merged state.
+    ///
+    /// Returns `None` if theirs has already been merged, or an error
+    /// if either commit is not found.
+    pub fn merge_with_metadata(
+        &self,
+        metadata: CommitMetadata<CustomData>,
+        resolver: impl Resolver,
+    ) -> Result<Option<Arc<Commit<CustomData>>>> {
+        // lock the index to keep new commits from interleaving
+        let wal = self.wal.lock();
+        let index = self.index.write();
+        self.merge_locked(wal.as_deref(), &index, metadata, resolver)
+    }
+
+    /// Merge the commits in `metadata` while the log and the index are
+    /// locked.
+    fn merge_locked(
+        &self,
+        wal: Option<&WriteAheadLog>,
+        index: &I,
+        metadata: CommitMetadata<CustomData>,
+        mut resolver: impl Resolver,
+    ) -> Result<Option<Arc<Commit<CustomData>>>> {
+        let (ours, theirs) = match (metadata.previous, metadata.merged.as_slice()) {
+            (Some(ours), [theirs]) => (ours, *theirs),
+            _ => bail!("merging needs exactly one commit on each side"),
+        };
+
+        let (base, ours, theirs) = {
+            let commits = self.commit_list();
+            let graph = commits.iter().map(|c| (c.id, c)).collect::<HashMap<_, _>>();
+
+            let ours_ancestors = ancestors(&graph, ours).context("commit not found")?;
+            if ours_ancestors.contains(&theirs) {
+                return Ok(None);
+            }
+
+            let base = merge_base(&graph, &ours_ancestors, theirs).context("commit not found")?;
+            let base_ancestors = match base {
+                Some(base) => ancestors(&graph, base).context("commit not found")?,
+                None => HashSet::new(),
+            };
+
+            (
+                first_parents(&graph, base, &HashSet::new()),
+                first_parents(&graph, Some(ours), &base_ancestors),
+                first_parents(&graph, Some(theirs), &base_ancestors),
+            )
+        };
+
+        let tr_log = self.root.transaction_log.read().clone();
+        let fields = index.merge_all()?;
+        for (_, name, _) in tr_log.iter() {
+            if !fields.iter().any(|f| &f.name == name) {
+                bail!("unknown field in the transaction log: {name}");
+            }
+        }
+
+        let mut sink = BufferedSink::new(self.chunk_writer()?);
+        let mut changeset = vec![];
+        for mut field in fields {
+            let select = |commits: &HashSet<CommitId>| {
+                tr_log
+                    .iter()
+                    .filter(|(id, name, _)| name == &field.name && commits.contains(id))
+                    .cloned()
+                    .collect::<TransactionList>()
+            };
+            let (base, ours, theirs) = (select(&base), select(&ours), select(&theirs));
+
+            let changed = field.strategy.merge(
+                self.reader_pool.clone(),
+                base,
+                ours,
+                theirs,
+                &mut |conflict| resolver.resolve(&field.name, conflict),
+                &mut sink,
+            )?;
+
+            let stream = sink.clear()?;
+            if changed {
+                changeset.push((field.name, stream));
+            }
+        }
+        sink.finish()?;
+
+        let metadata = metadata.rounded()?;
+        let id = self.commit_id(&metadata, &changeset)?;
+        self.hooks.read().pre_commit(&metadata, &changeset)?;
+        self.record_commit(id, metadata, changeset)?;
+
+        // the logged changes weren't part of the commit, so they're kept
+        self.wal_restart(wal, index);
+
+        Ok(self.last_commit())
+    }
+}
+
+pub(super) type Graph<'a, CustomData> = HashMap<CommitId, &'a Arc<Commit<CustomData>>>;
+
+/// All commits reachable from `id` through any parent, including
+/// `id` itself.
+///
+/// Returns `None` if `id` is not in the graph.
+pub(super) fn ancestors<CustomData: Serialize>(
+    graph: &Graph<'_, CustomData>,
+    id: CommitId,
+) -> Option<HashSet<CommitId>> {
+    graph.get(&id)?;
+
+    let mut seen = HashSet::from([id]);
+    let mut queue = VecDeque::from([id]);
+    while let Some(current) = queue.pop_front() {
+        let Some(commit) = graph.get(&current) else {
+            continue;
+        };
+
+        for parent in commit.metadata.parents() {
+            if seen.insert(*parent) {
+                queue.push_back(*parent);
+            }
+        }
+    }
+
+    Some(seen)
+}
+
+/// The closest ancestor of `theirs` that is also in `ours`, if any.
+///
+/// Returns `None` if `theirs` is not in the graph.
+fn merge_base<CustomData: Serialize>(
+    graph: &Graph<'_, CustomData>,
+    ours: &HashSet<CommitId>,
+    theirs: CommitId,
+) -> Option<Option<CommitId>> {
+    graph.get(&theirs)?;
+
+    let mut seen = HashSet::from([theirs]);
+    let mut queue = VecDeque::from([theirs]);
+    while let Some(current) = queue.pop_front() {
+        if ours.contains(&current) {
+            return Some(Some(current));
+        }
+
+        let Some(commit) = graph.get(&current) else {
+            continue;
+        };
+
+        for parent in commit.metadata.parents() {
+            if seen.insert(*parent) {
+                queue.push_back(*parent);
+            }
+        }
+    }
+
+    Some(None)
+}
+
+/// Follow the `previous` links from `start` until reaching a commit in
+/// `stop`.
+///
+/// Merge commits contain the changes they merged, so these are the
+/// commits that need loading to restore the state of `start`.
+pub(super) fn first_parents<CustomData: Serialize>(
+    graph: &Graph<'_, CustomData>,
+    start: Option<CommitId>,
+    stop: &HashSet<CommitId>,
+) -> HashSet<CommitId> {
+    let mut commits = HashSet::new();
+    let mut next = start;
+
+    while let Some(id) = next {
+        if stop.contains(&id) || !commits.insert(id) {
+            break;
+        }
+
+        next = graph.get(&id).and_then(|c| c.metadata.previous);
+    }
+
+    commits
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        backends::test::InMemoryBackend,
+        crypto::UsernamePassword,
+        fields::{Conflict, LinkedList, List, Resolution, Serialized, VersionedMap},
+        tree::{CommitFilter, VerifyMode},
+        Index, Infinitree,
+    };
+    use std::sync::Arc;
+
+    #[derive(Index, Default)]
+    #[infinitree(walk, merge)]
+    struct State {
+        map: VersionedMap<usize, usize>,
+        log: LinkedList<usize>,
+        last: Serialized<usize>,
+        tags: List<usize>,
+    }
+
+    fn key() -> UsernamePassword {
+        UsernamePassword::with_credentials("merge_user".to_string(), "merge_password".to_string())
+            .unwrap()
+    }
+
+    type Item = (usize, Option<Arc<usize>>);
+
+    #[test]
+    fn merge_branches() {
+        let backend = InMemoryBackend::shared();
+
+        {
+            let tree = Infinitree::<State>::empty(backend.clone(), key()).unwrap();
+            for i in 1..=3 {
+                tree.index().map.insert(i, i);
+            }
+            tree.index().log.push(0);
+            tree.index().tags.write().push(0);
+            let base = tree.commit("base").unwrap().unwrap().id;
+            tree.create_branch("main", base).unwrap();
+            tree.create_branch("feature", base).unwrap();
+        }
+
+        // each branch is changed by a writer that loaded its state
+        let writer = |branch: &str| {
+            let tree = Infinitree::<State>::open(backend.clone(), key()).unwrap();
+            tree.checkout(Some(branch.into()));
+            tree.load_all().unwrap();
+            tree
+        };
+
+        {
+            let tree = writer("main");
+            tree.index().map.update_with(1, |_| 10);
+            tree.index().map.remove(3);
+            tree.index().map.insert(4, 4);
+            tree.index().log.push(1);
+            tree.index().log.push(6);
+            *tree.index().last.write() = 1;
+            tree.index().tags.write().push(1);
+            tree.commit("main").unwrap();
+        }
+
+        {
+            let tree = writer("feature");
+            tree.index().map.update_with(1, |_| 11);
+            tree.index().map.update_with(2, |_| 20);
+            tree.index().map.insert(5, 5);
+            tree.index().log.push(2);
+            tree.index().log.push(6);
+            *tree.index().last.write() = 2;
+            tree.index().tags.write().push(2);
+            tree.commit("feature").unwrap();
+        }
+
+        let tree = Infinitree::<State>::open(backend.clone(), key()).unwrap();
+        let (main, feature) = {
+            let refs = tree.refs();
+            (refs.branches["main"], refs.branches["feature"])
+        };
+
+        tree.checkout(Some("main".into()));
+        let mut conflicts = vec![];
+        let merge = tree
+            .merge(feature, "merge", |field: &str, conflict: &Conflict<'_>| {
+                conflicts.push(field.to_string());
+                match field {
+                    "map" => {
+                        assert_eq!(conflict.key::<usize>(), Some(&1));
+                        assert_eq!(conflict.base::<Item>(), Some(&(1, Some(1.into()))));
+                        assert_eq!(conflict.ours::<Item>(), Some(&(1, Some(10.into()))));
+                        assert_eq!(conflict.theirs::<Item>(), Some(&(1, Some(11.into()))));
+                        Resolution::Theirs
+                    }
+                    "tags" => {
+                        assert_eq!(conflict.base::<Vec<usize>>(), Some(&vec![0]));
+                        assert_eq!(conflict.ours::<Vec<usize>>(), Some(&vec![0, 1]));
+                        assert_eq!(conflict.theirs::<Vec<usize>>(), Some(&vec![0, 2]));
+                        Resolution::Theirs
+                    }
+                    _ => {
+                        assert_eq!(conflict.ours::<usize>(), Some(&1));
+                        assert_eq!(conflict.theirs::<usize>(), Some(&2));
+                        Resolution::Ours
+                    }
+                }
+            })
+            .unwrap()
+            .unwrap();
+
+        conflicts.sort();
+        assert_eq!(conflicts, vec!["last", "map", "tags"]);
+        assert_eq!(merge.metadata.previous, Some(main));
+        assert_eq!(merge.metadata.merged, vec![feature]);
+        assert_eq!(tree.refs().branches["main"], merge.id);
+
+        // merging again is a no-op
+        assert!(tree
+            .merge(feature, "again", |_: &str, _: &Conflict<'_>| {
+                Resolution::Ours
+            })
+            .unwrap()
+            .is_none());
+        drop(tree);
+
+        let tree = Infinitree::<State>::open(backend.clone(), key()).unwrap();
+        tree.filter_commits(CommitFilter::Branch("main".into()));
+        tree.load_all().unwrap();
+
+        let index = tree.index();
+        assert_eq!(index.map.get(&1), Some(11.into()));
+        assert_eq!(index.map.get(&2), Some(20.into()));
+        assert_eq!(index.map.get(&3), None);
+        assert_eq!(index.map.get(&4), Some(4.into()));
+        assert_eq!(index.map.get(&5), Some(5.into()));
+        assert_eq!(*index.last.read(), 1);
+        assert_eq!(*index.tags.read(), vec![0, 2]);
+
+        // items from both sides are there, even if they're equal, but
+        // the ones from the common ancestor aren't added again
+        let log = index.log.iter().map(|v| *v).collect::<Vec<_>>();
+        let count = |n| log.iter().filter(|v| **v == n).count();
+        assert_eq!(count(1), 1);
+        assert_eq!(count(2), 1);
+        assert_eq!(count(6), 2);
+        let loaded = log.len();
+        drop(index);
+
+        let main = Infinitree::<State>::open(backend, key()).unwrap();
+        main.filter_commits(CommitFilter::Single(main.refs().branches["main"]));
+        main.load_all().unwrap();
+        let merged = main.index().log.iter().map(|v| *v).collect::<Vec<_>>();
+        assert_eq!(merged, vec![2, 6]);
+        assert!(loaded > merged.len());
+    }
+
+    #[test]
+    fn merge_diverged_replicas() {
+        let local = InMemoryBackend::shared();
+        let remote = InMemoryBackend::shared();
+
+        {
+            let tree = Infinitree::<State>::empty(local.clone(), key()).unwrap();
+            tree.index().map.insert(1, 1);
+            tree.index().log.push(0);
+            let base = tree.commit("base").unwrap().unwrap().id;
+            tree.create_branch("main", base).unwrap();
+            tree.replicate(remote.clone()).unwrap();
+
+            tree.checkout(Some("main".into()));
+
+            tree.index().map.insert(2, 2);
+            tree.index().log.push(1);
+            tree.commit("local").unwrap();
+        }
+
+        let theirs = {
+            let tree = Infinitree::<State>::open(remote.clone(), key()).unwrap();
+            tree.checkout(Some("main".into()));
+            tree.index().map.insert(3, 3);
+            tree.index().log.push(2);
+            tree.commit("remote").unwrap().unwrap().id
+        };
+
+        let tree = Infinitree::<State>::open(local.clone(), key()).unwrap();
+        tree.checkout(Some("main".into()));
+        let ours = tree.head().unwrap();
+        let other = Infinitree::<State>::open(remote, key()).unwrap();
+
+        let merge = tree
+            .merge_from(&other, "merge", |_: &str, _: &Conflict<'_>| {
+                Resolution::Ours
+            })
+            .unwrap()
+            .unwrap();
+        assert_eq!(merge.metadata.previous, Some(ours));
+        assert_eq!(merge.metadata.merged, vec![theirs]);
+        assert_eq!(tree.refs().branches["main"], merge.id);
+
+        // merging again is a no-op
+        assert!(tree
+            .merge_from(&other, "again", |_: &str, _: &Conflict<'_>| {
+                Resolution::Ours
+            })
+            .unwrap()
+            .is_none());
+        drop(tree);
+
+        let tree = Infinitree::<State>::open(local, key()).unwrap();
+        assert!(tree.verify(VerifyMode::Full).unwrap().is_ok());
+        assert_eq!(tree.commit_list().len(), 4);
+        tree.filter_commits(CommitFilter::Branch("main".into()));
+        tree.load_all().unwrap();
+
+        let index = tree.index();
+        for i in 1..=3 {
+            assert_eq!(index.map.get(&i), Some(i.into()));
+        }
+
+        // items from both sides are there, but only once
+        let log = index.log.iter().map(|v| *v).collect::<Vec<_>>();
+        let count = |n| log.iter().filter(|v| **v == n).count();
+        assert_eq!(count(1), 1);
+        assert_eq!(count(2), 1);
+    }
+}
@@ -0,0 +1,462 @@
+use super::{Commit, CommitId, CommitList, CommitMetadata, Infinitree};
+use crate::{
+    index::{Field, Index, TransactionList},
+    object::{BufferedSink, Stream},
+};
+use anyhow::{bail, Context, Result};
+use serde::{de::DeserializeOwned, Serialize};
+use std::{
+    collections::{HashMap, HashSet},
+    mem::replace,
+    sync::Arc,
+};
+
+/// Summary of a history rewrite.
+#[derive(Clone, Debug, Default)]
+pub struct PruneReport {
+    /// Commits that have been dropped from the history.
+    pub removed: Vec<CommitId>,
+
+    /// Commits that have been kept, but received a new id, as `(old,
+    /// new)` pairs.
+    ///
+    /// A commit is rewritten if any commits before it have been
+    /// removed, as its [`previous`](CommitMetadata::previous) link
+    /// changes.
+    pub rewritten: Vec<(CommitId, CommitId)>,
+}
+
+impl<I: Index, CustomData> Infinitree<I, CustomData>
+where
+    CustomData: Serialize + DeserializeOwned + Send + Sync,
+{
+    /// Only keep the last `count` commits in the history.
+    ///
+    /// The last commit is always kept, even if `count` is 0.
+    ///
+    /// For full documentation, please read [`Infinitree::prune`].
+    pub fn keep_last(&self, count: usize) -> Result<PruneReport> {
+        let keep = {
+            let commits = self.commit_list();
+            commits
+                .iter()
+                .skip(commits.len().saturating_sub(count))
+                .map(|c| c.id)
+                .collect::<HashSet<_>>()
+        };
+
+        self.prune(|commit| keep.contains(&commit.id))
+    }
+
+    /// Collapse all commits between `start` and `end` (inclusive)
+    /// into a single commit, which retains the metadata of `end`.
+    ///
+    /// Returns an error if either commit is not found, or `start` is
+    /// more recent than `end`.
+    ///
+    /// For full documentation, please read [`Infinitree::prune`].
+    pub fn squash(&self, start: &CommitId, end: &CommitId) -> Result<PruneReport> {
+        let drop = {
+            let commits = self.commit_list();
+            let position = |id| commits.iter().position(|c| &c.id == id);
+
+            let start = position(start).context("start commit not found")?;
+            let end = position(end).context("end commit not found")?;
+            if start > end {
+                bail!("start commit is more recent than end commit");
+            }
+
+            commits[start..end]
+                .iter()
+                .map(|c| c.id)
+                .collect::<HashSet<_>>()
+        };
+
+        self.prune(|commit| !drop.contains(&commit.id))
+    }
+
+    /// Drop all commits from the history for which `keep` returns
+    /// `false`.
+    ///
+    /// Changes in dropped commits are folded into the next kept
+    /// commit, so loading any remaining commit results in the same
+    /// state as before. To achieve this, the transactions of every
+    /// field are rewritten using [`Squash`](crate::fields::Squash).
+    ///
+    /// The last commit is always kept, and branches or tags that point
+    /// to a dropped commit are moved to the commit that absorbed its
+    /// changes.
+    ///
+    /// Returns an error if the history is not linear, i.e. there are
+    /// merge commits, or the [`previous`](CommitMetadata::previous) of
+    /// every commit is not the commit right before it.
+    ///
+    /// Since the ids of commits depend on their history, kept
+    /// commits may receive new ids, which are listed in the returned
+    /// [`PruneReport`]. Any [`CommitFilter`](super::CommitFilter)
+    /// referencing old ids needs updating.
+    ///
+    /// The objects that are no longer referenced by the tree are not
+    /// deleted. Use [`Infinitree::gc`] to release them, once the
+    /// backups of the root have moved past the pruned history, which
+    /// takes a few more commits.
+    pub fn prune(&self, mut keep: impl FnMut(&Commit<CustomData>) -> bool) -> Result<PruneReport> {
+        // lock the index to keep new commits from interleaving
+        let index = self.index.write();
+
+        let mut report = PruneReport::default();
+        let previous = {
+            let mut tr_log = self.root.transaction_log.write();
+            let mut commit_list = self.root.commit_list.write();
+
+            let Some(head) = commit_list.last().map(|c| c.id) else {
+                return Ok(report);
+            };
+
+            let keep = commit_list
+                .iter()
+                .filter(|c| c.id == head || keep(c))
+                .map(|c| c.id)
+                .collect::<HashSet<_>>();
+
+            if keep.len() == commit_list.len() {
+                return Ok(report);
+            }
+
+            // folding commits into their successors only makes sense
+            // if there's a single line of history
+            if commit_list.iter().any(|c| !c.metadata.merged.is_empty())
+                || commit_list
+                    .windows(2)
+                    .any(|w| w[1].metadata.previous != Some(w[0].id))
+            {
+                bail!("pruning is only supported on a linear history");
+            }
+
+            let mut fields = index.squash_all()?;
+            for (_, name, _) in tr_log.iter() {
+                if !fields.iter().any(|f| &f.name == name) {
+                    bail!("unknown field in the transaction log: {name}");
+                }
+            }
+
+            let mut sink = BufferedSink::new(self.chunk_writer()?);
+            let mut new_commits = CommitList::<CustomData>::with_capacity(keep.len());
+            let mut new_transactions = Vec::with_capacity(keep.len());
+
+            let mut window = HashSet::new();
+            let mut previous = None;
+            let mut absorbed = HashMap::new();
+
+            for commit in commit_list.iter() {
+                window.insert(commit.id);
+
+                if !keep.contains(&commit.id) {
+                    report.removed.push(commit.id);
+                    continue;
+                }
+
+                // newest first, same as the log
+                let transactions = tr_log
+                    .iter()
+                    .filter(|(id, _, _)| window.contains(id))
+                    .cloned()
+                    .collect::<TransactionList>();
+
+                let mut names = tr_log
+                    .iter()
+                    .filter(|(id, _, _)| id == &commit.id)
+                    .map(|(_, name, _)| name.clone())
+                    .collect::<Vec<Field>>();
+                for (_, name, _) in transactions.iter() {
+                    if !names.contains(name) {
+                        names.push(name.clone());
+                    }
+                }
+
+                let mut changeset: Vec<(Field, Stream)> = Vec::with_capacity(names.len());
+                for name in names {
+                    let mut field_transactions = transactions
+                        .iter()
+                        .filter(|(_, n, _)| n == &name)
+                        .cloned()
+                        .collect::<TransactionList>();
+
+                    let stream = if field_transactions.len() == 1 {
+                        field_transactions.remove(0).2
+                    } else {
+                        let field = fields
+                            .iter_mut()
+                            .find(|f| f.name == name)
+                            .expect("fields are checked above");
+
+                        field.strategy.squash(
+                            self.reader_pool.clone(),
+                            field_transactions,
+                            previous.is_none(),
+                            &mut sink,
+                        )?;
+                        sink.clear()?
+                    };
+
+                    changeset.push((name, stream));
+                }
+
+                let commit = if window.len() == 1 && commit.metadata.previous == previous {
+                    commit.clone()
+                } else {
+                    let mut metadata: CommitMetadata<CustomData> =
+                        crate::deserialize_from_slice(&crate::serialize_to_vec(&commit.metadata)?)?;
+                    metadata.previous = previous;
+
+                    let id = self.commit_id(&metadata, &changeset)?;
+                    report.rewritten.push((commit.id, id));
+
+                    let signature = self.sign(&id, &metadata)?;
+                    Arc::new(Commit {
+                        id,
+                        metadata,
+                        signature,
+                    })
+                };
+
+                previous = Some(commit.id);
+                absorbed.extend(window.iter().map(|old| (*old, commit.id)));
+                new_transactions.push(
+                    changeset
+                        .into_iter()
+                        .map(|(name, stream)| (commit.id, name, stream))
+                        .collect::<TransactionList>(),
+                );
+                new_commits.push(commit);
+                window.clear();
+            }
+
+            sink.finish()?;
+
+            // refs to removed commits will point to the commit that
+            // absorbed their changes
+            let mut refs = self.root.refs.write();
+            let mut updated = refs.clone();
+            for id in updated
+                .main
+                .iter_mut()
+                .chain(updated.branches.values_mut())
+                .chain(updated.tags.values_mut())
+            {
+                if let Some(new) = absorbed.get(id) {
+                    *id = *new;
+                }
+            }
+
+            (
+                replace(
+                    &mut *tr_log,
+                    new_transactions.into_iter().rev().flatten().collect(),
+                ),
+                replace(&mut *commit_list, new_commits),
+                replace(&mut *refs, updated),
+            )
+        };
+
+        // keep the history in memory the same as the one stored
+        if let Err(error) = self.commit_root() {
+            let (tr_log, commit_list, refs) = previous;
+            *self.root.transaction_log.write() = tr_log;
+            *self.root.commit_list.write() = commit_list;
+            *self.root.refs.write() = refs;
+            return Err(error);
+        }
+
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        backends::test::InMemoryBackend,
+        crypto::UsernamePassword,
+        fields::{Serialized, VersionedMap},
+        index::read_record,
+        object::DeserializeStream,
+        tree::{sealed_root::BACKUP_ROOTS, CommitFilter, GcMode},
+        Index, Infinitree,
+    };
+
+    #[derive(Index, Default)]
+    #[infinitree(walk, squash, refresh)]
+    struct Counters {
+        map: VersionedMap<usize, usize>,
+        last: Serialized<usize>,
+    }
+
+    fn key() -> UsernamePassword {
+        UsernamePassword::with_credentials("prune_user".to_string(), "prune_password".to_string())
+            .unwrap()
+    }
+
+    fn tree_with_commits(backend: std::sync::Arc<InMemoryBackend>, count: usize) {
+        let tree = Infinitree::<Counters>::empty(backend, key()).unwrap();
+        for i in 0..count {
+            tree.index().map.insert(i, i);
+            if i > 0 {
+                tree.index().map.update_with(0, |_| i);
+            }
+            *tree.index().last.write() = i;
+            tree.commit(format!("commit {i}")).unwrap();
+        }
+    }
+
+    #[test]
+    fn keep_last_commits() {
+        let backend = InMemoryBackend::shared();
+        tree_with_commits(backend.clone(), 5);
+
+        {
+            let tree = Infinitree::<Counters>::open(backend.clone(), key()).unwrap();
+            let old = tree.commit_list()[1].id;
+            tree.create_tag("old", old).unwrap();
+
+            let report = tree.keep_last(2).unwrap();
+            assert_eq!(report.removed.len(), 3);
+            assert_eq!(report.rewritten.len(), 2);
+
+            let commits = tree.commit_list();
+            assert_eq!(commits.len(), 2);
+            assert_eq!(commits[0].metadata.previous, None);
+            assert_eq!(commits[1].metadata.previous, Some(commits[0].id));
+            assert_eq!(commits[1].metadata.message.as_deref(), Some("commit 4"));
+            assert_eq!(tree.refs().tags["old"], commits[0].id);
+
+            // the backups of the root still need the pruned history
+            assert!(tree.gc(GcMode::Delete).unwrap().unreferenced.is_empty());
+        }
+
+        let tree = Infinitree::<Counters>::open(backend, key()).unwrap();
+        assert_eq!(tree.commit_list().len(), 2);
+
+        tree.load_all().unwrap();
+        assert_eq!(tree.index().map.len(), 5);
+        assert_eq!(*tree.index().map.get(&0).unwrap(), 4);
+        assert_eq!(*tree.index().last.read(), 4);
+
+        let first = tree.commit_list()[0].id;
+        tree.filter_commits(CommitFilter::UpTo(first));
+        tree.index().map.clear();
+        tree.load_all().unwrap();
+        assert_eq!(tree.index().map.len(), 4);
+        assert_eq!(*tree.index().map.get(&0).unwrap(), 3);
+        assert_eq!(*tree.index().last.read(), 3);
+
+        for i in 0..BACKUP_ROOTS {
+            tree.index().map.insert(10 + i, i);
+            tree.commit(None).unwrap();
+        }
+        assert!(!tree.gc(GcMode::Delete).unwrap().unreferenced.is_empty());
+    }
+
+    #[test]
+    fn squash_range() {
+        let backend = InMemoryBackend::shared();
+        tree_with_commits(backend.clone(), 4);
+
+        let tree = Infinitree::<Counters>::open(backend, key()).unwrap();
+        let (start, end) = {
+            let commits = tree.commit_list();
+            (commits[1].id, commits[2].id)
+        };
+
+        let report = tree.squash(&start, &end).unwrap();
+        assert_eq!(report.removed, vec![start]);
+
+        let commits = tree.commit_list();
+        assert_eq!(commits.len(), 3);
+        assert_eq!(commits[1].metadata.message.as_deref(), Some("commit 2"));
+        assert_eq!(commits[1].metadata.previous, Some(commits[0].id));
+        assert_eq!(commits[2].metadata.previous, Some(commits[1].id));
+        drop(commits);
+
+        tree.filter_commits(CommitFilter::Single(tree.commit_list()[1].id));
+        tree.load_all().unwrap();
+        assert_eq!(tree.index().map.len(), 3);
+        assert_eq!(*tree.index().map.get(&0).unwrap(), 2);
+        assert_eq!(*tree.index().map.get(&1).unwrap(), 1);
+    }
+
+    /// Count the records stored for the `map` field across all commits.
+    fn map_records(tree: &Infinitree<Counters>) -> usize {
+        let mut count = 0;
+        for (_, name, stream) in tree.root.transaction_log.read().iter() {
+            if name != "map" {
+                continue;
+            }
+
+            let mut records =
+                DeserializeStream::new(stream.open_reader(tree.reader_pool.lease().unwrap()));
+            while read_record::<serde::de::IgnoredAny>(&mut records)
+                .unwrap()
+                .is_some()
+            {
+                count += 1;
+            }
+        }
+        count
+    }
+
+    #[test]
+    fn squash_keeps_latest_records() {
+        let backend = InMemoryBackend::shared();
+        tree_with_commits(backend.clone(), 5);
+
+        let tree = Infinitree::<Counters>::open(backend.clone(), key()).unwrap();
+        tree.load_all().unwrap();
+        tree.index().map.remove(1);
+        tree.commit("remove 1").unwrap();
+
+        let before = map_records(&tree);
+        tree.keep_last(1).unwrap();
+
+        // only the latest value of the keys that are still around
+        let after = map_records(&tree);
+        assert!(after < before);
+        assert_eq!(after, 4);
+
+        let tree = Infinitree::<Counters>::open(backend, key()).unwrap();
+        tree.load_all().unwrap();
+        assert_eq!(tree.index().map.len(), 4);
+        assert!(tree.index().map.get(&1).is_none());
+        assert_eq!(*tree.index().map.get(&0).unwrap(), 4);
+        assert_eq!(*tree.index().map.get(&4).unwrap(), 4);
+    }
+
+    #[test]
+    fn history_is_unchanged_after_conflict() {
+        let backend = InMemoryBackend::shared();
+        tree_with_commits(backend.clone(), 3);
+
+        let tree = Infinitree::<Counters>::open(backend.clone(), key()).unwrap();
+        let commits = tree.commit_list().iter().map(|c| c.id).collect::<Vec<_>>();
+        tree.create_tag("first", commits[0]).unwrap();
+
+        let other = Infinitree::<Counters>::open(backend.clone(), key()).unwrap();
+        other.index().map.insert(10, 10);
+        other.commit("other").unwrap();
+
+        assert!(tree.prune(|_| false).is_err());
+        assert_eq!(
+            tree.commit_list().iter().map(|c| c.id).collect::<Vec<_>>(),
+            commits
+        );
+        assert_eq!(tree.refs().tags["first"], commits[0]);
+
+        // the next commit doesn't store the pruned history
+        tree.refresh().unwrap();
+        tree.index().map.insert(11, 11);
+        tree.commit("after").unwrap();
+
+        let tree = Infinitree::<Counters>::open(backend, key()).unwrap();
+        assert_eq!(tree.commit_list().len(), 5);
+        assert_eq!(tree.refs().tags["first"], commits[0]);
+    }
+}
@@ -0,0 +1,274 @@
+use super::{sealed_root, Commit, Infinitree, RootIndex};
+use crate::{index::Index, object::BlockBuffer};
+use anyhow::{bail, Result};
+use serde::{de::DeserializeOwned, Serialize};
+use std::{collections::HashSet, mem::swap, sync::Arc};
+
+impl<I: Index, CustomData> Infinitree<I, CustomData>
+where
+    CustomData: Serialize + DeserializeOwned + Send + Sync,
+{
+    /// Pick up the commits that other writers have made since the
+    /// tree was opened or last refreshed.
+    ///
+    /// The root is read again from the backend, and the new commits,
+    /// along with their transactions, are added to the history.
+    /// Fields that have been loaded using [`load`](Self::load) or
+    /// [`load_all`](Self::load_all) are brought up to date by only
+    /// loading the new transactions that match the current
+    /// [`CommitFilter`](super::CommitFilter). Fields that have been
+    /// [queried](Self::query) are left alone.
+    ///
+    /// New commits will be made on top of the refreshed history.
+    /// Uncommitted changes to incremental fields, like
+    /// [`VersionedMap`](crate::fields::VersionedMap), are kept. Fields
+    /// that are stored as a whole on every commit, like
+    /// [`Serialized`](crate::fields::Serialized), are replaced if
+    /// another writer has committed a newer version of them, which
+    /// discards their uncommitted changes. The [write-ahead
+    /// log](Self::open_write_ahead_log) is started again on top of the
+    /// new head.
+    ///
+    /// Returns the new commits, oldest first.
+    ///
+    /// If the history has been rewritten by the other writer, e.g. by
+    /// [pruning](Self::prune), this returns an error, and the tree
+    /// needs to be opened again. It's also an error if the current
+    /// [`CommitFilter`](super::CommitFilter) refers to a commit or
+    /// reference that the refreshed history doesn't have, in which
+    /// case the tree is left unchanged, so it can be refreshed again
+    /// after changing the filter.
+    pub fn refresh(&self) -> Result<Vec<Arc<Commit<CustomData>>>> {
+        // keep commits from this process out until we're done
+        let wal = self.wal.lock();
+        let index = self.index.write();
+        let mut root_head = self.root.root_head.write();
+
+        let fresh = sealed_root::open::<CustomData>(
+            BlockBuffer::default(),
+            self.backend.clone(),
+            self.root.key.clone(),
+        )?;
+        if *fresh.root_head.read() == *root_head {
+            return Ok(vec![]);
+        }
+
+        let known = {
+            let commit_list = self.root.commit_list.read();
+            let fresh_commits = fresh.commit_list.read();
+
+            if fresh_commits.len() < commit_list.len()
+                || commit_list
+                    .iter()
+                    .zip(fresh_commits.iter())
+                    .any(|(ours, theirs)| ours.id != theirs.id)
+            {
+                bail!("the history of the tree has been rewritten");
+            }
+
+            commit_list.len()
+        };
+
+        swap_roots(&self.root, &fresh);
+        swap(&mut *root_head, &mut *fresh.root_head.write());
+
+        let Some(selected) = self.apply_commit_filter() else {
+            // keep the new commits for the next refresh
+            swap_roots(&self.root, &fresh);
+            swap(&mut *root_head, &mut *fresh.root_head.write());
+            bail!("the commit filter doesn't match the refreshed history");
+        };
+        drop(root_head);
+
+        let new_commits = self.commit_list()[known..].to_vec();
+        let generations = new_commits
+            .iter()
+            .map(|commit| commit.id)
+            .filter(|id| selected.contains(id))
+            .collect::<HashSet<_>>();
+
+        let loaded = self.loaded.read();
+        let transaction_log = self.root.transaction_log.read();
+        for mut field in index.refresh_all()? {
+            if !loaded.contains(&field.name) {
+                continue;
+            }
+
+            let transactions = transaction_log
+                .iter()
+                .filter(|(id, name, _)| name == &field.name && generations.contains(id))
+                .cloned()
+                .collect::<Vec<_>>();
+
+            if !transactions.is_empty() {
+                field
+                    .strategy
+                    .refresh(self.reader_pool.clone(), transactions)?;
+            }
+        }
+        // the logged changes of replaced fields are gone from the index
+        self.wal_rewrite(wal.as_deref(), &index);
+
+        Ok(new_commits)
+    }
+}
+
+/// Swap the history and the state of the root of `ours` and
+/// `theirs`.
+fn swap_roots<CustomData>(ours: &RootIndex<CustomData>, theirs: &RootIndex<CustomData>)
+where
+    CustomData: Serialize + DeserializeOwned + Send + Sync,
+{
+    swap(
+        &mut *ours.transaction_log.write(),
+        &mut *theirs.transaction_log.write(),
+    );
+    swap(
+        &mut *ours.commit_list.write(),
+        &mut *theirs.commit_list.write(),
+    );
+    swap(&mut *ours.refs.write(), &mut *theirs.refs.write());
+    swap(
+        &mut *ours.trusted_writers.write(),
+        &mut *theirs.trusted_writers.write(),
+    );
+    swap(
+        &mut *ours.checkpoints.write(),
+        &mut *theirs.checkpoints.write(),
+    );
+    swap(
+        &mut *ours.generation.write(),
+        &mut *theirs.generation.write(),
+    );
+    swap(&mut *ours.objects.write(), &mut *theirs.objects.write());
+    swap(
+        &mut *ours.shadow_root.write(),
+        &mut *theirs.shadow_root.write(),
+    );
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        backends::test::InMemoryBackend,
+        crypto::UsernamePassword,
+        fields::{Serialized, VersionedMap},
+        tree::{CommitFilter, InfinitreeView},
+        Index, Infinitree,
+    };
+
+    #[derive(Index, Default)]
+    #[infinitree(refresh)]
+    struct Counters {
+        map: VersionedMap<usize, usize>,
+        last: Serialized<usize>,
+    }
+
+    fn key() -> UsernamePassword {
+        UsernamePassword::with_credentials(
+            "refresh_user".to_string(),
+            "refresh_password".to_string(),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn refresh_reader() {
+        let backend = InMemoryBackend::shared();
+        let writer = Infinitree::<Counters>::empty(backend.clone(), key()).unwrap();
+        writer.index().map.insert(1, 1);
+        *writer.index().last.write() = 1;
+        writer.commit("first").unwrap();
+
+        let reader = InfinitreeView::<Counters>::open(backend.clone(), key()).unwrap();
+        reader.load_all().unwrap();
+        assert!(reader.refresh().unwrap().is_empty());
+
+        writer.index().map.update_with(1, |_| 10);
+        writer.index().map.insert(2, 2);
+        *writer.index().last.write() = 2;
+        writer.commit("second").unwrap();
+        writer.index().map.remove(2);
+        writer.commit("third").unwrap();
+
+        let new = reader.refresh().unwrap();
+        assert_eq!(new.len(), 2);
+        assert_eq!(reader.commit_list().len(), 3);
+        assert_eq!(*reader.index().map.get(&1).unwrap(), 10);
+        assert_eq!(reader.index().map.get(&2), None);
+        assert_eq!(*reader.index().last.read(), 2);
+    }
+
+    #[test]
+    fn commit_after_refresh() {
+        let backend = InMemoryBackend::shared();
+        let first = Infinitree::<Counters>::empty(backend.clone(), key()).unwrap();
+        first.index().map.insert(1, 1);
+        first.commit("first").unwrap();
+
+        let second = Infinitree::<Counters>::open(backend.clone(), key()).unwrap();
+        second.load_all().unwrap();
+
+        first.index().map.insert(2, 2);
+        first.commit("second").unwrap();
+
+        second.refresh().unwrap();
+        second.index().map.insert(3, 3);
+        second.commit("third").unwrap();
+        assert_eq!(
+            second.commit_list()[2].metadata.previous,
+            Some(second.commit_list()[1].id)
+        );
+
+        let tree = Infinitree::<Counters>::open(backend, key()).unwrap();
+        tree.load_all().unwrap();
+        assert_eq!(tree.index().map.len(), 3);
+    }
+
+    #[test]
+    fn refresh_with_uncommitted_changes() {
+        let backend = InMemoryBackend::shared();
+        let writer = Infinitree::<Counters>::empty(backend.clone(), key()).unwrap();
+        writer.index().map.insert(1, 1);
+        *writer.index().last.write() = 1;
+        writer.commit("first").unwrap();
+
+        let reader = Infinitree::<Counters>::open(backend.clone(), key()).unwrap();
+        reader.load_all().unwrap();
+        reader.index().map.insert(3, 3);
+        *reader.index().last.write() = 3;
+
+        writer.index().map.insert(2, 2);
+        *writer.index().last.write() = 2;
+        writer.commit("second").unwrap();
+
+        // the newer snapshot replaces the uncommitted value
+        reader.refresh().unwrap();
+        assert_eq!(*reader.index().map.get(&2).unwrap(), 2);
+        assert_eq!(*reader.index().map.get(&3).unwrap(), 3);
+        assert_eq!(*reader.index().last.read(), 2);
+    }
+
+    #[test]
+    fn refresh_with_missing_filter_target() {
+        let backend = InMemoryBackend::shared();
+        let writer = Infinitree::<Counters>::empty(backend.clone(), key()).unwrap();
+        writer.index().map.insert(1, 1);
+        writer.commit("first").unwrap();
+
+        let reader = Infinitree::<Counters>::open(backend.clone(), key()).unwrap();
+        reader.filter_commits(CommitFilter::Branch("missing".into()));
+        reader.load_all().unwrap();
+
+        writer.index().map.insert(2, 2);
+        writer.commit("second").unwrap();
+
+        assert!(reader.refresh().is_err());
+        assert_eq!(reader.commit_list().len(), 1);
+
+        // the new commit is picked up once the filter matches
+        reader.filter_commits(CommitFilter::All);
+        assert_eq!(reader.refresh().unwrap().len(), 1);
+        assert_eq!(*reader.index().map.get(&2).unwrap(), 2);
+    }
+}
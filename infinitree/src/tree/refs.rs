@@ -0,0 +1,258 @@
+use super::{CommitId, Infinitree};
+use crate::index::Index;
+use anyhow::{bail, Result};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::{collections::BTreeMap, ops::Deref};
+
+/// Named references to commits in the tree.
+///
+/// Tags permanently pin a commit with a label, while the head of a
+/// branch moves forward on every commit made while the branch is
+/// checked out using [`Infinitree::checkout`]. Commits made without a
+/// branch checked out move the head of the main line.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Refs {
+    /// The head of the main line, which is followed when no branch
+    /// is checked out.
+    pub main: Option<CommitId>,
+
+    /// The current head of each branch.
+    pub branches: BTreeMap<String, CommitId>,
+
+    /// The commit each tag points to.
+    pub tags: BTreeMap<String, CommitId>,
+}
+
+impl<I: Index, CustomData> Infinitree<I, CustomData>
+where
+    CustomData: Serialize + DeserializeOwned + Send + Sync,
+{
+    /// Return all branches and tags in the tree.
+    pub fn refs(&self) -> impl Deref<Target = Refs> + '_ {
+        self.root.refs.read()
+    }
+
+    /// Return the branch that new commits will be added to, if any.
+    pub fn current_branch(&self) -> Option<String> {
+        self.branch.read().clone()
+    }
+
+    /// Record new commits on `branch`.
+    ///
+    /// New commits will follow the head of `branch`, and move it
+    /// forward. If the branch doesn't exist yet, the next commit will
+    /// create it with no history, so consider calling
+    /// [`create_branch`](Self::create_branch) first to fork off an
+    /// existing commit.
+    ///
+    /// Using `None` restores the default behaviour, which is to
+    /// follow the main line of the tree, see [`Refs::main`].
+    ///
+    /// Note that this also changes which commits
+    /// [`CommitFilter::All`](super::CommitFilter::All) will select.
+    pub fn checkout(&self, branch: Option<String>) {
+        *self.branch.write() = branch;
+    }
+
+    /// Create a new branch with its head at `commit`.
+    ///
+    /// Returns an error if the branch already exists, or the commit
+    /// is unknown.
+    pub fn create_branch(&self, name: impl Into<String>, commit: CommitId) -> Result<()> {
+        let name = name.into();
+        self.update_refs(|refs| {
+            if refs.branches.contains_key(&name) {
+                bail!("branch already exists: {name}");
+            }
+
+            refs.branches.insert(name, commit);
+            Ok(())
+        })
+    }
+
+    /// Delete a branch. The commits on the branch are not affected.
+    ///
+    /// Returns an error if the branch does not exist.
+    pub fn delete_branch(&self, name: &str) -> Result<()> {
+        self.update_refs(|refs| match refs.branches.remove(name) {
+            Some(_) => Ok(()),
+            None => bail!("no such branch: {name}"),
+        })
+    }
+
+    /// Label `commit` with a tag.
+    ///
+    /// Returns an error if the tag already exists, or the commit is
+    /// unknown.
+    pub fn create_tag(&self, name: impl Into<String>, commit: CommitId) -> Result<()> {
+        let name = name.into();
+        self.update_refs(|refs| {
+            if refs.tags.contains_key(&name) {
+                bail!("tag already exists: {name}");
+            }
+
+            refs.tags.insert(name, commit);
+            Ok(())
+        })
+    }
+
+    /// Delete a tag. The commit it points to is not affected.
+    ///
+    /// Returns an error if the tag does not exist.
+    pub fn delete_tag(&self, name: &str) -> Result<()> {
+        self.update_refs(|refs| match refs.tags.remove(name) {
+            Some(_) => Ok(()),
+            None => bail!("no such tag: {name}"),
+        })
+    }
+
+    /// The commit that the next commit will follow.
+    pub(super) fn head(&self) -> Option<CommitId> {
+        let refs = self.root.refs.read();
+        match &*self.branch.read() {
+            Some(branch) => refs.branches.get(branch).copied(),
+            None => refs.main,
+        }
+    }
+
+    /// Apply `change` to the refs, then persist them.
+    ///
+    /// The refs are left unchanged if they can't be persisted.
+    fn update_refs(&self, change: impl FnOnce(&mut Refs) -> Result<()>) -> Result<()> {
+        // lock the index to keep new commits from interleaving
+        let _index = self.index.write();
+
+        let previous = {
+            let commits = self.root.commit_list.read();
+            let mut refs = self.root.refs.write();
+
+            let mut updated = refs.clone();
+            change(&mut updated)?;
+
+            for id in updated
+                .main
+                .iter()
+                .chain(updated.branches.values())
+                .chain(updated.tags.values())
+            {
+                if !commits.iter().any(|c| &c.id == id) {
+                    bail!("no such commit: {id:?}");
+                }
+            }
+
+            std::mem::replace(&mut *refs, updated)
+        };
+
+        if let Err(error) = self.commit_root() {
+            *self.root.refs.write() = previous;
+            return Err(error);
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        backends::test::InMemoryBackend, crypto::UsernamePassword, fields::VersionedMap,
+        tree::CommitFilter, Infinitree,
+    };
+
+    fn key() -> UsernamePassword {
+        UsernamePassword::with_credentials("refs_user".to_string(), "refs_password".to_string())
+            .unwrap()
+    }
+
+    type Tree = Infinitree<VersionedMap<usize, usize>>;
+
+    #[test]
+    fn branches_and_tags() {
+        let backend = InMemoryBackend::shared();
+
+        {
+            let tree = Tree::empty(backend.clone(), key()).unwrap();
+            tree.index().insert(1, 1);
+            let base = tree.commit("base").unwrap().unwrap().id;
+            tree.create_tag("v1", base).unwrap();
+            tree.create_branch("feature", base).unwrap();
+            assert!(tree.create_tag("v1", base).is_err());
+
+            tree.index().insert(2, 2);
+            let main = tree.commit("main").unwrap().unwrap();
+            assert_eq!(main.metadata.previous, Some(base));
+
+            tree.checkout(Some("feature".into()));
+            tree.index().insert(3, 3);
+            let feature = tree.commit("feature").unwrap().unwrap();
+            assert_eq!(feature.metadata.previous, Some(base));
+            assert_eq!(tree.refs().branches["feature"], feature.id);
+        }
+
+        let tree = Tree::open(backend.clone(), key()).unwrap();
+        assert_eq!(tree.refs().tags.len(), 1);
+        assert_eq!(tree.refs().branches.len(), 1);
+
+        tree.filter_commits(CommitFilter::Branch("feature".into()));
+        tree.load_all().unwrap();
+        assert_eq!(tree.index().get(&1), Some(1.into()));
+        assert_eq!(tree.index().get(&2), None);
+        assert_eq!(tree.index().get(&3), Some(3.into()));
+
+        let tree = Tree::open(backend, key()).unwrap();
+        tree.filter_commits(CommitFilter::Tag("v1".into()));
+        tree.load_all().unwrap();
+        assert_eq!(tree.index().len(), 1);
+
+        tree.delete_tag("v1").unwrap();
+        assert!(tree.delete_tag("v1").is_err());
+        assert!(tree.refs().tags.is_empty());
+    }
+
+    #[test]
+    fn branches_leave_main_line_alone() {
+        let backend = InMemoryBackend::shared();
+
+        {
+            let tree = Tree::empty(backend.clone(), key()).unwrap();
+            tree.index().insert(1, 1);
+            let base = tree.commit("base").unwrap().unwrap().id;
+            tree.create_branch("feature", base).unwrap();
+
+            tree.checkout(Some("feature".into()));
+            tree.index().insert(2, 2);
+            tree.commit("feature").unwrap();
+
+            tree.checkout(None);
+            tree.index().insert(3, 3);
+            let main = tree.commit("main").unwrap().unwrap();
+            assert_eq!(main.metadata.previous, Some(base));
+            assert_eq!(tree.refs().main, Some(main.id));
+        }
+
+        let tree = Tree::open(backend, key()).unwrap();
+        tree.load_all().unwrap();
+        assert_eq!(tree.index().get(&1), Some(1.into()));
+        assert_eq!(tree.index().get(&2), None);
+        assert_eq!(tree.index().get(&3), Some(3.into()));
+    }
+
+    #[test]
+    fn refs_are_unchanged_after_conflict() {
+        let backend = InMemoryBackend::shared();
+        let tree = Tree::empty(backend.clone(), key()).unwrap();
+        tree.index().insert(1, 1);
+        let base = tree.commit("base").unwrap().unwrap().id;
+
+        let other = Tree::open(backend, key()).unwrap();
+        other.index().insert(2, 2);
+        other.commit("other").unwrap();
+
+        assert!(tree.create_tag("v1", base).is_err());
+        assert!(tree.refs().tags.is_empty());
+
+        tree.refresh().unwrap();
+        tree.create_tag("v1", base).unwrap();
+        assert_eq!(tree.refs().tags["v1"], base);
+    }
+}
@@ -0,0 +1,310 @@
+use super::{sealed_root, Infinitree};
+use crate::{
+    backends::{Backend, BackendError},
+    index::Index,
+    object::WriteObject,
+    ObjectId,
+};
+use anyhow::Result;
+use serde::{de::DeserializeOwned, Serialize};
+use std::{collections::HashSet, io, sync::Arc};
+
+/// The result of replicating a tree to another backend.
+#[derive(Clone, Debug, Default)]
+pub struct ReplicationReport {
+    /// Objects that have been copied to the destination.
+    pub copied: Vec<ObjectId>,
+
+    /// The number of objects that the destination already had.
+    pub skipped: usize,
+}
+
+impl<I: Index, CustomData> Infinitree<I, CustomData>
+where
+    CustomData: Serialize + DeserializeOwned + Send + Sync,
+{
+    /// Copy the objects of the tree that are missing from
+    /// `destination`.
+    ///
+    /// Objects are copied without decrypting them, so `destination`
+    /// can be any [`Backend`]. To keep the destination usable if
+    /// replication is interrupted, they're copied in this order:
+    ///
+    /// 1. the objects of the history, which are never rewritten,
+    /// 2. the backups of the root, which only refer to objects copied
+    ///    before them,
+    /// 3. the root index, then the root object.
+    ///
+    /// The root index is written into new objects in the
+    /// destination, so the destination keeps the previous state of
+    /// the tree until the root object is replaced at the very end.
+    /// The objects of earlier replicated root indexes are left for
+    /// [garbage collection](Self::gc) on the destination.
+    ///
+    /// As backups are overwritten by later commits, they're copied
+    /// every time.
+    ///
+    /// If replication is interrupted, running it again will only copy
+    /// the objects that haven't made it to the destination yet.
+    ///
+    /// If the destination can [list its
+    /// objects](Backend::list_objects), a single listing is used to
+    /// find the missing ones. Otherwise each object is looked up
+    /// individually.
+    ///
+    /// Only committed changes are replicated, and the destination
+    /// should not be written to by anything else, as its root will be
+    /// overwritten.
+    pub fn replicate(&self, destination: Arc<dyn Backend>) -> Result<ReplicationReport> {
+        let root = self.root.key.root_object_id()?;
+
+        // keep commits from this process out, so the root matches the
+        // objects that are copied
+        let _root_head = self.root.root_head.read();
+
+        // the root index is written separately, into new objects
+        let mut objects = self.live_objects()?;
+        objects.remove(&root);
+        for id in self.root.objects.read().iter() {
+            objects.remove(id);
+        }
+        // backups are rewritten in place, so they're always copied
+        let backups = sealed_root::backup_root_ids(&self.root.key)?
+            .into_iter()
+            .filter(|id| objects.remove(id))
+            .collect::<Vec<_>>();
+
+        let mut report = ReplicationReport::default();
+        self.copy_missing(destination.as_ref(), objects, &mut report)?;
+
+        for id in backups.iter() {
+            self.copy_object(destination.as_ref(), id, &mut report)?;
+        }
+
+        destination.sync()?;
+        report
+            .copied
+            .extend(sealed_root::write_copy(&self.root, destination.clone())?);
+        destination.sync()?;
+
+        Ok(report)
+    }
+
+    /// Copy the `objects` that are missing from `destination`.
+    ///
+    /// If the destination can [list its
+    /// objects](Backend::list_objects), a single listing is used to
+    /// find the missing ones. Otherwise each object is looked up
+    /// individually.
+    pub(super) fn copy_missing(
+        &self,
+        destination: &dyn Backend,
+        objects: impl IntoIterator<Item = ObjectId>,
+        report: &mut ReplicationReport,
+    ) -> Result<()> {
+        let present = match destination.list_objects() {
+            Ok(listing) => Some(
+                listing
+                    .map(|info| info.map(|info| info.id))
+                    .collect::<std::result::Result<HashSet<_>, _>>()?,
+            ),
+            Err(BackendError::Unsupported) => None,
+            Err(error) => return Err(error.into()),
+        };
+
+        for id in objects {
+            let exists = match &present {
+                Some(present) => present.contains(&id),
+                None => exists(destination, &id)?,
+            };
+
+            if exists {
+                report.skipped += 1;
+            } else {
+                self.copy_object(destination, &id, report)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn copy_object(
+        &self,
+        destination: &dyn Backend,
+        id: &ObjectId,
+        report: &mut ReplicationReport,
+    ) -> Result<()> {
+        let object = self.backend.read_fresh(id)?;
+        destination.write_object(&WriteObject::from(object))?;
+        report.copied.push(*id);
+
+        Ok(())
+    }
+}
+
+/// Check if `backend` has the object `id`.
+pub(super) fn exists(backend: &dyn Backend, id: &ObjectId) -> Result<bool> {
+    match backend.read_fresh(id) {
+        Ok(_) => Ok(true),
+        Err(BackendError::NotFound { .. }) => Ok(false),
+        Err(BackendError::Io { source }) if source.kind() == io::ErrorKind::NotFound => Ok(false),
+        Err(error) => Err(error.into()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        backends::{test::InMemoryBackend, Backend, BackendError, ObjectList},
+        crypto::UsernamePassword,
+        fields::VersionedMap,
+        object::{ReadObject, WriteObject},
+        tree::{sealed_root::backup_root_ids, VerifyMode},
+        Index, Infinitree, ObjectId,
+    };
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    #[derive(Index, Default)]
+    #[infinitree(walk)]
+    struct Counters {
+        map: VersionedMap<usize, usize>,
+    }
+
+    fn key() -> UsernamePassword {
+        UsernamePassword::with_credentials(
+            "replicate_user".to_string(),
+            "replicate_password".to_string(),
+        )
+        .unwrap()
+    }
+
+    /// Fails every write after a number of successful ones, and
+    /// can't list its objects.
+    struct Flaky {
+        inner: InMemoryBackend,
+        writes_left: AtomicUsize,
+    }
+
+    impl Backend for Flaky {
+        fn write_object(&self, object: &WriteObject) -> crate::backends::Result<()> {
+            if self.writes_left.load(Ordering::SeqCst) == 0 {
+                return Err(BackendError::Create);
+            }
+
+            self.writes_left.fetch_sub(1, Ordering::SeqCst);
+            self.inner.write_object(object)
+        }
+
+        fn read_object(&self, id: &ObjectId) -> crate::backends::Result<Arc<ReadObject>> {
+            self.inner.read_object(id)
+        }
+
+        fn list_objects(&self) -> crate::backends::Result<ObjectList<'_>> {
+            Err(BackendError::Unsupported)
+        }
+    }
+
+    #[test]
+    fn replicate_and_resume() {
+        let source = InMemoryBackend::shared();
+        let tree = Infinitree::<Counters>::empty(source.clone(), key()).unwrap();
+        for i in 0..3 {
+            tree.index().map.insert(i, i);
+            tree.commit(None).unwrap();
+        }
+
+        let flaky = Arc::new(Flaky {
+            inner: InMemoryBackend::new(),
+            writes_left: AtomicUsize::new(2),
+        });
+        assert!(tree.replicate(flaky.clone()).is_err());
+
+        // nothing is visible until the root is written
+        assert!(Infinitree::<Counters>::open(Arc::new(flaky.inner.clone()), key()).is_err());
+
+        flaky.writes_left.store(usize::MAX, Ordering::SeqCst);
+        let report = tree.replicate(flaky.clone()).unwrap();
+        assert_eq!(report.skipped, 2);
+
+        let replica = Infinitree::<Counters>::open(Arc::new(flaky.inner.clone()), key()).unwrap();
+        assert!(replica.verify(VerifyMode::Full).unwrap().is_ok());
+        replica.load_all().unwrap();
+        assert_eq!(replica.index().map.len(), 3);
+        drop(replica);
+
+        // only the new commit and the root are copied next time
+        tree.index().map.insert(3, 3);
+        tree.commit(None).unwrap();
+
+        let destination = Arc::new(flaky.inner.clone());
+        let report = tree.replicate(destination.clone()).unwrap();
+        assert!(report.skipped >= 3);
+
+        let replica = Infinitree::<Counters>::open(destination, key()).unwrap();
+        replica.load_all().unwrap();
+        assert_eq!(replica.index().map.len(), 4);
+    }
+
+    #[test]
+    fn interrupted_replica_keeps_previous_root() {
+        let source = InMemoryBackend::shared();
+        let tree = Infinitree::<Counters>::empty(source, key()).unwrap();
+        tree.index().map.insert(0, 0);
+        tree.commit(None).unwrap();
+
+        let reference = InMemoryBackend::shared();
+        let flaky = Arc::new(Flaky {
+            inner: InMemoryBackend::new(),
+            writes_left: AtomicUsize::new(usize::MAX),
+        });
+        tree.replicate(reference.clone()).unwrap();
+        tree.replicate(flaky.clone()).unwrap();
+
+        tree.index().map.insert(1, 1);
+        tree.commit(None).unwrap();
+
+        // fail right before the root object is written
+        let writes = tree.replicate(reference).unwrap().copied.len();
+        flaky.writes_left.store(writes - 1, Ordering::SeqCst);
+        assert!(tree.replicate(flaky.clone()).is_err());
+
+        let replica = Infinitree::<Counters>::open(Arc::new(flaky.inner.clone()), key()).unwrap();
+        assert!(replica.recovered_root().is_none());
+        assert_eq!(replica.commit_list().len(), 1);
+        replica.load_all().unwrap();
+        assert_eq!(replica.index().map.len(), 1);
+    }
+
+    #[test]
+    fn replica_is_recovered_from_backup() {
+        let source = InMemoryBackend::shared();
+        let tree = Infinitree::<Counters>::empty(source, key()).unwrap();
+        for i in 0..4 {
+            tree.index().map.insert(i, i);
+            tree.commit(None).unwrap();
+        }
+
+        let destination = InMemoryBackend::shared();
+        tree.replicate(destination.clone()).unwrap();
+
+        let backups = backup_root_ids(&tree.root.key).unwrap();
+        for id in backups.iter() {
+            assert!(destination.read_object(id).is_ok());
+        }
+
+        let mut damaged = WriteObject::default();
+        damaged.set_id(tree.root.key.root_object_id().unwrap());
+        destination.write_object(&damaged).unwrap();
+
+        let replica = Infinitree::<Counters>::open(destination, key()).unwrap();
+        assert_eq!(
+            replica.recovered_root().unwrap().commit,
+            tree.commit_list().last().map(|c| c.id)
+        );
+        replica.load_all().unwrap();
+        assert_eq!(replica.index().map.len(), 4);
+    }
+}
@@ -0,0 +1,346 @@
+use super::{Commit, CommitId, Infinitree, PruneReport};
+use crate::index::Index;
+use anyhow::Result;
+use serde::{de::DeserializeOwned, Serialize};
+use std::{cmp::Reverse, collections::HashSet, time::SystemTime};
+
+const SECONDS_PER_HOUR: i64 = 3600;
+const SECONDS_PER_DAY: i64 = 24 * SECONDS_PER_HOUR;
+
+type Bucket = fn(SystemTime) -> i64;
+type Predicate<CustomData> = Box<dyn Fn(&Commit<CustomData>) -> bool + Send + Sync>;
+
+/// Decides which commits to keep when thinning out the history of a
+/// tree.
+///
+/// Similarly to `restic forget`, a commit is kept if _any_ of the
+/// rules selects it:
+///
+///  * the last `n` commits,
+///  * the most recent commit in each of the last `n` hours, days,
+///    weeks, months or years that have commits,
+///  * commits for which a custom predicate returns `true`.
+///
+/// Time periods are calculated in UTC based on
+/// [`CommitMetadata::time`](super::CommitMetadata::time), and weeks
+/// start on Monday.
+///
+/// The last commit of the tree is always kept, so an empty policy
+/// will only retain the last commit.
+///
+/// # Examples
+///
+/// ```no_run
+/// use infinitree::{*, crypto::UsernamePassword, fields::VersionedMap, backends::Directory, tree::RetentionPolicy};
+///
+/// let tree = Infinitree::<VersionedMap<String, String>>::open(
+///     Directory::new("/storage").unwrap(),
+///     UsernamePassword::with_credentials("username".to_string(),
+///                                        "password".to_string()).unwrap()
+/// ).unwrap();
+///
+/// let policy = RetentionPolicy::new()
+///     .keep_last(3)
+///     .keep_daily(7)
+///     .keep_monthly(12)
+///     .keep_if(|commit| commit.metadata.message.as_deref() == Some("release"));
+///
+/// for id in tree.retention_plan(&policy).forget {
+///     println!("forgetting {id:?}");
+/// }
+///
+/// tree.forget(&policy).unwrap();
+/// ```
+pub struct RetentionPolicy<CustomData = ()>
+where
+    CustomData: Serialize,
+{
+    last: usize,
+    hourly: usize,
+    daily: usize,
+    weekly: usize,
+    monthly: usize,
+    yearly: usize,
+    predicates: Vec<Predicate<CustomData>>,
+}
+
+impl<CustomData: Serialize> Default for RetentionPolicy<CustomData> {
+    fn default() -> Self {
+        Self {
+            last: 0,
+            hourly: 0,
+            daily: 0,
+            weekly: 0,
+            monthly: 0,
+            yearly: 0,
+            predicates: vec![],
+        }
+    }
+}
+
+impl<CustomData: Serialize> RetentionPolicy<CustomData> {
+    /// Create an empty policy.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Keep the last `count` commits.
+    pub fn keep_last(mut self, count: usize) -> Self {
+        self.last = count;
+        self
+    }
+
+    /// Keep the most recent commit for the last `count` hours.
+    pub fn keep_hourly(mut self, count: usize) -> Self {
+        self.hourly = count;
+        self
+    }
+
+    /// Keep the most recent commit for the last `count` days.
+    pub fn keep_daily(mut self, count: usize) -> Self {
+        self.daily = count;
+        self
+    }
+
+    /// Keep the most recent commit for the last `count` weeks.
+    pub fn keep_weekly(mut self, count: usize) -> Self {
+        self.weekly = count;
+        self
+    }
+
+    /// Keep the most recent commit for the last `count` months.
+    pub fn keep_monthly(mut self, count: usize) -> Self {
+        self.monthly = count;
+        self
+    }
+
+    /// Keep the most recent commit for the last `count` years.
+    pub fn keep_yearly(mut self, count: usize) -> Self {
+        self.yearly = count;
+        self
+    }
+
+    /// Keep all commits for which `predicate` returns `true`.
+    ///
+    /// This is useful to match on the message or custom data of a
+    /// commit. Can be called multiple times to add more predicates.
+    pub fn keep_if(
+        mut self,
+        predicate: impl Fn(&Commit<CustomData>) -> bool + Send + Sync + 'static,
+    ) -> Self {
+        self.predicates.push(Box::new(predicate));
+        self
+    }
+
+    /// Decide which of `commits` to keep and forget.
+    ///
+    /// `commits` should be in chronological order, as returned by
+    /// [`Infinitree::commit_list`].
+    pub fn plan<'a>(
+        &self,
+        commits: impl IntoIterator<Item = &'a Commit<CustomData>>,
+    ) -> RetentionPlan
+    where
+        CustomData: 'a,
+    {
+        let commits = commits.into_iter().collect::<Vec<_>>();
+
+        // newest first, in case there are commits with the same time
+        let mut by_time = commits.iter().rev().copied().collect::<Vec<_>>();
+        by_time.sort_by_key(|c| Reverse(c.metadata.time));
+
+        let mut keep = HashSet::new();
+        if let Some(head) = commits.last() {
+            keep.insert(head.id);
+        }
+
+        keep.extend(commits.iter().rev().take(self.last).map(|c| c.id));
+        keep.extend(
+            commits
+                .iter()
+                .filter(|c| self.predicates.iter().any(|p| p(c)))
+                .map(|c| c.id),
+        );
+
+        let buckets: [(usize, Bucket); 5] = [
+            (self.hourly, hour),
+            (self.daily, day),
+            (self.weekly, week),
+            (self.monthly, month),
+            (self.yearly, year),
+        ];
+
+        for (count, bucket) in buckets {
+            let mut last_bucket = None;
+            let mut kept = 0;
+
+            for commit in by_time.iter() {
+                if kept == count {
+                    break;
+                }
+
+                let current = bucket(commit.metadata.time);
+                if last_bucket != Some(current) {
+                    last_bucket = Some(current);
+                    keep.insert(commit.id);
+                    kept += 1;
+                }
+            }
+        }
+
+        let (keep, forget): (Vec<_>, Vec<_>) = commits
+            .iter()
+            .map(|c| c.id)
+            .partition(|id| keep.contains(id));
+        RetentionPlan { keep, forget }
+    }
+}
+
+/// The outcome of applying a [`RetentionPolicy`] to a list of
+/// commits.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RetentionPlan {
+    /// Commits to keep, in chronological order.
+    pub keep: Vec<CommitId>,
+    /// Commits to forget, in chronological order.
+    pub forget: Vec<CommitId>,
+}
+
+impl<I: Index, CustomData> Infinitree<I, CustomData>
+where
+    CustomData: Serialize + DeserializeOwned + Send + Sync,
+{
+    /// Calculate which commits would be removed by
+    /// [`forget`](Self::forget) without changing the tree.
+    pub fn retention_plan(&self, policy: &RetentionPolicy<CustomData>) -> RetentionPlan {
+        policy.plan(self.commit_list().iter().map(AsRef::as_ref))
+    }
+
+    /// Remove all commits from the history that are not retained by
+    /// `policy`.
+    ///
+    /// For full documentation, please read [`Infinitree::prune`].
+    pub fn forget(&self, policy: &RetentionPolicy<CustomData>) -> Result<PruneReport> {
+        let keep = self
+            .retention_plan(policy)
+            .keep
+            .into_iter()
+            .collect::<HashSet<_>>();
+
+        self.prune(|commit| keep.contains(&commit.id))
+    }
+}
+
+fn seconds(time: SystemTime) -> i64 {
+    match time.duration_since(SystemTime::UNIX_EPOCH) {
+        Ok(since) => since.as_secs() as i64,
+        Err(before) => -(before.duration().as_secs() as i64),
+    }
+}
+
+fn hour(time: SystemTime) -> i64 {
+    seconds(time).div_euclid(SECONDS_PER_HOUR)
+}
+
+fn day(time: SystemTime) -> i64 {
+    seconds(time).div_euclid(SECONDS_PER_DAY)
+}
+
+fn week(time: SystemTime) -> i64 {
+    // 1970-01-01 was a Thursday
+    (day(time) + 3).div_euclid(7)
+}
+
+fn month(time: SystemTime) -> i64 {
+    let (year, month) = year_and_month(day(time));
+    year * 12 + month - 1
+}
+
+fn year(time: SystemTime) -> i64 {
+    year_and_month(day(time)).0
+}
+
+/// Convert days since the epoch into a calendar year and month (1-12).
+///
+/// See <http://howardhinnant.github.io/date_algorithms.html#civil_from_days>
+fn year_and_month(days: i64) -> (i64, i64) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    (year, month)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{year_and_month, RetentionPolicy};
+    use crate::tree::{Commit, CommitId, CommitMetadata};
+    use std::time::{Duration, SystemTime};
+
+    const DAY: u64 = 24 * 3600;
+
+    fn commit(n: u8, days: u64, message: &str) -> Commit<()> {
+        Commit {
+            id: CommitId::from_bytes([n; 32]),
+            metadata: CommitMetadata {
+                time: SystemTime::UNIX_EPOCH + Duration::from_secs(days * DAY),
+                message: Some(message.to_string()),
+                ..Default::default()
+            },
+            signature: None,
+        }
+    }
+
+    #[test]
+    fn calendar() {
+        assert_eq!(year_and_month(0), (1970, 1));
+        assert_eq!(year_and_month(-1), (1969, 12));
+        // 2024-02-29
+        assert_eq!(year_and_month(19_782), (2024, 2));
+        // 2024-03-01
+        assert_eq!(year_and_month(19_783), (2024, 3));
+    }
+
+    #[test]
+    fn plan_buckets() {
+        // 1970-01-01 is a Thursday
+        let commits = [
+            commit(0, 0, "tag"),
+            commit(1, 1, ""),
+            commit(2, 4, ""),
+            commit(3, 5, ""),
+            commit(4, 5, ""),
+            commit(5, 40, ""),
+            commit(6, 41, ""),
+        ];
+        let id = |n: u8| commits[n as usize].id;
+
+        let plan = RetentionPolicy::new().plan(&commits);
+        assert_eq!(plan.keep, vec![id(6)]);
+
+        let plan = RetentionPolicy::new().keep_last(2).plan(&commits);
+        assert_eq!(plan.keep, vec![id(5), id(6)]);
+
+        let plan = RetentionPolicy::new().keep_daily(3).plan(&commits);
+        assert_eq!(plan.keep, vec![id(4), id(5), id(6)]);
+
+        let plan = RetentionPolicy::new().keep_weekly(3).plan(&commits);
+        assert_eq!(plan.keep, vec![id(1), id(4), id(6)]);
+
+        let plan = RetentionPolicy::new().keep_monthly(5).plan(&commits);
+        assert_eq!(plan.keep, vec![id(4), id(6)]);
+
+        let plan = RetentionPolicy::new()
+            .keep_yearly(1)
+            .keep_if(|c| c.metadata.message.as_deref() == Some("tag"))
+            .plan(&commits);
+        assert_eq!(plan.keep, vec![id(0), id(6)]);
+        assert_eq!(plan.forget, vec![id(1), id(2), id(3), id(4), id(5)]);
+    }
+}
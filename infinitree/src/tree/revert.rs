@@ -0,0 +1,235 @@
+use super::{merge::first_parents, Commit, CommitId, CommitMetadata, Infinitree, Message};
+use crate::{
+    index::{Index, TransactionList},
+    object::BufferedSink,
+};
+use anyhow::{bail, Context, Result};
+use serde::{de::DeserializeOwned, Serialize};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+    time::SystemTime,
+};
+
+impl<I: Index, CustomData> Infinitree<I, CustomData>
+where
+    CustomData: Serialize + DeserializeOwned + Send + Sync + Default,
+{
+    /// Record a new commit with `message` that restores every field
+    /// to its state at `target`.
+    ///
+    /// For full documentation, please read
+    /// [`Infinitree::revert_with_metadata`].
+    pub fn revert_to(
+        &self,
+        target: CommitId,
+        message: impl Into<Message>,
+    ) -> Result<Option<Arc<Commit<CustomData>>>> {
+        self.revert_with_metadata(target, None, self.revert_metadata(message))
+    }
+
+    /// Record a new commit with `message` that restores the given
+    /// fields to their state at `target`.
+    ///
+    /// For full documentation, please read
+    /// [`Infinitree::revert_with_metadata`].
+    pub fn revert_fields_to(
+        &self,
+        target: CommitId,
+        fields: &[&str],
+        message: impl Into<Message>,
+    ) -> Result<Option<Arc<Commit<CustomData>>>> {
+        self.revert_with_metadata(target, Some(fields), self.revert_metadata(message))
+    }
+
+    fn revert_metadata(&self, message: impl Into<Message>) -> CommitMetadata<CustomData> {
+        CommitMetadata {
+            time: SystemTime::now(),
+            message: message.into().into(),
+            previous: self.head(),
+            ..Default::default()
+        }
+    }
+}
+
+impl<I: Index, CustomData> Infinitree<I, CustomData>
+where
+    CustomData: Serialize + DeserializeOwned + Send + Sync,
+{
+    /// Revert to an earlier state using manually prepared metadata.
+    ///
+    /// The new commit is made on top of
+    /// [`previous`](CommitMetadata::previous), and contains the
+    /// changes that restore `fields`, or every field if `None`, to
+    /// the state they had at `target`. The history in between is
+    /// kept.
+    ///
+    /// Incremental fields, such as
+    /// [`VersionedMap`](crate::fields::VersionedMap), are restored
+    /// record by record, while snapshot fields are restored as a
+    /// whole. Fields that can't remove records, such as
+    /// [`LinkedList`](crate::fields::LinkedList), can only be
+    /// reverted if nothing has been added to them since `target`.
+    ///
+    /// The index in memory is not changed. Reload the index to work
+    /// with the reverted state.
+    ///
+    /// Returns `None` if the fields are already in the state of
+    /// `target`, or an error if `target` is not found.
+    pub fn revert_with_metadata(
+        &self,
+        target: CommitId,
+        fields: Option<&[&str]>,
+        metadata: CommitMetadata<CustomData>,
+    ) -> Result<Option<Arc<Commit<CustomData>>>> {
+        // lock the index to keep new commits from interleaving
+        let wal = self.wal.lock();
+        let index = self.index.write();
+
+        let (current, target) = {
+            let commits = self.commit_list();
+            let graph = commits.iter().map(|c| (c.id, c)).collect::<HashMap<_, _>>();
+            graph.get(&target).context("commit not found")?;
+
+            // merge commits contain the changes they merged, so
+            // following the first parents is enough
+            (
+                first_parents(&graph, metadata.previous, &HashSet::new()),
+                first_parents(&graph, Some(target), &HashSet::new()),
+            )
+        };
+
+        let mut selected = index.revert_all()?;
+        if let Some(fields) = fields {
+            for name in fields {
+                if !selected.iter().any(|f| &f.name == name) {
+                    bail!("unknown field: {name}");
+                }
+            }
+
+            selected.retain(|f| fields.contains(&f.name.as_str()));
+        }
+
+        let tr_log = self.root.transaction_log.read().clone();
+        let mut sink = BufferedSink::new(self.chunk_writer()?);
+        let mut changeset = vec![];
+        for mut field in selected {
+            let select = |commits: &HashSet<CommitId>| {
+                tr_log
+                    .iter()
+                    .filter(|(id, name, _)| name == &field.name && commits.contains(id))
+                    .cloned()
+                    .collect::<TransactionList>()
+            };
+            let (current, target) = (select(&current), select(&target));
+
+            let changed = field
+                .strategy
+                .revert(self.reader_pool.clone(), current, target, &mut sink)
+                .with_context(|| format!("failed to revert field {}", field.name))?;
+
+            let stream = sink.clear()?;
+            if changed {
+                changeset.push((field.name, stream));
+            }
+        }
+        sink.finish()?;
+
+        if changeset.is_empty() {
+            return Ok(None);
+        }
+
+        let metadata = metadata.rounded()?;
+        let id = self.commit_id(&metadata, &changeset)?;
+        self.hooks.read().pre_commit(&metadata, &changeset)?;
+        self.record_commit(id, metadata, changeset)?;
+
+        // the logged changes weren't part of the commit, so they're kept
+        self.wal_restart(wal.as_deref(), &index);
+
+        Ok(self.last_commit())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        backends::test::InMemoryBackend,
+        crypto::UsernamePassword,
+        fields::{LinkedList, Serialized, VersionedMap},
+        Index, Infinitree,
+    };
+
+    #[derive(Index, Default)]
+    #[infinitree(revert)]
+    struct Config {
+        map: VersionedMap<usize, usize>,
+        version: Serialized<usize>,
+        log: LinkedList<usize>,
+    }
+
+    fn key() -> UsernamePassword {
+        UsernamePassword::with_credentials("revert_user".to_string(), "revert_password".to_string())
+            .unwrap()
+    }
+
+    #[test]
+    fn revert_to_earlier_commit() {
+        let backend = InMemoryBackend::shared();
+        let tree = Infinitree::<Config>::empty(backend.clone(), key()).unwrap();
+        tree.index().map.insert(1, 1);
+        tree.index().map.insert(2, 2);
+        *tree.index().version.write() = 1;
+        let good = tree.commit("good").unwrap().unwrap();
+
+        tree.index().map.update_with(1, |_| 10);
+        tree.index().map.remove(2);
+        tree.index().map.insert(3, 3);
+        *tree.index().version.write() = 2;
+        tree.commit("bad").unwrap();
+
+        let reverted = tree.revert_to(good.id, "revert").unwrap().unwrap();
+        assert_eq!(reverted.metadata.previous, Some(tree.commit_list()[1].id));
+        assert_eq!(tree.commit_list().len(), 3);
+
+        // nothing left to revert
+        assert!(tree.revert_to(good.id, "again").unwrap().is_none());
+
+        let tree = Infinitree::<Config>::open(backend, key()).unwrap();
+        tree.load_all().unwrap();
+        assert_eq!(*tree.index().map.get(&1).unwrap(), 1);
+        assert_eq!(*tree.index().map.get(&2).unwrap(), 2);
+        assert_eq!(tree.index().map.get(&3), None);
+        assert_eq!(*tree.index().version.read(), 1);
+    }
+
+    #[test]
+    fn revert_some_fields() {
+        let backend = InMemoryBackend::shared();
+        let tree = Infinitree::<Config>::empty(backend.clone(), key()).unwrap();
+        tree.index().map.insert(1, 1);
+        *tree.index().version.write() = 1;
+        let good = tree.commit("good").unwrap().unwrap();
+
+        tree.index().map.update_with(1, |_| 10);
+        tree.index().log.push(1);
+        *tree.index().version.write() = 2;
+        tree.commit("bad").unwrap();
+
+        // records can't be removed from a linked list
+        assert!(tree.revert_to(good.id, "revert").is_err());
+        assert!(tree
+            .revert_fields_to(good.id, &["missing"], "revert")
+            .is_err());
+
+        tree.revert_fields_to(good.id, &["version"], "revert")
+            .unwrap()
+            .unwrap();
+
+        let tree = Infinitree::<Config>::open(backend, key()).unwrap();
+        tree.load_all().unwrap();
+        assert_eq!(*tree.index().map.get(&1).unwrap(), 10);
+        assert_eq!(*tree.index().version.read(), 1);
+        assert_eq!(tree.index().log.iter().count(), 1);
+    }
+}
@@ -1,9 +1,18 @@
-use super::commit::*;
-use crate::{crypto::Key, fields::Serialized, index::TransactionList, ObjectId};
+use super::{
+    commit::*,
+    sealed_root::{Checkpoint, RecoveredRoot},
+    Refs,
+};
+use crate::{
+    crypto::{Key, SealedHeader, WriterKey},
+    fields::Serialized,
+    index::TransactionList,
+    ObjectId,
+};
 use serde::{de::DeserializeOwned, Serialize};
+use std::collections::BTreeSet;
 
 /// The root index of the tree that stores version information
-#[derive(infinitree_macros::Index)]
 pub(crate) struct RootIndex<CustomData>
 where
     CustomData: Serialize + DeserializeOwned + Send + Sync + 'static,
@@ -19,17 +28,38 @@ where
     /// Chronologically ordered list of commits
     pub(crate) commit_list: Serialized<CommitList<CustomData>>,
 
-    /// Stores the list of objects that contain the index.  To make
-    /// storage use more efficient, on commit these are going to be
-    /// rewritten first, but only with index data.
-    #[infinitree(skip)]
+    /// Branches and tags
+    pub(crate) refs: Serialized<Refs>,
+
+    /// Writers whose signed commits are trusted
+    pub(crate) trusted_writers: Serialized<BTreeSet<WriterKey>>,
+
+    /// The parts of the history that have been sealed into objects
+    /// that are never rewritten, oldest first.
+    ///
+    /// Only the history since the last checkpoint is written on
+    /// commit.
+    pub(crate) checkpoints: Serialized<Vec<Checkpoint>>,
+
+    /// The number of times the root has been written.
+    pub(crate) generation: Serialized<u64>,
+
+    /// Stores the list of objects that contain the head of the root
+    /// index.  To make storage use more efficient, on commit these
+    /// are going to be rewritten first, but only with index data.
     pub(crate) objects: Serialized<Vec<ObjectId>>,
 
-    #[infinitree(skip)]
     pub(crate) shadow_root: Serialized<ObjectId>,
 
-    #[infinitree(skip)]
+    /// The root object and its sealed header as last read or
+    /// written. Used to detect other writers before committing.
+    pub(crate) root_head: Serialized<Option<(ObjectId, SealedHeader)>>,
+
     pub(crate) key: Key,
+
+    /// Set if the root object couldn't be opened, and a backup was
+    /// used instead.
+    pub(crate) recovered: Option<RecoveredRoot>,
 }
 
 impl<CustomData> RootIndex<CustomData>
@@ -40,9 +70,15 @@ where
         Self {
             transaction_log: Default::default(),
             commit_list: Default::default(),
+            refs: Default::default(),
+            trusted_writers: Default::default(),
+            checkpoints: Default::default(),
+            generation: Default::default(),
             objects: objects.into(),
             shadow_root: shadow_root.into(),
+            root_head: Default::default(),
             key,
+            recovered: None,
         }
     }
 
@@ -50,9 +86,15 @@ where
         Self {
             transaction_log: Default::default(),
             commit_list: Default::default(),
+            refs: Default::default(),
+            trusted_writers: Default::default(),
+            checkpoints: Default::default(),
+            generation: Default::default(),
             objects: Default::default(),
             shadow_root: Default::default(),
+            root_head: Default::default(),
             key,
+            recovered: None,
         }
     }
 }
@@ -63,10 +105,12 @@ where
 {
     pub(crate) fn objects(&self) -> Vec<ObjectId> {
         let root = self.objects.read();
+        let checkpoints = self.checkpoints.read();
         let transactions = self.transaction_log.read();
         let mut stream = root
             .iter()
             .cloned()
+            .chain(checkpoints.iter().flat_map(|c| c.stream.objects()))
             .chain(
                 transactions
                     .iter()
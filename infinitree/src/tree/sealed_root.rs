@@ -1,18 +1,21 @@
+use super::{CommitId, CommitList, Refs};
 use crate::{
     backends::{Backend, BackendError},
     chunks::RawChunkPointer,
-    crypto::{CryptoError, Key, SealedHeader},
+    crypto::{CryptoError, IndexKey, Key, SealedHeader, WriterKey},
     deserialize_from_slice,
-    index::{FieldReader, IndexExt, TransactionList},
+    index::{FieldReader, TransactionList},
     object::{
-        AEADReader, AEADWriter, BlockBuffer, BufferedSink, DeserializeStream, ObjectId, Pool,
-        PoolRef, Stream, Writer,
+        AEADReader, AEADWriter, BlockBuffer, BufferedSink, DeserializeStream, ObjectError,
+        ObjectId, Pool, PoolRef, ReadObject, Stream, Writer,
     },
     tree::RootIndex,
 };
-use serde::{de::DeserializeOwned, Serialize};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_with::serde_as;
 use std::{
-    io,
+    collections::BTreeSet,
+    io::{self, Write},
     mem::{size_of, take},
     num::NonZeroUsize,
     sync::Arc,
@@ -49,33 +52,218 @@ pub(crate) enum Error {
     #[error("Internal error: {source}")]
     Internal {
         #[from]
-        source: crate::object::ObjectError,
+        source: ObjectError,
     },
     #[error("anyhow")]
     Anyhow {
         #[from]
         source: anyhow::Error,
     },
+    #[error("{source}")]
+    Conflict {
+        #[from]
+        source: ConflictError,
+    },
+    #[error("unsupported root format: {version}")]
+    Unsupported { version: u32 },
 }
 pub(crate) type Result<T> = std::result::Result<T, Error>;
 
+/// Returned when committing to a tree that another writer has
+/// changed since it was opened.
+///
+/// Nothing is written to the root object when this happens, and the
+/// commit is not added to the history. Use
+/// [`refresh`](super::Infinitree::refresh) to pick up the new commits,
+/// then try again: the changes of the failed commit are carried over
+/// into the next one.
+#[derive(thiserror::Error, Debug)]
+#[error("the tree has been modified by another writer")]
+pub struct ConflictError;
+
+/// The number of backups of the root object that are kept in a ring.
+pub(crate) const BACKUP_ROOTS: usize = 3;
+
+/// Describes the root that a tree has been opened from, if the latest
+/// one couldn't be read.
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub struct RecoveredRoot {
+    /// The last commit in the recovered root, if there is any.
+    pub commit: Option<CommitId>,
+
+    /// Why the latest root couldn't be opened.
+    pub reason: String,
+}
+
+/// Once the history since the last checkpoint takes up this many
+/// bytes, it is sealed into a new checkpoint.
+pub(crate) const CHECKPOINT_SIZE: usize = 1024 * 1024;
+
+/// A part of the history that has been sealed into objects which are
+/// never rewritten.
+#[derive(Serialize, Deserialize, Clone)]
+pub(crate) struct Checkpoint {
+    /// The number of commits in the history up to and including this
+    /// checkpoint.
+    commits: usize,
+
+    /// The number of transactions in the history up to and including
+    /// this checkpoint.
+    transactions: usize,
+
+    /// The last commit in the checkpoint.
+    last: CommitId,
+
+    /// The [`Segment`] of the history since the previous checkpoint.
+    pub(crate) stream: Stream,
+}
+
+impl Checkpoint {
+    /// Returns `true` if the history still starts with the contents
+    /// of this checkpoint, i.e. it hasn't been rewritten since.
+    fn is_prefix_of<CustomData>(
+        &self,
+        transaction_log: &TransactionList,
+        commit_list: &CommitList<CustomData>,
+    ) -> bool
+    where
+        CustomData: Serialize,
+    {
+        self.transactions <= transaction_log.len()
+            && self
+                .commits
+                .checked_sub(1)
+                .and_then(|i| commit_list.get(i))
+                .map(|c| c.id)
+                == Some(self.last)
+    }
+}
+
+/// A consecutive range of the history.
+#[derive(Serialize, Deserialize)]
+struct Segment<CustomData>
+where
+    CustomData: Serialize,
+{
+    /// Newest first, same as the transaction log.
+    transactions: TransactionList,
+
+    /// Oldest first, same as the commit list.
+    commits: CommitList<CustomData>,
+}
+
+/// The version of the [`Head`] that's written on commit.
+const HEAD_VERSION: u32 = 1;
+
+/// The first record in the stream of the root.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Format {
+    /// The version of the [`Head`] that follows.
+    Versioned(u32),
+
+    /// Roots written before the head had a version contain the
+    /// transactions of the `transaction_log` and `commit_list`
+    /// fields, each of them stored in a separate stream.
+    Legacy(TransactionList),
+}
+
+/// The root pointer refers to the stream that contains the version
+/// of the format, followed by the head.
+///
+/// Everything but the checkpoints is rewritten on every commit, so
+/// its size only depends on the changes since the last checkpoint.
+#[serde_as]
+#[derive(Serialize, Deserialize)]
+struct Head {
+    /// Incremented on every commit, to find the latest backup.
+    generation: u64,
+
+    checkpoints: Vec<Checkpoint>,
+
+    /// The serialized [`Segment`] since the last checkpoint.
+    #[serde_as(as = "serde_with::Bytes")]
+    tail: Vec<u8>,
+
+    refs: Refs,
+    trusted_writers: BTreeSet<WriterKey>,
+}
+
+/// Open the root of the tree.
+///
+/// If the root object is missing or damaged, the newest backup that
+/// can be opened is used instead, and the tree is marked as
+/// recovered. The next commit will then overwrite the root object.
 pub(crate) fn open<CustomData>(
-    mut buffer: BlockBuffer,
+    buffer: BlockBuffer,
     backend: Arc<dyn Backend>,
     crypto: Key,
 ) -> Result<RootIndex<CustomData>>
 where
     CustomData: Serialize + DeserializeOwned + Send + Sync + 'static,
 {
-    let root = crypto.root_object_id()?;
+    let root_id = crypto.root_object_id()?;
+    let root = match open_at::<CustomData>(root_id, buffer, backend.clone(), crypto.clone()) {
+        Ok(root) => root,
+        Err(error) if is_damaged(&error) => {
+            let mut newest: Option<RootIndex<CustomData>> = None;
+            for id in backup_root_ids(&crypto)? {
+                let Ok(backup) = open_at::<CustomData>(
+                    id,
+                    BlockBuffer::default(),
+                    backend.clone(),
+                    crypto.clone(),
+                ) else {
+                    continue;
+                };
+
+                if newest
+                    .as_ref()
+                    .is_none_or(|n| *n.generation.read() < *backup.generation.read())
+                {
+                    newest = Some(backup);
+                }
+            }
 
-    let object = backend.read_fresh(&root)?;
-    let header = {
-        let mut sealed_header = [0u8; size_of::<SealedHeader>()];
-        sealed_header.copy_from_slice(object.head(size_of::<SealedHeader>()));
-        crypto.open_root(sealed_header.into())?
+            let Some(mut root) = newest else {
+                return Err(error);
+            };
+
+            // the next commit replaces whatever is in the root object
+            *root.root_head.write() =
+                read_current_head(backend.as_ref(), &root_id)?.map(|head| (root_id, head));
+            root.recovered = Some(RecoveredRoot {
+                commit: root.commit_list.read().last().map(|c| c.id),
+                reason: error.to_string(),
+            });
+            root
+        }
+        Err(error) => return Err(error),
     };
 
+    let objects = root.objects();
+    backend.preload(&objects)?;
+    backend.keep_warm(&objects)?;
+
+    Ok(root)
+}
+
+/// Open the root stored in the object `root_id`, which is either the
+/// root object or one of its backups.
+pub(crate) fn open_at<CustomData>(
+    root_id: ObjectId,
+    mut buffer: BlockBuffer,
+    backend: Arc<dyn Backend>,
+    crypto: Key,
+) -> Result<RootIndex<CustomData>>
+where
+    CustomData: Serialize + DeserializeOwned + Send + Sync + 'static,
+{
+    let object = backend.read_fresh(&root_id)?;
+    let sealed_header = read_head(&object);
+    let header = crypto.open_root(sealed_header.clone())?;
+
     let pool = {
         let backend = backend.clone();
         let key = header.key.index_key()?;
@@ -84,43 +272,75 @@ where
         })
     };
 
-    let (shadow_root, objects, transaction_list) = {
+    let (shadow_root, objects, head) = {
         let (shadow_root, stream_ptr) = parse_transactions_stream(
             &header.root_ptr,
-            root,
+            root_id,
             object.as_inner(),
             &mut buffer,
             pool.lease()?,
         )?;
 
-        let stream_objects = stream_ptr.objects();
+        let mut objects = stream_ptr.objects();
+        objects.sort_unstable_by(|a, b| a.as_ref().cmp(b.as_ref()));
 
         // TODO
         //
         // This stream *might* be stale in the cache. Very likely,
-        // though, it's tightly packed with the root object.
-        //
-        // It would be prudent to ensure that all objects of the
-        // stream are up-to-date.
+        // though, it's tightly packed with the root object, as only
+        // the history since the last checkpoint is stored here.
         //
-        // If you encounter an unexplicable decryption bug when using
-        // caches, you probably have a huge transaction log.
+        // Checkpoints are never rewritten, so they're safe to cache.
         //
         let mut stream = DeserializeStream::new(stream_ptr.open_with_buffer(pool.lease()?, buffer));
+        let head = match stream.read_next::<Format>()? {
+            Format::Versioned(HEAD_VERSION) => stream.read_next::<Head>()?,
+            Format::Versioned(version) => return Err(Error::Unsupported { version }),
+            Format::Legacy(fields) => {
+                // release the only reader of the pool
+                drop(stream);
 
-        (
-            shadow_root,
-            stream_objects,
-            stream.read_next::<TransactionList>()?,
-        )
+                let (head, legacy_objects) = read_legacy::<CustomData>(fields, &pool)?;
+                objects.extend(legacy_objects);
+                objects.sort_unstable_by(|a, b| a.as_ref().cmp(b.as_ref()));
+                objects.dedup();
+                head
+            }
+        };
+
+        (shadow_root, objects, head)
     };
 
-    let mut root = RootIndex::<CustomData>::new(shadow_root, objects, header.key);
-    root.load_all_from(&transaction_list, &pool)?;
+    let tail: Segment<CustomData> = deserialize_from_slice(&head.tail)?;
+    let mut transaction_log = tail.transactions;
+    let mut commit_list = CommitList::new();
+    let mut sealed = Vec::with_capacity(head.checkpoints.len());
 
-    let objects = root.objects();
-    backend.preload(&objects)?;
-    backend.keep_warm(&objects)?;
+    for checkpoint in head.checkpoints.iter() {
+        let mut stream = DeserializeStream::new(checkpoint.stream.open_reader(pool.lease()?));
+        let segment = stream.read_next::<Segment<CustomData>>()?;
+
+        commit_list.extend(segment.commits);
+        sealed.push(segment.transactions);
+
+        if commit_list.len() != checkpoint.commits
+            || commit_list.last().map(|c| c.id) != Some(checkpoint.last)
+        {
+            return Err(anyhow::anyhow!("checkpoint doesn't match the history").into());
+        }
+    }
+
+    commit_list.extend(tail.commits);
+    transaction_log.extend(sealed.into_iter().rev().flatten());
+
+    let root = RootIndex::<CustomData>::new(shadow_root, objects, header.key);
+    *root.root_head.write() = Some((root_id, sealed_header));
+    *root.transaction_log.write() = transaction_log;
+    *root.commit_list.write() = commit_list;
+    *root.refs.write() = head.refs;
+    *root.trusted_writers.write() = head.trusted_writers;
+    *root.checkpoints.write() = head.checkpoints;
+    *root.generation.write() = head.generation;
 
     Ok(root)
 }
@@ -135,43 +355,363 @@ where
     let crypto = index.key.clone();
     let root = crypto.root_object_id()?;
     let index_key = crypto.index_key()?;
+    let generation = *index.generation.read() + 1;
+
+    // hold the lock until the new header is written, so commits
+    // from the same process don't look like conflicts
+    let mut root_head = index.root_head.write();
+    check_conflict(root_head.as_ref(), root, backend.as_ref())?;
+
+    let head = {
+        let tr_log = index.transaction_log.read();
+        let commit_list = index.commit_list.read();
+        let mut checkpoints = index.checkpoints.read().clone();
+
+        // if the history has been rewritten, e.g. by pruning, it's
+        // sealed again from the start
+        if let Some(last) = checkpoints.last() {
+            if !last.is_prefix_of(&tr_log, &commit_list) {
+                checkpoints.clear();
+            }
+        }
+
+        let mut tail = serialize_tail(&tr_log, &commit_list, &checkpoints)?;
+
+        match commit_list.last() {
+            Some(last) if tail.len() >= CHECKPOINT_SIZE => {
+                checkpoints.push(Checkpoint {
+                    commits: commit_list.len(),
+                    transactions: tr_log.len(),
+                    last: last.id,
+                    stream: seal_checkpoint(&tail, backend.clone(), index_key.clone())?,
+                });
+
+                tail = crate::serialize_to_vec(&Segment::<CustomData> {
+                    transactions: vec![],
+                    commits: vec![],
+                })?;
+            }
+            _ => {}
+        }
+
+        Head {
+            generation,
+            checkpoints,
+            tail,
+            refs: index.refs.read().clone(),
+            trusted_writers: index.trusted_writers.read().clone(),
+        }
+    };
+
+    let head_buf = serialize_head(&head)?;
+
+    // the backup is written first, so there's a root that can be
+    // opened even if writing the root object is interrupted
+    //
+    // backups don't reuse any objects, so if the head doesn't fit
+    // into a single object, the rest may be garbage collected
+    let backup = crypto.backup_root_object_id(generation as usize % BACKUP_ROOTS)?;
+    write_root(backup, &head_buf, &crypto, backend.clone(), vec![])?;
+
+    let (shadow_root, stream, sealed_header) = write_root(
+        root,
+        &head_buf,
+        &crypto,
+        backend.clone(),
+        take(&mut index.objects.write()),
+    )?;
+
+    let mut objects_written = stream.objects();
+    objects_written.sort_unstable_by(|a, b| a.as_ref().cmp(b.as_ref()));
+    backend.keep_warm(&objects_written)?;
+    *index.objects.write() = objects_written;
+
+    *index.shadow_root.write() = shadow_root;
+    *root_head = Some((root, sealed_header));
+    *index.checkpoints.write() = head.checkpoints;
+    *index.generation.write() = generation;
+
+    Ok(())
+}
+
+/// Write the current root of `index` into `backend`, which is
+/// expected to have all the objects the root refers to.
+///
+/// Like the backups, the root index is written into new objects, so
+/// a root that's already in `backend` stays intact until the root
+/// object is replaced at the very end. Returns the ids of the objects
+/// that have been written, the root object last.
+///
+/// The caller needs to keep commits out while the objects the root
+/// refers to are copied, and the root is written.
+pub(crate) fn write_copy<CustomData>(
+    index: &RootIndex<CustomData>,
+    backend: Arc<dyn Backend>,
+) -> Result<Vec<ObjectId>>
+where
+    CustomData: Serialize + DeserializeOwned + Send + Sync,
+{
+    let crypto = index.key.clone();
+    let root = crypto.root_object_id()?;
+
+    let head = {
+        let checkpoints = index.checkpoints.read().clone();
+        let tail = serialize_tail(
+            &index.transaction_log.read(),
+            &index.commit_list.read(),
+            &checkpoints,
+        )?;
+
+        Head {
+            generation: *index.generation.read(),
+            checkpoints,
+            tail,
+            refs: index.refs.read().clone(),
+            trusted_writers: index.trusted_writers.read().clone(),
+        }
+    };
+
+    let (shadow_root, stream, _) =
+        write_root(root, &serialize_head(&head)?, &crypto, backend, vec![])?;
+
+    let mut written = stream
+        .objects()
+        .into_iter()
+        .filter(|id| id != &shadow_root)
+        .collect::<Vec<_>>();
+    written.push(root);
+
+    Ok(written)
+}
+
+/// Serialize the history since the last of the `checkpoints` into a
+/// [`Segment`].
+fn serialize_tail<CustomData>(
+    tr_log: &TransactionList,
+    commit_list: &CommitList<CustomData>,
+    checkpoints: &[Checkpoint],
+) -> Result<Vec<u8>>
+where
+    CustomData: Serialize,
+{
+    let (commits, transactions) = checkpoints
+        .last()
+        .map(|c| (c.commits, c.transactions))
+        .unwrap_or_default();
+
+    Ok(crate::serialize_to_vec(&Segment {
+        transactions: tr_log[..tr_log.len() - transactions].to_vec(),
+        commits: commit_list[commits..].to_vec(),
+    })?)
+}
+
+/// Serialize the version of the format, followed by `head`.
+fn serialize_head(head: &Head) -> Result<Vec<u8>> {
+    let mut head_buf = crate::serialize_to_vec(&HEAD_VERSION)?;
+    head_buf.extend(crate::serialize_to_vec(head)?);
+
+    Ok(head_buf)
+}
+
+/// Convert the `fields` of a root that has been written before the
+/// head had a version into a [`Head`].
+///
+/// The whole history is kept in the tail, and gets sealed into
+/// checkpoints on the next commit. Returns the head, and the objects
+/// that contain the fields, which are rewritten on the next commit.
+fn read_legacy<CustomData>(
+    fields: TransactionList,
+    pool: &Pool<AEADReader>,
+) -> Result<(Head, Vec<ObjectId>)>
+where
+    CustomData: Serialize + DeserializeOwned,
+{
+    let mut segment = Segment::<CustomData> {
+        transactions: vec![],
+        commits: vec![],
+    };
+    let mut objects = vec![];
+
+    for (_, name, stream) in fields {
+        let mut reader = DeserializeStream::new(stream.open_reader(pool.lease()?));
+        match name.as_str() {
+            "transaction_log" => segment.transactions = reader.read_next()?,
+            "commit_list" => segment.commits = reader.read_next()?,
+            _ => return Err(anyhow::anyhow!("unknown field in the root: {name}").into()),
+        }
 
+        objects.extend(stream.objects());
+    }
+
+    // without branches, the whole history is on the main line
+    let refs = Refs {
+        main: segment.commits.last().map(|c| c.id),
+        ..Default::default()
+    };
+
+    let head = Head {
+        generation: 0,
+        checkpoints: vec![],
+        tail: crate::serialize_to_vec(&segment)?,
+        refs,
+        trusted_writers: BTreeSet::new(),
+    };
+
+    Ok((head, objects))
+}
+
+/// Write the serialized `head` into the root object `id`, reusing the
+/// `rewrite` objects for anything that doesn't fit.
+///
+/// Returns the shadow id of the root object, the stream of the head,
+/// and the sealed header.
+fn write_root(
+    id: ObjectId,
+    head: &[u8],
+    crypto: &Key,
+    backend: Arc<dyn Backend>,
+    rewrite: Vec<ObjectId>,
+) -> Result<(ObjectId, Stream, SealedHeader)> {
     let mut writer = Pool::new(
         NonZeroUsize::new(1).unwrap(),
         AEADWriter::for_root(
-            backend.clone(),
-            index_key.clone(),
+            backend,
+            crypto.index_key()?,
             size_of::<SealedHeader>() as u64,
-            take(&mut index.objects.write()),
+            rewrite,
         ),
     )?;
 
     let stream = {
         let mut sink = BufferedSink::new(writer.clone());
-
-        let (commit_id, fields) = index.commit(&mut sink, &mut writer, vec![], index_key)?;
-        let transactions = fields
-            .into_iter()
-            .map(|(field, stream)| (commit_id, field, stream))
-            .collect::<TransactionList>();
-
-        crate::serialize_to_writer(&mut sink, &transactions)?;
+        sink.write_all(head)?;
         sink.clear()?
     };
 
-    let objects_written = stream.objects();
-    backend.keep_warm(&objects_written)?;
-    *index.objects.write() = objects_written;
-
     let stream_buf = crate::serialize_to_vec(&stream)?;
     let root_ptr = writer.write(&stream_buf)?.into_raw();
-    *index.shadow_root.write() = root_ptr.object;
 
     // there's only 1 writer in the pool, so this is deterministic
     // this needs to change if saving indexes ever becomes multi-threaded
-    Ok(writer
-        .lease()?
-        .flush_root_head(root, &crypto.seal_root(&root_ptr)?)?)
+    let sealed_header = crypto.seal_root(&root_ptr)?;
+    writer.lease()?.flush_root_head(id, &sealed_header)?;
+
+    Ok((root_ptr.object, stream, sealed_header))
+}
+
+/// The ids of the backups of the root object.
+pub(crate) fn backup_root_ids(crypto: &Key) -> Result<Vec<ObjectId>> {
+    (0..BACKUP_ROOTS)
+        .map(|slot| Ok(crypto.backup_root_object_id(slot)?))
+        .collect()
+}
+
+/// Returns `true` if `error` means that the root object is missing, or
+/// is in a supported format but fails authentication or doesn't
+/// decode.
+///
+/// Roots that are unreachable, written in a newer format, or opened
+/// with a key that doesn't work are not damaged, and shouldn't be
+/// replaced by a backup.
+pub(crate) fn is_damaged(error: &Error) -> bool {
+    match error {
+        Error::Backend { source } => is_missing(source),
+        Error::Crypto { source } => is_authentication(source),
+        Error::Internal {
+            source: ObjectError::Backend { source },
+        } => is_missing(source),
+        Error::Internal {
+            source: ObjectError::Crypto { source },
+        } => is_authentication(source),
+        Error::Anyhow { source } => source.chain().all(|cause| {
+            cause.downcast_ref::<BackendError>().is_none_or(is_missing)
+                && cause
+                    .downcast_ref::<CryptoError>()
+                    .is_none_or(is_authentication)
+        }),
+        Error::Io { .. } | Error::Decode { .. } | Error::Internal { .. } => true,
+        Error::Encode { .. } | Error::Conflict { .. } | Error::Unsupported { .. } => false,
+    }
+}
+
+fn is_missing(error: &BackendError) -> bool {
+    match error {
+        BackendError::NotFound { .. } => true,
+        BackendError::Io { source } => source.kind() == io::ErrorKind::NotFound,
+        _ => false,
+    }
+}
+
+fn is_authentication(error: &CryptoError) -> bool {
+    matches!(error, CryptoError::Authentication)
+}
+
+/// Write a serialized [`Segment`] into new objects, which are never
+/// rewritten by the root.
+fn seal_checkpoint(segment: &[u8], backend: Arc<dyn Backend>, key: IndexKey) -> Result<Stream> {
+    let mut sink = BufferedSink::new(AEADWriter::for_root(
+        backend,
+        key,
+        size_of::<SealedHeader>() as u64,
+        vec![],
+    ));
+    sink.write_all(segment)?;
+
+    Ok(sink.finish()?)
+}
+
+/// Make sure that nobody has written the root object since `root_head`
+/// was read or written by `index`.
+pub(crate) fn check_unchanged<CustomData>(
+    index: &RootIndex<CustomData>,
+    root_head: Option<&(ObjectId, SealedHeader)>,
+    backend: &dyn Backend,
+) -> Result<()>
+where
+    CustomData: Serialize + DeserializeOwned + Send + Sync,
+{
+    check_conflict(root_head, index.key.root_object_id()?, backend)
+}
+
+/// Make sure that nobody else has written the root object since we
+/// last read or wrote it.
+///
+/// Without a previous header, this is a new tree, which must not
+/// overwrite an existing one.
+fn check_conflict(
+    expected: Option<&(ObjectId, SealedHeader)>,
+    root: ObjectId,
+    backend: &dyn Backend,
+) -> Result<()> {
+    let (id, expected) = match expected {
+        Some((id, header)) => (*id, Some(header)),
+        None => (root, None),
+    };
+
+    if read_current_head(backend, &id)?.as_ref() != expected {
+        return Err(ConflictError.into());
+    }
+
+    Ok(())
+}
+
+/// Read the header that's currently in the object `id`, if it exists.
+fn read_current_head(backend: &dyn Backend, id: &ObjectId) -> Result<Option<SealedHeader>> {
+    match backend.read_fresh(id) {
+        Ok(object) => Ok(Some(read_head(&object))),
+        Err(BackendError::NotFound { .. }) => Ok(None),
+        Err(BackendError::Io { source }) if source.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(source) => Err(source.into()),
+    }
+}
+
+pub(super) fn read_head(object: &ReadObject) -> SealedHeader {
+    let mut sealed_header = [0u8; size_of::<SealedHeader>()];
+
+    // a truncated object results in a header that doesn't open
+    let len = object.as_inner().len().min(sealed_header.len());
+    sealed_header[..len].copy_from_slice(object.head(len));
+    sealed_header.into()
 }
 
 fn parse_transactions_stream(
@@ -192,3 +732,275 @@ fn parse_transactions_stream(
 
     Ok((shadow_root, stream))
 }
+
+#[cfg(test)]
+mod test {
+    use super::{backup_root_ids, write_root, BACKUP_ROOTS, CHECKPOINT_SIZE, HEAD_VERSION};
+    use crate::{
+        backends::{test::InMemoryBackend, Backend},
+        crypto::{ICryptoOps, SealedHeader, UsernamePassword},
+        fields::VersionedMap,
+        object::{AEADWriter, BufferedSink, Pool, WriteObject, Writer},
+        tree::{CommitId, VerifyMode},
+        Infinitree,
+    };
+    use serde::Serialize;
+    use serde_with::serde_as;
+    use std::{mem::size_of, num::NonZeroUsize, sync::Arc, time::SystemTime};
+
+    type Tree = Infinitree<VersionedMap<usize, usize>>;
+
+    fn key() -> UsernamePassword {
+        UsernamePassword::with_credentials("root_user".to_string(), "root_password".to_string())
+            .unwrap()
+    }
+
+    fn history(tree: &Tree) -> Vec<(CommitId, String)> {
+        tree.root
+            .transaction_log
+            .read()
+            .iter()
+            .map(|(id, name, _)| (*id, name.clone()))
+            .collect()
+    }
+
+    /// Commit metadata in the format used before merges were
+    /// supported.
+    #[serde_as]
+    #[derive(Serialize)]
+    struct LegacyMetadata {
+        previous: Option<CommitId>,
+        message: Option<String>,
+        #[serde_as(as = "serde_with::TimestampSecondsWithFrac<f64>")]
+        time: SystemTime,
+        custom_data: (),
+    }
+
+    fn legacy_commits(tree: &Tree) -> Vec<(CommitId, LegacyMetadata)> {
+        tree.commit_list()
+            .iter()
+            .map(|commit| {
+                assert!(commit.metadata.merged.is_empty());
+                (
+                    commit.id,
+                    LegacyMetadata {
+                        previous: commit.metadata.previous,
+                        message: commit.metadata.message.clone(),
+                        time: commit.metadata.time,
+                        custom_data: (),
+                    },
+                )
+            })
+            .collect()
+    }
+
+    /// Overwrite the root object with the format used before the
+    /// head had a version, and commits had signatures or merges.
+    fn write_legacy_root(tree: &Tree, backend: Arc<dyn Backend>) {
+        let crypto = tree.root.key.clone();
+        let mut writer = Pool::new(
+            NonZeroUsize::new(1).unwrap(),
+            AEADWriter::for_root(
+                backend,
+                crypto.index_key().unwrap(),
+                size_of::<SealedHeader>() as u64,
+                vec![],
+            ),
+        )
+        .unwrap();
+
+        let stream = {
+            let mut sink = BufferedSink::new(writer.clone());
+
+            crate::serialize_to_writer(&mut sink, &*tree.root.transaction_log.read()).unwrap();
+            let transaction_log = sink.clear().unwrap();
+            crate::serialize_to_writer(&mut sink, &legacy_commits(tree)).unwrap();
+            let commit_list = sink.clear().unwrap();
+
+            let fields = vec![
+                (
+                    CommitId::default(),
+                    "transaction_log".to_string(),
+                    transaction_log,
+                ),
+                (CommitId::default(), "commit_list".to_string(), commit_list),
+            ];
+            crate::serialize_to_writer(&mut sink, &fields).unwrap();
+            sink.clear().unwrap()
+        };
+
+        let stream_buf = crate::serialize_to_vec(&stream).unwrap();
+        let root_ptr = writer.write(&stream_buf).unwrap().into_raw();
+        writer
+            .lease()
+            .unwrap()
+            .flush_root_head(
+                crypto.root_object_id().unwrap(),
+                &crypto.seal_root(&root_ptr).unwrap(),
+            )
+            .unwrap();
+    }
+
+    #[test]
+    fn legacy_root_is_opened() {
+        let backend = InMemoryBackend::shared();
+        let tree = Tree::empty(backend.clone(), key()).unwrap();
+        for i in 0..3 {
+            tree.index().insert(i, i);
+            tree.commit(None).unwrap();
+        }
+
+        let commits = tree.commit_list().iter().map(|c| c.id).collect::<Vec<_>>();
+        write_legacy_root(&tree, backend.clone());
+
+        // without backups, only the legacy root can be opened
+        backend
+            .delete(&backup_root_ids(&tree.root.key).unwrap())
+            .unwrap();
+        drop(tree);
+
+        let tree = Tree::open(backend.clone(), key()).unwrap();
+        assert!(tree.recovered_root().is_none());
+        assert_eq!(
+            tree.commit_list().iter().map(|c| c.id).collect::<Vec<_>>(),
+            commits
+        );
+        tree.load_all().unwrap();
+        assert_eq!(tree.index().len(), 3);
+        assert!(tree.verify(VerifyMode::Full).unwrap().is_ok());
+
+        // the next commit writes the current format
+        tree.index().insert(3, 3);
+        tree.commit(None).unwrap();
+        drop(tree);
+
+        let tree = Tree::open(backend, key()).unwrap();
+        assert_eq!(tree.commit_list().len(), 4);
+        tree.load_all().unwrap();
+        assert_eq!(tree.index().len(), 4);
+    }
+
+    #[test]
+    fn commit_ids_are_unchanged_without_merges() {
+        let tree = Tree::empty(InMemoryBackend::shared(), key()).unwrap();
+        for i in 0..3 {
+            tree.index().insert(i, i);
+            tree.commit(format!("commit {i}")).unwrap();
+        }
+
+        // ids used to be the hash of the metadata and the changeset
+        let chunk_key = tree.root.key.chunk_key().unwrap();
+        for (id, metadata) in legacy_commits(&tree) {
+            let changeset = tree
+                .root
+                .transaction_log
+                .read()
+                .iter()
+                .filter(|(commit, _, _)| commit == &id)
+                .map(|(_, field, stream)| (field.clone(), stream.clone()))
+                .collect::<Vec<_>>();
+
+            let mut hashed_data = crate::serialize_to_vec(&metadata).unwrap();
+            hashed_data.extend(crate::serialize_to_vec(&changeset).unwrap());
+            assert_eq!(CommitId::from_bytes(chunk_key.hash(&hashed_data)), id);
+        }
+    }
+
+    #[test]
+    fn history_is_sealed_into_checkpoints() {
+        let backend = InMemoryBackend::shared();
+        let tree = Tree::empty(backend.clone(), key()).unwrap();
+
+        // every 4 commits fill a checkpoint
+        let message = "x".repeat(CHECKPOINT_SIZE / 4 + 1);
+        for i in 0..9 {
+            tree.index().insert(i, i);
+            tree.commit(message.clone()).unwrap();
+        }
+        assert_eq!(tree.root.checkpoints.read().len(), 2);
+
+        let reopened = Tree::open(backend.clone(), key()).unwrap();
+        reopened.load_all().unwrap();
+        assert_eq!(reopened.root.checkpoints.read().len(), 2);
+        assert_eq!(history(&reopened), history(&tree));
+        assert_eq!(
+            reopened
+                .commit_list()
+                .iter()
+                .map(|c| c.id)
+                .collect::<Vec<_>>(),
+            tree.commit_list().iter().map(|c| c.id).collect::<Vec<_>>()
+        );
+        assert_eq!(reopened.index().len(), 9);
+
+        // pruning rewrites the history, so the checkpoints are dropped
+        reopened.prune(|_| false).unwrap();
+        assert!(reopened.root.checkpoints.read().is_empty());
+
+        let pruned = Tree::open(backend, key()).unwrap();
+        pruned.load_all().unwrap();
+        assert_eq!(pruned.commit_list().len(), 1);
+        assert_eq!(pruned.index().len(), 9);
+    }
+
+    #[test]
+    fn damaged_root_is_recovered_from_backup() {
+        let backend = InMemoryBackend::shared();
+        let tree = Tree::empty(backend.clone(), key()).unwrap();
+        for i in 0..4 {
+            tree.index().insert(i, i);
+            tree.commit(None).unwrap();
+        }
+
+        let commits = tree.commit_list().iter().map(|c| c.id).collect::<Vec<_>>();
+        let root = tree.root.key.root_object_id().unwrap();
+        let backups = backup_root_ids(&tree.root.key).unwrap();
+        let newest = *tree.root.generation.read() as usize % BACKUP_ROOTS;
+        drop(tree);
+
+        let mut damaged = WriteObject::default();
+        damaged.set_id(root);
+        backend.write_object(&damaged).unwrap();
+
+        let tree = Tree::open(backend.clone(), key()).unwrap();
+        assert_eq!(tree.recovered_root().unwrap().commit, Some(commits[3]));
+        tree.load_all().unwrap();
+        assert_eq!(tree.index().len(), 4);
+        drop(tree);
+
+        // without the newest backup, the one before it is used
+        backend.delete(&[backups[newest]]).unwrap();
+
+        let tree = Tree::open(backend.clone(), key()).unwrap();
+        assert_eq!(tree.recovered_root().unwrap().commit, Some(commits[2]));
+        tree.load_all().unwrap();
+        assert_eq!(tree.index().len(), 3);
+
+        // committing replaces the damaged root
+        tree.index().insert(4, 4);
+        tree.commit(None).unwrap();
+        drop(tree);
+
+        let tree = Tree::open(backend, key()).unwrap();
+        assert!(tree.recovered_root().is_none());
+        assert_eq!(tree.commit_list().len(), 4);
+    }
+
+    #[test]
+    fn newer_root_is_not_recovered_from_backup() {
+        let backend = InMemoryBackend::shared();
+        let tree = Tree::empty(backend.clone(), key()).unwrap();
+        tree.index().insert(0, 0);
+        tree.commit(None).unwrap();
+
+        let crypto = tree.root.key.clone();
+        let root = crypto.root_object_id().unwrap();
+        drop(tree);
+
+        let head = crate::serialize_to_vec(&(HEAD_VERSION + 1)).unwrap();
+        write_root(root, &head, &crypto, backend.clone(), vec![]).unwrap();
+
+        let error = Tree::open(backend, key()).err().unwrap();
+        assert!(error.to_string().contains("unsupported root format"));
+    }
+}
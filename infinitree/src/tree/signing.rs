@@ -0,0 +1,318 @@
+use super::{Commit, CommitId, CommitMetadata, CommitSignature, Infinitree};
+use crate::{
+    crypto::{SigningKey, WriterKey},
+    index::Index,
+    Backend, Key,
+};
+use anyhow::{bail, Result};
+use serde::{de::DeserializeOwned, Serialize};
+use std::{
+    collections::{BTreeSet, HashSet},
+    ops::Deref,
+    sync::Arc,
+};
+
+/// Decides which commits a tree accepts, based on their signatures.
+///
+/// Commits that are not accepted are left out when resolving the
+/// [`CommitFilter`](super::CommitFilter), so their changes are never
+/// loaded.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum SignatureCheck {
+    /// Accept every commit, signed or not.
+    #[default]
+    Any,
+
+    /// Only accept commits signed by one of the writers recorded in
+    /// the tree using [`Infinitree::trust_writer`].
+    ///
+    /// This is advisory, and doesn't verify who wrote a commit. The
+    /// recorded writers are stored in the root of the tree, so anyone
+    /// who holds the key of the tree can add their own. It keeps
+    /// cooperating writers from accepting unsigned commits, or ones
+    /// signed by writers that have been distrusted.
+    Recorded,
+
+    /// Only accept commits signed by one of these writers.
+    ///
+    /// The writers recorded in the tree are ignored, so this is the
+    /// only check that verifies the authorship of commits, and the
+    /// choice for readers that don't trust everyone who holds the
+    /// key of the tree.
+    Pinned(BTreeSet<WriterKey>),
+}
+
+/// The signing state of a tree.
+#[derive(Default)]
+pub(super) struct Signatures {
+    key: Option<SigningKey>,
+    check: SignatureCheck,
+
+    /// Commits that have already passed the check.
+    accepted: HashSet<CommitId>,
+}
+
+impl<I: Index + Default, CustomData> Infinitree<I, CustomData>
+where
+    CustomData: Serialize + DeserializeOwned + Send + Sync,
+{
+    /// Load all version information from the tree, and make sure
+    /// that every commit passes `check`.
+    ///
+    /// See [`check_signatures`](Self::check_signatures).
+    pub fn open_verified(
+        backend: Arc<dyn Backend>,
+        key: impl Into<Key>,
+        check: SignatureCheck,
+    ) -> Result<Self> {
+        let tree = Self::open(backend, key)?;
+        tree.check_signatures(check)?;
+        Ok(tree)
+    }
+}
+
+impl<I: Index, CustomData> Infinitree<I, CustomData>
+where
+    CustomData: Serialize + DeserializeOwned + Send + Sync,
+{
+    /// Sign new commits with `key`, or stop signing them if it's
+    /// `None`.
+    ///
+    /// This includes merge commits, and the commits rewritten by
+    /// [`prune`](Self::prune).
+    pub fn sign_commits(&self, key: Option<SigningKey>) {
+        self.signatures.write().key = key;
+    }
+
+    /// Only accept commits that pass `check` from now on.
+    ///
+    /// Returns an error if any commit in the tree doesn't pass. The
+    /// check is in effect regardless, and rejected commits are left
+    /// out when resolving the [`CommitFilter`](super::CommitFilter).
+    pub fn check_signatures(&self, check: SignatureCheck) -> Result<()> {
+        {
+            let mut signatures = self.signatures.write();
+            signatures.check = check;
+            signatures.accepted.clear();
+        }
+
+        for commit in self.commit_list().iter() {
+            if !self.accepts(commit) {
+                bail!("commit {:?} is not signed by a trusted writer", commit.id);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Return the writers that are recorded as trusted in the tree.
+    pub fn trusted_writers(&self) -> impl Deref<Target = BTreeSet<WriterKey>> + '_ {
+        self.root.trusted_writers.read()
+    }
+
+    /// Record `writer` as trusted in the tree, then persist it.
+    ///
+    /// Anyone who holds the key of the tree can do this, so the
+    /// recorded writers are only checked by
+    /// [`SignatureCheck::Recorded`], which is advisory.
+    pub fn trust_writer(&self, writer: WriterKey) -> Result<()> {
+        {
+            // lock the index to keep new commits from interleaving
+            let _index = self.index.write();
+            self.root.trusted_writers.write().insert(writer);
+        }
+
+        self.commit_root()
+    }
+
+    /// Remove `writer` from the trusted writers of the tree, then
+    /// persist the change.
+    pub fn distrust_writer(&self, writer: &WriterKey) -> Result<()> {
+        {
+            let _index = self.index.write();
+            self.root.trusted_writers.write().remove(writer);
+        }
+        self.signatures.write().accepted.clear();
+
+        self.commit_root()
+    }
+
+    /// Sign a new commit, if there's a signing key.
+    pub(super) fn sign(
+        &self,
+        id: &CommitId,
+        metadata: &CommitMetadata<CustomData>,
+    ) -> Result<Option<CommitSignature>> {
+        let signatures = self.signatures.read();
+        let Some(key) = &signatures.key else {
+            return Ok(None);
+        };
+
+        Ok(Some(CommitSignature {
+            writer: key.writer(),
+            signature: key.sign(&signed_message(id, metadata)?),
+        }))
+    }
+
+    /// Return `true` if `commit` passes the signature check.
+    pub(super) fn accepts(&self, commit: &Commit<CustomData>) -> bool {
+        let verified = {
+            let signatures = self.signatures.read();
+            if signatures.accepted.contains(&commit.id) {
+                return true;
+            }
+
+            let Some(signature) = &commit.signature else {
+                return signatures.check == SignatureCheck::Any;
+            };
+
+            let trusted = match &signatures.check {
+                SignatureCheck::Any => return true,
+                SignatureCheck::Recorded => {
+                    self.root.trusted_writers.read().contains(&signature.writer)
+                }
+                SignatureCheck::Pinned(writers) => writers.contains(&signature.writer),
+            };
+
+            // the signature only covers the changes through the id
+            trusted
+                && self.has_valid_id(commit)
+                && signed_message(&commit.id, &commit.metadata)
+                    .map(|message| signature.writer.verify(&message, &signature.signature))
+                    .unwrap_or(false)
+        };
+
+        if verified {
+            self.signatures.write().accepted.insert(commit.id);
+        }
+
+        verified
+    }
+
+    /// Return `true` if the id of `commit` matches its metadata and
+    /// the changes recorded for it.
+    fn has_valid_id(&self, commit: &Commit<CustomData>) -> bool {
+        // the log keeps the changes of a commit in their original order
+        let changeset = self
+            .root
+            .transaction_log
+            .read()
+            .iter()
+            .filter(|(id, _, _)| id == &commit.id)
+            .map(|(_, field, stream)| (field.clone(), stream.clone()))
+            .collect::<Vec<_>>();
+
+        self.commit_id(&commit.metadata, &changeset)
+            .is_ok_and(|id| id == commit.id)
+    }
+}
+
+/// The message that's signed for a commit.
+fn signed_message<CustomData: Serialize>(
+    id: &CommitId,
+    metadata: &CommitMetadata<CustomData>,
+) -> Result<Vec<u8>> {
+    Ok(crate::serialize_to_vec(&(id, metadata))?)
+}
+
+#[cfg(test)]
+mod test {
+    use super::SignatureCheck;
+    use crate::{
+        backends::test::InMemoryBackend,
+        crypto::{SigningKey, UsernamePassword},
+        fields::VersionedMap,
+        Index, Infinitree,
+    };
+
+    #[derive(Index, Default)]
+    struct Counters {
+        map: VersionedMap<usize, usize>,
+    }
+
+    fn key() -> UsernamePassword {
+        UsernamePassword::with_credentials(
+            "signing_user".to_string(),
+            "signing_password".to_string(),
+        )
+        .unwrap()
+    }
+
+    fn signing_key() -> SigningKey {
+        SigningKey::from_pkcs8(&SigningKey::generate_pkcs8().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn reject_untrusted_writers() {
+        let backend = InMemoryBackend::shared();
+        let trusted = signing_key();
+        let writer = trusted.writer();
+
+        let tree = Infinitree::<Counters>::empty(backend.clone(), key()).unwrap();
+        tree.sign_commits(Some(trusted));
+        tree.index().map.insert(1, 1);
+        tree.commit("trusted").unwrap();
+        tree.trust_writer(writer).unwrap();
+
+        // someone else with the key of the tree
+        tree.sign_commits(Some(signing_key()));
+        tree.index().map.insert(2, 2);
+        tree.commit("forged").unwrap();
+        drop(tree);
+
+        let tree = Infinitree::<Counters>::open(backend.clone(), key()).unwrap();
+        assert!(tree.commit_list()[0].signature.is_some());
+        assert_eq!(tree.trusted_writers().len(), 1);
+        drop(tree);
+
+        assert!(Infinitree::<Counters>::open_verified(
+            backend.clone(),
+            key(),
+            SignatureCheck::Recorded
+        )
+        .is_err());
+
+        // the forged commit is never loaded
+        let tree = Infinitree::<Counters>::open(backend.clone(), key()).unwrap();
+        assert!(tree
+            .check_signatures(SignatureCheck::Pinned([writer].into()))
+            .is_err());
+        tree.load_all().unwrap();
+        assert_eq!(*tree.index().map.get(&1).unwrap(), 1);
+        assert_eq!(tree.index().map.get(&2), None);
+    }
+
+    #[test]
+    fn reject_swapped_changes() {
+        let backend = InMemoryBackend::shared();
+        let signer = signing_key();
+        let writer = signer.writer();
+
+        let tree = Infinitree::<Counters>::empty(backend.clone(), key()).unwrap();
+        tree.sign_commits(Some(signer));
+        for i in 0..2 {
+            tree.index().map.insert(i, i);
+            tree.commit(None).unwrap();
+        }
+        assert!(tree
+            .check_signatures(SignatureCheck::Pinned([writer].into()))
+            .is_ok());
+
+        // keep the signed ids, but swap the changes of the commits
+        {
+            let mut tr_log = tree.root.transaction_log.write();
+            let first = tr_log[0].2.clone();
+            tr_log[0].2 = tr_log[1].2.clone();
+            tr_log[1].2 = first;
+        }
+        tree.commit_root().unwrap();
+        drop(tree);
+
+        assert!(Infinitree::<Counters>::open_verified(
+            backend,
+            key(),
+            SignatureCheck::Pinned([writer].into())
+        )
+        .is_err());
+    }
+}
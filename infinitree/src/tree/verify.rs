@@ -0,0 +1,428 @@
+use super::{sealed_root, CommitId, Infinitree};
+use crate::{
+    backends::BackendError,
+    crypto::CryptoError,
+    index::{Field, Index},
+    object::{BlockBuffer, ObjectError, Reader},
+    ObjectId,
+};
+use anyhow::Result;
+use serde::{de::DeserializeOwned, Serialize};
+use std::{collections::HashSet, io};
+
+/// Controls how much data [`Infinitree::verify`] will read.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum VerifyMode {
+    /// Only check the index of the tree.
+    Index,
+    /// Also check every chunk the index references, such as the
+    /// values of a [`SparseField`](crate::fields::SparseField), or
+    /// [`ChunkPointer`](crate::ChunkPointer)s stored in a field.
+    Full,
+}
+
+/// The result of an integrity check.
+#[derive(Clone, Debug, Default)]
+pub struct VerifyReport {
+    /// The number of commits that have been checked.
+    pub commits: usize,
+
+    /// The number of chunks that have been read and authenticated.
+    pub chunks: usize,
+
+    /// Objects that are referenced by the tree, but not found in the
+    /// backend.
+    pub missing_objects: Vec<ObjectId>,
+
+    /// Objects that contain chunks that failed authentication, which
+    /// means they have been corrupted or tampered with.
+    pub mac_failures: Vec<ObjectId>,
+
+    /// Objects that contain chunks that have been authenticated, but
+    /// can't be decompressed.
+    pub corrupt_objects: Vec<ObjectId>,
+
+    /// Fields whose records can't be decoded in a commit, as
+    /// `(commit, field)` pairs.
+    pub undecodable: Vec<(CommitId, Field)>,
+
+    /// Commits with a parent that's not in the history, as `(commit,
+    /// parent)` pairs.
+    pub broken_links: Vec<(CommitId, CommitId)>,
+
+    /// Commits whose id doesn't match their metadata and changes.
+    pub invalid_ids: Vec<CommitId>,
+}
+
+impl VerifyReport {
+    /// Returns `true` if no problems have been found.
+    pub fn is_ok(&self) -> bool {
+        self.missing_objects.is_empty()
+            && self.mac_failures.is_empty()
+            && self.corrupt_objects.is_empty()
+            && self.undecodable.is_empty()
+            && self.broken_links.is_empty()
+            && self.invalid_ids.is_empty()
+    }
+
+    /// Record a failed read of a chunk in `object`.
+    ///
+    /// Errors that are not caused by the stored data, e.g. a failing
+    /// connection to the backend, are returned instead.
+    fn record_failure(&mut self, error: ObjectError, object: ObjectId) -> Result<()> {
+        match error {
+            ObjectError::Backend { source } => self.record_backend_failure(source, object)?,
+            ObjectError::Crypto {
+                source: CryptoError::Authentication,
+            } => push_unique(&mut self.mac_failures, object),
+            ObjectError::Decompress { .. } => push_unique(&mut self.corrupt_objects, object),
+            error => return Err(error.into()),
+        }
+
+        Ok(())
+    }
+
+    fn record_backend_failure(&mut self, error: BackendError, object: ObjectId) -> Result<()> {
+        match error {
+            BackendError::NotFound { id } => push_unique(&mut self.missing_objects, id),
+            BackendError::Io { source } if source.kind() == io::ErrorKind::NotFound => {
+                push_unique(&mut self.missing_objects, object)
+            }
+            error => return Err(error.into()),
+        }
+
+        Ok(())
+    }
+}
+
+fn push_unique<T: PartialEq>(list: &mut Vec<T>, item: T) {
+    if !list.contains(&item) {
+        list.push(item);
+    }
+}
+
+impl<I: Index, CustomData> Infinitree<I, CustomData>
+where
+    CustomData: Serialize + DeserializeOwned + Send + Sync + 'static,
+{
+    /// Check the integrity of the tree end to end.
+    ///
+    /// The sealed root is read and opened again from the backend,
+    /// then every index chunk in the transaction log is decrypted,
+    /// and every record is decoded. In [`VerifyMode::Full`], the
+    /// chunks referenced by the records are read from storage, too.
+    ///
+    /// The id of every commit is recalculated, and all of their
+    /// parents need to be part of the history.
+    ///
+    /// Problems with the stored data are collected in the returned
+    /// [`VerifyReport`], while other errors, such as a failing
+    /// backend, abort the check.
+    ///
+    /// Every version of every field in the index will be read, so
+    /// this may be an expensive operation on large trees.
+    pub fn verify(&self, mode: VerifyMode) -> Result<VerifyReport> {
+        let mut report = VerifyReport::default();
+
+        self.verify_root(&mut report)?;
+        self.verify_commits(&mut report)?;
+        self.verify_transactions(mode, &mut report)?;
+
+        Ok(report)
+    }
+
+    fn verify_root(&self, report: &mut VerifyReport) -> Result<()> {
+        let root = self.root.key.root_object_id()?;
+        // backups are not considered, so a damaged root is reported
+        let opened = sealed_root::open_at::<CustomData>(
+            root,
+            BlockBuffer::default(),
+            self.backend.clone(),
+            self.root.key.clone(),
+        );
+
+        match opened {
+            Ok(_) => {}
+            Err(sealed_root::Error::Backend { source }) => {
+                report.record_backend_failure(source, root)?
+            }
+            Err(sealed_root::Error::Internal { source }) => report.record_failure(source, root)?,
+            Err(sealed_root::Error::Crypto { .. }) => push_unique(&mut report.mac_failures, root),
+            Err(sealed_root::Error::Decode { .. } | sealed_root::Error::Anyhow { .. }) => {
+                push_unique(&mut report.corrupt_objects, root)
+            }
+            Err(error) => return Err(error.into()),
+        }
+
+        Ok(())
+    }
+
+    fn verify_commits(&self, report: &mut VerifyReport) -> Result<()> {
+        let commits = self.commit_list();
+        let tr_log = self.root.transaction_log.read();
+        let ids = commits.iter().map(|c| c.id).collect::<HashSet<_>>();
+
+        for commit in commits.iter() {
+            report.commits += 1;
+
+            for parent in commit.metadata.parents() {
+                if !ids.contains(parent) {
+                    report.broken_links.push((commit.id, *parent));
+                }
+            }
+
+            // the log keeps the changes of a commit in their original order
+            let changeset = tr_log
+                .iter()
+                .filter(|(id, _, _)| id == &commit.id)
+                .map(|(_, field, stream)| (field.clone(), stream.clone()))
+                .collect::<Vec<_>>();
+
+            if self.commit_id(&commit.metadata, &changeset)? != commit.id {
+                report.invalid_ids.push(commit.id);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn verify_transactions(&self, mode: VerifyMode, report: &mut VerifyReport) -> Result<()> {
+        let transaction_log = self.root.transaction_log.read().clone();
+        let mut fields = self.index.read().walk_all()?;
+
+        let mut reader = self.chunk_reader()?;
+        let mut buffer = BlockBuffer::default();
+        let mut referenced = HashSet::new();
+
+        for (commit, name, stream) in transaction_log.iter() {
+            let mut readable = true;
+            for chunk in stream.chunks() {
+                match reader.read_chunk(chunk, buffer.as_mut()) {
+                    Ok(_) => report.chunks += 1,
+                    Err(error) => {
+                        readable = false;
+                        report.record_failure(error, *chunk.object_id())?;
+                    }
+                }
+            }
+
+            let transactions = vec![(*commit, name.clone(), stream.clone())];
+            let field = fields.iter_mut().find(|f| &f.name == name);
+            let Some(field) = field.filter(|_| readable) else {
+                report.undecodable.push((*commit, name.clone()));
+                continue;
+            };
+
+            let decoded = field.strategy.walk(
+                self.reader_pool.clone(),
+                transactions.clone(),
+                false,
+                &mut |ptr| {
+                    if mode == VerifyMode::Full {
+                        referenced.insert(ptr.clone());
+                    }
+                },
+            );
+
+            if decoded.is_err() {
+                report.undecodable.push((*commit, name.clone()));
+                continue;
+            }
+
+            if mode == VerifyMode::Index {
+                continue;
+            }
+
+            // the chunks of values that can't be read are reported
+            // with the rest of the referenced chunks below
+            let loaded =
+                field
+                    .strategy
+                    .walk(self.reader_pool.clone(), transactions, true, &mut |ptr| {
+                        referenced.insert(ptr.clone());
+                    });
+
+            match loaded.map_err(|error| error.downcast::<ObjectError>()) {
+                Ok(()) => {}
+                Err(Ok(ObjectError::Deserialize { .. })) => {
+                    report.undecodable.push((*commit, name.clone()))
+                }
+                Err(Ok(_)) => {}
+                Err(Err(error)) => return Err(error),
+            }
+        }
+
+        if mode == VerifyMode::Index {
+            return Ok(());
+        }
+
+        // sparse fields are stored with the index key, everything else
+        // is expected to be written through a storage writer
+        let mut storage = self.storage_reader()?;
+        for ptr in referenced {
+            let result = match reader.read_chunk(&ptr, buffer.as_mut()) {
+                Err(ObjectError::Crypto { .. }) => storage.read_chunk(&ptr, buffer.as_mut()),
+                result => result,
+            };
+
+            match result {
+                Ok(_) => report.chunks += 1,
+                Err(error) => report.record_failure(error, *ptr.object_id())?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::VerifyMode;
+    use crate::{
+        backends::test::InMemoryBackend,
+        crypto::UsernamePassword,
+        fields::{Serialized, VersionedMap},
+        object::{WriteObject, Writer},
+        tree::{Commit, CommitId, CommitMetadata, GcMode},
+        Backend, ChunkPointer, Index, Infinitree,
+    };
+    use std::{collections::HashSet, sync::Arc};
+
+    #[derive(Index, Default)]
+    #[infinitree(walk)]
+    struct Files {
+        files: VersionedMap<String, ChunkPointer>,
+        counter: Serialized<usize>,
+    }
+
+    #[derive(Index, Default)]
+    #[infinitree(walk)]
+    struct Sparse {
+        #[infinitree(strategy = "crate::fields::SparseField")]
+        files: VersionedMap<String, ChunkPointer>,
+    }
+
+    fn key() -> UsernamePassword {
+        UsernamePassword::with_credentials("verify_user".to_string(), "verify_password".to_string())
+            .unwrap()
+    }
+
+    #[test]
+    fn verify_tree() {
+        let backend = InMemoryBackend::shared();
+
+        let (first, second) = {
+            let tree = Infinitree::<Files>::empty(backend.clone(), key()).unwrap();
+
+            let mut writer = tree.storage_writer().unwrap();
+            let first = writer.write(b"first").unwrap();
+            writer.flush().unwrap();
+            tree.index().files.insert("first".into(), first.clone());
+            *tree.index().counter.write() = 1;
+            tree.commit(None).unwrap();
+
+            let mut writer = tree.storage_writer().unwrap();
+            let second = writer.write(b"second").unwrap();
+            writer.flush().unwrap();
+            tree.index().files.insert("second".into(), second.clone());
+            *tree.index().counter.write() = 2;
+            tree.commit(None).unwrap();
+
+            (first, second)
+        };
+
+        let tree = Infinitree::<Files>::open(backend.clone(), key()).unwrap();
+        let report = tree.verify(VerifyMode::Full).unwrap();
+        assert!(report.is_ok(), "{report:?}");
+        assert_eq!(report.commits, 2);
+
+        // storage chunks are only checked in full mode
+        backend.delete(&[*first.object_id()]).unwrap();
+        assert!(tree.verify(VerifyMode::Index).unwrap().is_ok());
+
+        let report = tree.verify(VerifyMode::Full).unwrap();
+        assert_eq!(report.missing_objects, vec![*first.object_id()]);
+
+        // flip a bit in the second chunk
+        let original = backend.read_object(second.object_id()).unwrap();
+        let mut tampered = WriteObject::default();
+        tampered.set_id(*second.object_id());
+        tampered.as_inner_mut().copy_from_slice(original.as_inner());
+        tampered.as_inner_mut()[second.as_raw().offs as usize] ^= 1;
+        backend.write_object(&tampered).unwrap();
+
+        let report = tree.verify(VerifyMode::Full).unwrap();
+        assert_eq!(report.mac_failures, vec![*second.object_id()]);
+
+        // history that's been rewritten behind our back
+        let (last, missing) = {
+            let mut commits = tree.root.commit_list.write();
+            let last = commits[1].id;
+            let missing = CommitId::from_bytes([1; 32]);
+            commits[1] = Arc::new(Commit {
+                id: last,
+                metadata: CommitMetadata {
+                    previous: Some(missing),
+                    ..Default::default()
+                },
+                signature: None,
+            });
+            (last, missing)
+        };
+
+        let report = tree.verify(VerifyMode::Index).unwrap();
+        assert_eq!(report.invalid_ids, vec![last]);
+        assert_eq!(report.broken_links, vec![(last, missing)]);
+    }
+
+    #[test]
+    fn missing_sparse_value() {
+        let backend = InMemoryBackend::shared();
+        let tree = Infinitree::<Sparse>::empty(backend.clone(), key()).unwrap();
+
+        let mut writer = tree.storage_writer().unwrap();
+        let file = writer.write(b"file").unwrap();
+        writer.flush().unwrap();
+        tree.index().files.insert("file".into(), file.clone());
+        tree.commit(None).unwrap();
+
+        // the value is the only thing in its object that's not part
+        // of the index
+        let index = tree
+            .root
+            .transaction_log
+            .read()
+            .iter()
+            .flat_map(|(_, _, stream)| stream.objects())
+            .chain(tree.root.objects.read().iter().copied())
+            .collect::<HashSet<_>>();
+        let mut referenced = vec![];
+        tree.index()
+            .walk_all()
+            .unwrap()
+            .remove(0)
+            .strategy
+            .walk(
+                tree.reader_pool.clone(),
+                tree.root.transaction_log.read().clone(),
+                false,
+                &mut |ptr| referenced.push(*ptr.object_id()),
+            )
+            .unwrap();
+        let value = referenced
+            .into_iter()
+            .find(|id| id != file.object_id() && !index.contains(id))
+            .unwrap();
+        backend.delete(&[value]).unwrap();
+
+        let tree = Infinitree::<Sparse>::open(backend, key()).unwrap();
+        assert!(tree.verify(VerifyMode::Index).unwrap().is_ok());
+
+        let report = tree.verify(VerifyMode::Full).unwrap();
+        assert_eq!(report.missing_objects, vec![value]);
+        assert!(report.undecodable.is_empty());
+
+        // nothing can be collected safely without the value
+        assert!(tree.gc(GcMode::DryRun).is_err());
+    }
+}
@@ -0,0 +1,272 @@
+use super::{
+    ArchiveContents, Change, Commit, CommitFilter, CommitId, CommitList, Infinitree, Refs,
+    Subscription, VerifyMode, VerifyReport,
+};
+use crate::{
+    backends::{Backend, BackendError, ObjectList},
+    crypto::WriteProtected,
+    fields::{Collection, Intent, Load, Query, QueryAction},
+    object::{AEADReader, ObjectId, PoolRef, ReadObject, WriteObject},
+    Index, Key,
+};
+use anyhow::Result;
+use serde::{de::DeserializeOwned, Serialize};
+use std::{hash::Hash, io::Write, ops::Deref, sync::Arc, time::Duration};
+
+/// A handle to an [`Infinitree`] that can't commit or reseal it.
+///
+/// The handle doesn't offer any methods that would change the tree,
+/// and it never writes to the backend, not even when it's dropped.
+///
+/// The key is wrapped in [`WriteProtected`], so even the internals of
+/// the tree can't write to it by accident.
+///
+/// This is not an access control, as the holder of the key can always
+/// open the tree for writing with the key it wraps. Processes that
+/// must not write need read-only access to the backend instead.
+pub struct InfinitreeView<I, CustomData = ()>
+where
+    CustomData: Serialize + DeserializeOwned + Send + Sync + 'static,
+{
+    tree: Infinitree<I, CustomData>,
+}
+
+impl<I: Index + Default, CustomData> InfinitreeView<I, CustomData>
+where
+    CustomData: Serialize + DeserializeOwned + Send + Sync + 'static,
+{
+    /// Load all version information from the tree.
+    ///
+    /// See [`Infinitree::open`].
+    pub fn open(backend: Arc<dyn Backend>, key: impl Into<Key>) -> Result<Self> {
+        let backend = Arc::new(NoWrites(backend));
+        let key = WriteProtected::new(key);
+
+        Ok(Self {
+            tree: Infinitree::open(backend, key)?,
+        })
+    }
+}
+
+impl<I: Index, CustomData> InfinitreeView<I, CustomData>
+where
+    CustomData: Serialize + DeserializeOwned + Send + Sync + 'static,
+{
+    /// Return an immutable reference to the internal index.
+    ///
+    /// See [`Infinitree::index`].
+    pub fn index(&self) -> impl Deref<Target = I> + '_ {
+        self.tree.index()
+    }
+
+    /// Load into memory all fields for the selected version ranges.
+    pub fn load_all(&self) -> Result<()> {
+        self.tree.load_all()
+    }
+
+    /// Load the field for the selected generation set.
+    pub fn load(&self, field: impl Into<Intent<Box<dyn Load>>>) -> Result<()> {
+        self.tree.load(field)
+    }
+
+    /// Load into memory all data from `field` where `pred` returns `true`.
+    pub fn query<K>(
+        &self,
+        field: Intent<Box<impl Query<Key = K>>>,
+        pred: impl Fn(&K) -> QueryAction,
+    ) -> Result<()> {
+        self.tree.query(field, pred)
+    }
+
+    /// Same as [`query`][Self::query], but returns an `Iterator`.
+    pub fn iter<'a, K, O, Q>(
+        &'a self,
+        field: Intent<Box<Q>>,
+        pred: impl Fn(&K) -> QueryAction + Send + Sync + 'static,
+    ) -> Result<impl Iterator<Item = O> + Send + Sync + 'a>
+    where
+        for<'de> Q::Serialized: serde::Deserialize<'de>,
+        Q: Collection<Key = K, Item = O> + Send + Sync + 'static,
+        K: Eq + Hash + Clone + Send + Sync + 'a,
+    {
+        self.tree.iter(field, pred)
+    }
+
+    /// Pick up the commits that have been made since the tree was
+    /// opened or last refreshed.
+    ///
+    /// See [`Infinitree::refresh`].
+    pub fn refresh(&self) -> Result<Vec<Arc<Commit<CustomData>>>> {
+        self.tree.refresh()
+    }
+
+    /// Watch the tree for new commits.
+    ///
+    /// See [`Infinitree::subscribe`].
+    pub fn subscribe(&self, interval: Duration) -> Result<Subscription<CustomData>> {
+        self.tree.subscribe(interval)
+    }
+
+    /// Return all generations in the tree.
+    pub fn commit_list(&self) -> impl Deref<Target = CommitList<CustomData>> + '_ {
+        self.tree.commit_list()
+    }
+
+    /// Only run persistence query operations on the selected
+    /// generations.
+    ///
+    /// See [`Infinitree::filter_commits`].
+    pub fn filter_commits(&self, version: CommitFilter<CustomData>) {
+        self.tree.filter_commits(version)
+    }
+
+    /// The branches and tags of the tree.
+    pub fn refs(&self) -> impl Deref<Target = Refs> + '_ {
+        self.tree.refs()
+    }
+
+    /// Read the history of `branch`, or the entire history if it's
+    /// `None`.
+    ///
+    /// See [`Infinitree::checkout`].
+    pub fn checkout(&self, branch: Option<String>) {
+        self.tree.checkout(branch)
+    }
+
+    /// Return a handle for an object reader.
+    ///
+    /// See [`Infinitree::storage_reader`].
+    pub fn storage_reader(&self) -> Result<PoolRef<AEADReader>> {
+        self.tree.storage_reader()
+    }
+
+    /// List the changes of a field between two commits.
+    ///
+    /// See [`Infinitree::diff`].
+    pub fn diff<K, V, Q>(
+        &self,
+        field: Intent<Box<Q>>,
+        from: &CommitId,
+        to: &CommitId,
+    ) -> Result<impl Iterator<Item = Result<Change<K, V>>>>
+    where
+        Q: Collection<Key = K, Item = (K, Option<Arc<V>>)>,
+        K: Eq + Hash + Clone,
+        V: Serialize,
+    {
+        self.tree.diff(field, from, to)
+    }
+
+    /// Check the integrity of the tree.
+    ///
+    /// See [`Infinitree::verify`].
+    pub fn verify(&self, mode: VerifyMode) -> Result<VerifyReport> {
+        self.tree.verify(mode)
+    }
+
+    /// Pack the objects of the tree into a single archive.
+    ///
+    /// See [`Infinitree::export`].
+    pub fn export(
+        &self,
+        commits: CommitFilter<CustomData>,
+        writer: impl Write,
+    ) -> Result<ArchiveContents> {
+        self.tree.export(commits, writer)
+    }
+
+    /// Return the backend.
+    ///
+    /// Any attempt to write to the returned backend will fail.
+    pub fn backend(&self) -> Arc<dyn Backend> {
+        self.tree.backend()
+    }
+}
+
+/// Refuses to change the underlying backend.
+struct NoWrites(Arc<dyn Backend>);
+
+impl Backend for NoWrites {
+    fn write_object(&self, _object: &WriteObject) -> crate::backends::Result<()> {
+        Err(BackendError::Unsupported)
+    }
+
+    fn read_object(&self, id: &ObjectId) -> crate::backends::Result<Arc<ReadObject>> {
+        self.0.read_object(id)
+    }
+
+    fn preload(&self, objects: &[ObjectId]) -> crate::backends::Result<()> {
+        self.0.preload(objects)
+    }
+
+    fn delete(&self, _objects: &[ObjectId]) -> crate::backends::Result<()> {
+        Err(BackendError::Unsupported)
+    }
+
+    fn read_fresh(&self, id: &ObjectId) -> crate::backends::Result<Arc<ReadObject>> {
+        self.0.read_fresh(id)
+    }
+
+    fn keep_warm(&self, objects: &[ObjectId]) -> crate::backends::Result<()> {
+        self.0.keep_warm(objects)
+    }
+
+    fn list_objects(&self) -> crate::backends::Result<ObjectList<'_>> {
+        self.0.list_objects()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::InfinitreeView;
+    use crate::{
+        backends::{test::InMemoryBackend, Backend},
+        crypto::{UsernamePassword, WriteProtected},
+        fields::VersionedMap,
+        object::WriteObject,
+        Index, Infinitree,
+    };
+
+    #[derive(Index, Default)]
+    struct Counters {
+        map: VersionedMap<usize, usize>,
+    }
+
+    fn key() -> UsernamePassword {
+        UsernamePassword::with_credentials("view_user".to_string(), "view_password".to_string())
+            .unwrap()
+    }
+
+    #[test]
+    fn view_tree() {
+        let backend = InMemoryBackend::shared();
+        {
+            let tree = Infinitree::<Counters>::empty(backend.clone(), key()).unwrap();
+            tree.index().map.insert(1, 1);
+            tree.commit("first").unwrap();
+        }
+        let objects = backend.list_objects().unwrap().count();
+
+        let tree = InfinitreeView::<Counters>::open(backend.clone(), key()).unwrap();
+        tree.load_all().unwrap();
+        assert_eq!(*tree.index().map.get(&1).unwrap(), 1);
+        assert_eq!(tree.commit_list().len(), 1);
+        assert!(tree
+            .backend()
+            .write_object(&WriteObject::default())
+            .is_err());
+        drop(tree);
+
+        // a write-protected key can open, but not change the tree
+        let tree =
+            Infinitree::<Counters>::open(backend.clone(), WriteProtected::new(key())).unwrap();
+        tree.load_all().unwrap();
+        tree.index().map.insert(2, 2);
+        assert!(tree.commit("second").is_err());
+        assert!(tree.reseal().is_err());
+        assert!(tree.storage_writer().is_err());
+        drop(tree);
+
+        assert_eq!(backend.list_objects().unwrap().count(), objects);
+    }
+}
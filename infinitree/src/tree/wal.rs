@@ -0,0 +1,779 @@
+use super::{CommitId, Infinitree};
+use crate::{
+    chunks::RawChunkPointer,
+    crypto::{ChunkKey, ICryptoOps, SystemRandom, Tag},
+    fields::JournalLog,
+    index::{Field, Index},
+    ChunkPointer, ObjectId,
+};
+use anyhow::{anyhow, bail, Context, Result};
+use parking_lot::Mutex;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_with::serde_as;
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    mem::size_of,
+    path::PathBuf,
+    sync::Arc,
+};
+
+const MAGIC: &[u8; 16] = b"infinitree-wal\0\x01";
+
+/// Every record is preceded by its length, the id its key is derived
+/// from, and the authentication tag.
+const FRAME_HEADER: usize = size_of::<u32>() + size_of::<ObjectId>() + size_of::<Tag>();
+
+/// What to do with the changes in a write-ahead log that were made
+/// on top of a different commit than the current head.
+///
+/// This happens if the tree has moved on since the changes were
+/// logged, e.g. because the log was opened after a
+/// [`refresh`](Infinitree::refresh) that picked up commits of
+/// other writers.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ReplayMode {
+    /// Return an error, and leave the log alone.
+    #[default]
+    Strict,
+
+    /// Replay the changes on top of the current head.
+    ///
+    /// Every logged change is applied again, so later changes to the
+    /// same keys win over the ones in the current head.
+    Rebase,
+
+    /// Throw the logged changes away, and start a new log.
+    Discard,
+}
+
+/// A local file that keeps the changes of the index that haven't
+/// been committed yet.
+pub(super) struct WriteAheadLog {
+    path: PathBuf,
+    crypto: ChunkKey,
+    file: Mutex<LogFile>,
+}
+
+#[derive(Default)]
+struct LogFile {
+    file: Option<File>,
+
+    /// Set if a change couldn't be written, after which nothing is
+    /// written until the log is started again.
+    error: Option<anyhow::Error>,
+}
+
+#[serde_as]
+#[derive(Serialize, Deserialize)]
+enum Record {
+    /// The log has been started on top of this commit.
+    Head(Option<CommitId>),
+
+    /// Records written by a field into its journal.
+    Change {
+        field: Field,
+        #[serde_as(as = "serde_with::Bytes")]
+        records: Vec<u8>,
+    },
+
+    /// The changes logged before this are being committed with this
+    /// id.
+    Committing(CommitId),
+}
+
+impl WriteAheadLog {
+    /// Atomically replace the contents of the log with a new log that
+    /// starts at `head` with the `changes`, then keep adding changes
+    /// to it.
+    fn start(&self, head: Option<CommitId>, changes: Vec<(Field, Vec<u8>)>) -> Result<()> {
+        let mut log = self.file.lock();
+        self.replace(&mut log, head, changes)
+    }
+
+    /// Start the log again on top of `head`, with the changes in it
+    /// that haven't been committed.
+    fn restart(&self, head: Option<CommitId>, committed: impl Fn(&CommitId) -> bool) -> Result<()> {
+        let mut log = self.file.lock();
+        let (_, changes) = uncommitted(self.read()?, committed);
+        self.replace(&mut log, head, changes)
+    }
+
+    fn replace(
+        &self,
+        log: &mut LogFile,
+        head: Option<CommitId>,
+        changes: Vec<(Field, Vec<u8>)>,
+    ) -> Result<()> {
+        log.file = None;
+
+        let temp = self.path.with_extension("tmp");
+        let mut file = File::create(&temp)?;
+        file.write_all(MAGIC)?;
+        file.write_all(&seal(&self.crypto, &Record::Head(head))?)?;
+        for (field, records) in changes {
+            file.write_all(&seal(&self.crypto, &Record::Change { field, records })?)?;
+        }
+        file.sync_all()?;
+        fs::rename(&temp, &self.path)?;
+
+        log.file = Some(OpenOptions::new().append(true).open(&self.path)?);
+        log.error = None;
+        Ok(())
+    }
+
+    /// Add `record` to the end of the log.
+    fn append(&self, record: &Record) -> Result<()> {
+        let mut log = self.file.lock();
+        if let Some(error) = &log.error {
+            bail!("the write-ahead log couldn't be written: {error:#}");
+        }
+
+        let Some(file) = log.file.as_mut() else {
+            bail!("the write-ahead log hasn't been started");
+        };
+        let written = seal(&self.crypto, record).and_then(|frame| Ok(file.write_all(&frame)?));
+
+        if let Err(error) = &written {
+            log.error = Some(anyhow!("{error:#}"));
+        }
+        written
+    }
+
+    /// Add the `records` of `field` to the end of the log.
+    ///
+    /// Errors, including records that couldn't be serialized, are
+    /// kept until the log is synced.
+    fn change(&self, field: &Field, records: Result<&[u8]>) {
+        match records {
+            Ok(records) => {
+                let _ = self.append(&Record::Change {
+                    field: field.clone(),
+                    records: records.to_vec(),
+                });
+            }
+            Err(error) => self.fail(error),
+        }
+    }
+
+    /// Keep `error` until the log is started again, and stop writing
+    /// changes to it in the meantime.
+    fn fail(&self, error: anyhow::Error) {
+        self.file.lock().error.get_or_insert(error);
+    }
+
+    /// Returns `true` if changes may be missing from the log.
+    fn failed(&self) -> bool {
+        self.file.lock().error.is_some()
+    }
+
+    fn sync(&self) -> Result<()> {
+        let log = self.file.lock();
+        if let Some(error) = &log.error {
+            bail!("the write-ahead log couldn't be written: {error:#}");
+        }
+
+        if let Some(file) = &log.file {
+            file.sync_data()?;
+        }
+        Ok(())
+    }
+
+    fn read(&self) -> Result<Vec<Record>> {
+        let data = match fs::read(&self.path) {
+            Ok(data) => data,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+            Err(e) => return Err(e.into()),
+        };
+
+        let Some(mut frames) = data.strip_prefix(MAGIC.as_slice()) else {
+            bail!("not a write-ahead log, or unsupported version");
+        };
+
+        let mut records = vec![];
+        while !frames.is_empty() {
+            match unseal(&self.crypto, frames) {
+                Ok((record, rest)) => {
+                    records.push(record);
+                    frames = rest;
+                }
+                // the last record may have been torn by a crash
+                Err(_) if is_last_frame(frames) => break,
+                Err(e) => return Err(e).context("the write-ahead log is damaged"),
+            }
+        }
+
+        Ok(records)
+    }
+}
+
+/// Find the changes in `records` that haven't been committed, along
+/// with the commit they were made on top of.
+fn uncommitted(
+    records: Vec<Record>,
+    committed: impl Fn(&CommitId) -> bool,
+) -> (Option<CommitId>, Vec<(Field, Vec<u8>)>) {
+    let mut head = None;
+    let mut changes = vec![];
+    for record in records {
+        match record {
+            Record::Head(start) => {
+                head = start;
+                changes.clear();
+            }
+            Record::Change { field, records } => changes.push((field, records)),
+            Record::Committing(id) => {
+                if committed(&id) {
+                    head = Some(id);
+                    changes.clear();
+                }
+            }
+        }
+    }
+
+    (head, changes)
+}
+
+/// Encrypt `record` with a key that's derived from a random id.
+fn seal(crypto: &impl ICryptoOps, record: &Record) -> Result<Vec<u8>> {
+    let mut data = crate::serialize_to_vec(record)?;
+    let id = ObjectId::new(&SystemRandom::new());
+    let key = crypto.hash(id.as_ref());
+    let tag = crypto.encrypt_chunk(id, 0, &key, &mut data).as_raw().tag;
+
+    let mut frame = Vec::with_capacity(FRAME_HEADER + data.len());
+    frame.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    frame.extend_from_slice(id.as_ref());
+    frame.extend_from_slice(&tag);
+    frame.extend_from_slice(&data);
+
+    Ok(frame)
+}
+
+/// Decrypt the first record in `frames`, and return it along with
+/// the rest of the frames.
+fn unseal<'a>(crypto: &impl ICryptoOps, frames: &'a [u8]) -> Result<(Record, &'a [u8])> {
+    if frames.len() < FRAME_HEADER {
+        bail!("truncated record");
+    }
+
+    let (len, rest) = frames.split_at(size_of::<u32>());
+    let len = u32::from_le_bytes(len.try_into()?) as usize;
+    let (id, rest) = rest.split_at(size_of::<ObjectId>());
+    let (tag, rest) = rest.split_at(size_of::<Tag>());
+    if rest.len() < len {
+        bail!("truncated record");
+    }
+
+    let id = ObjectId::from_bytes(id);
+    let pointer: ChunkPointer = RawChunkPointer {
+        offs: 0,
+        size: len as u32,
+        object: id,
+        key: crypto.hash(id.as_ref()),
+        tag: tag.try_into()?,
+    }
+    .into();
+
+    let (data, rest) = rest.split_at(len);
+    let mut buf = vec![0; len + size_of::<Tag>()];
+    let plaintext = crypto.decrypt_chunk(&mut buf, data, &pointer)?;
+
+    Ok((crate::deserialize_from_slice(plaintext)?, rest))
+}
+
+/// Returns `true` if nothing would follow the first frame in
+/// `frames`, or its length is unreadable.
+fn is_last_frame(frames: &[u8]) -> bool {
+    if frames.len() < FRAME_HEADER {
+        return true;
+    }
+
+    let mut len = [0; size_of::<u32>()];
+    len.copy_from_slice(&frames[..size_of::<u32>()]);
+    frames.len() <= FRAME_HEADER + u32::from_le_bytes(len) as usize
+}
+
+impl<I: Index, CustomData> Infinitree<I, CustomData>
+where
+    CustomData: Serialize + DeserializeOwned + Send + Sync,
+{
+    /// Keep the changes of the index that haven't been committed in
+    /// an encrypted write-ahead log at `path`.
+    ///
+    /// If the log contains changes that haven't made it into a
+    /// commit, they are replayed into the index as uncommitted
+    /// changes, on top of anything that's been loaded already. Load
+    /// the fields before opening the log. If the changes were made
+    /// on top of a different commit than the current head, `mode`
+    /// decides whether they are replayed.
+    ///
+    /// The log is only useful with the same tree, and is kept on the
+    /// local disk, independently of the backend. From now on, every
+    /// change to the fields of the index is added to the log as it's
+    /// made, and the log is started again once the changes are
+    /// committed. Fields that are stored as a whole on every commit,
+    /// like [`Serialized`](crate::fields::Serialized) and
+    /// [`List`](crate::fields::List), can't track their changes, so
+    /// their values are added to the log when it's synced instead.
+    ///
+    /// Changes are written to the log without waiting for the disk,
+    /// so they survive the process crashing. Use
+    /// [`sync_write_ahead_log`](Self::sync_write_ahead_log) to make
+    /// sure they survive the system crashing, too.
+    ///
+    /// Returns `true` if any changes were replayed.
+    pub fn open_write_ahead_log(&self, path: impl Into<PathBuf>, mode: ReplayMode) -> Result<bool> {
+        self.ensure_writable()?;
+
+        let log = Arc::new(WriteAheadLog {
+            path: path.into(),
+            crypto: self.root.key.chunk_key()?,
+            file: Default::default(),
+        });
+
+        let mut wal = self.wal.lock();
+        let index = self.index.write();
+
+        let (head, changes) = uncommitted(log.read()?, |id| self.is_committed(id));
+
+        let replay = !changes.is_empty()
+            && match mode {
+                _ if head == self.head() => true,
+                ReplayMode::Strict => bail!(
+                    "the write-ahead log was recorded on top of a different commit, \
+                     open it with `ReplayMode::Rebase` or `ReplayMode::Discard`"
+                ),
+                ReplayMode::Rebase => true,
+                ReplayMode::Discard => false,
+            };
+
+        if replay {
+            for mut field in index.journal_all()? {
+                let records = changes
+                    .iter()
+                    .filter(|(name, _)| name == &field.name)
+                    .flat_map(|(_, records)| records.iter().copied())
+                    .collect::<Vec<_>>();
+
+                if !records.is_empty() {
+                    field.strategy.replay(&records)?;
+                }
+            }
+        }
+
+        log.start(self.head(), journal(&*index)?)?;
+        for mut field in index.journal_all()? {
+            let (log, name) = (log.clone(), field.name.clone());
+            field.strategy.attach(Some(JournalLog::new(move |records| {
+                log.change(&name, records)
+            })));
+        }
+
+        *wal = Some(log);
+        Ok(replay)
+    }
+
+    /// Stop writing changes to the write-ahead log.
+    ///
+    /// The log is kept on the disk, so changes that have been logged
+    /// but not committed will be replayed when it's opened again.
+    pub fn close_write_ahead_log(&self) -> Result<()> {
+        let mut wal = self.wal.lock();
+        for mut field in self.index.write().journal_all()? {
+            field.strategy.attach(None);
+        }
+
+        *wal = None;
+        Ok(())
+    }
+
+    /// Make sure the changes written into the write-ahead log so far
+    /// survive a system crash.
+    ///
+    /// The values of the fields that can't track their changes are
+    /// added to the log first.
+    ///
+    /// Returns an error if there's no write-ahead log open, or a
+    /// change couldn't be written into it. After an error, changes
+    /// are only logged again once they are committed.
+    pub fn sync_write_ahead_log(&self) -> Result<()> {
+        let wal = self.wal.lock();
+        let log = wal.as_ref().context("no write-ahead log is open")?;
+
+        for mut field in self.index.read().journal_all()? {
+            if !field.strategy.logs_changes() {
+                let mut records = vec![];
+                field.strategy.journal(&mut records)?;
+                log.change(&field.name, Ok(&records));
+            }
+        }
+
+        log.sync()
+    }
+
+    /// Note in the log that the changes logged so far are about to be
+    /// committed as `id`, so they aren't replayed if the commit
+    /// succeeds.
+    ///
+    /// The index needs to be locked since storing the changes.
+    pub(super) fn wal_committing(&self, log: Option<&WriteAheadLog>, id: CommitId) -> Result<()> {
+        match log {
+            Some(log) => {
+                // a failed log may have some of the changes, which are
+                // committed now, and shouldn't be replayed again
+                if log.failed() {
+                    log.start(self.head(), vec![])?;
+                }
+                log.append(&Record::Committing(id))?;
+                log.sync()
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Start the log again on top of the current head, with the
+    /// changes that haven't been committed.
+    ///
+    /// The head has already moved when this is called, so errors are
+    /// kept in the log until it's synced. If changes may be missing
+    /// from the log, the uncommitted changes of `index` are written
+    /// instead.
+    pub(super) fn wal_restart(&self, log: Option<&WriteAheadLog>, index: &I) {
+        let Some(log) = log else {
+            return;
+        };
+
+        let restarted = match log.failed() {
+            true => journal(index).and_then(|changes| log.start(self.head(), changes)),
+            false => log.restart(self.head(), |id| self.is_committed(id)),
+        };
+        if let Err(error) = restarted {
+            log.fail(error);
+        }
+    }
+
+    /// Start the log again on top of the current head with the
+    /// uncommitted changes of `index`, dropping everything that's
+    /// been logged so far.
+    ///
+    /// Errors are kept in the log until it's synced.
+    pub(super) fn wal_rewrite(&self, log: Option<&WriteAheadLog>, index: &I) {
+        let Some(log) = log else {
+            return;
+        };
+
+        if let Err(error) = journal(index).and_then(|changes| log.start(self.head(), changes)) {
+            log.fail(error);
+        }
+    }
+
+    fn is_committed(&self, id: &CommitId) -> bool {
+        self.commit_list().iter().any(|c| &c.id == id)
+    }
+}
+
+/// Collect the uncommitted changes of every field of `index`.
+fn journal(index: &impl Index) -> Result<Vec<(Field, Vec<u8>)>> {
+    let mut changes = vec![];
+    for mut field in index.journal_all()? {
+        let mut records = vec![];
+        field.strategy.journal(&mut records)?;
+        if !records.is_empty() {
+            changes.push((field.name, records));
+        }
+    }
+
+    Ok(changes)
+}
+
+#[cfg(test)]
+mod test {
+    use super::ReplayMode;
+    use crate::{
+        backends::test::InMemoryBackend,
+        crypto::UsernamePassword,
+        fields::{Conflict, LinkedList, List, Map, Resolution, Serialized, VersionedMap},
+        Index, Infinitree, TEST_DATA_DIR,
+    };
+    use std::path::{Path, PathBuf};
+
+    #[derive(Index, Default)]
+    #[infinitree(journal, refresh)]
+    struct State {
+        map: VersionedMap<String, usize>,
+        list: LinkedList<usize>,
+        value: Serialized<usize>,
+        names: Map<usize, String>,
+        tags: List<usize>,
+    }
+
+    #[derive(Index, Default)]
+    #[infinitree(journal, refresh, revert, merge)]
+    struct History {
+        map: VersionedMap<String, usize>,
+    }
+
+    fn key() -> UsernamePassword {
+        UsernamePassword::with_credentials("wal_user".to_string(), "wal_password".to_string())
+            .unwrap()
+    }
+
+    fn wal_path(name: &str) -> PathBuf {
+        let path = Path::new(TEST_DATA_DIR).join(name);
+        let _ = std::fs::remove_file(&path);
+        path
+    }
+
+    #[test]
+    fn replay_uncommitted_changes() {
+        let path = wal_path("replay.wal");
+        let backend = InMemoryBackend::shared();
+
+        {
+            let tree = Infinitree::<State>::empty(backend.clone(), key()).unwrap();
+            tree.index().map.insert("committed".into(), 1);
+            tree.index().list.push(1);
+            tree.index().names.insert(1, "committed".to_string());
+            tree.commit(None).unwrap();
+
+            // made before the log is opened
+            tree.index().map.insert("early".into(), 0);
+
+            assert!(!tree
+                .open_write_ahead_log(&path, ReplayMode::Strict)
+                .unwrap());
+            tree.index().map.insert("logged".into(), 2);
+            tree.index().map.remove("committed".into());
+            tree.index().list.push(2);
+            *tree.index().value.write() = 2;
+            tree.index().names.insert(2, "logged".to_string());
+            tree.index().names.remove(&1);
+            tree.index().tags.write().push(2);
+            tree.sync_write_ahead_log().unwrap();
+        }
+
+        {
+            let tree = Infinitree::<State>::open(backend.clone(), key()).unwrap();
+            tree.load_all().unwrap();
+            assert!(tree
+                .open_write_ahead_log(&path, ReplayMode::Strict)
+                .unwrap());
+
+            let index = tree.index();
+            assert_eq!(index.map.get("committed"), None);
+            assert_eq!(index.map.get("early"), Some(0.into()));
+            assert_eq!(index.map.get("logged"), Some(2.into()));
+            assert_eq!(index.list.iter().map(|v| *v).collect::<Vec<_>>(), [1, 2]);
+            assert_eq!(*index.value.read(), 2);
+            assert_eq!(index.names.get(&1), None);
+            assert_eq!(index.names.get(&2), Some("logged".to_string().into()));
+            assert_eq!(*index.tags.read(), [2]);
+            drop(index);
+
+            tree.commit(None).unwrap();
+        }
+
+        let tree = Infinitree::<State>::open(backend, key()).unwrap();
+        tree.load_all().unwrap();
+        assert!(!tree
+            .open_write_ahead_log(&path, ReplayMode::Strict)
+            .unwrap());
+        assert_eq!(tree.index().map.get("logged"), Some(2.into()));
+        assert_eq!(*tree.index().tags.read(), [2]);
+    }
+
+    #[test]
+    fn only_replay_changes_after_the_last_commit() {
+        let path = wal_path("after_commit.wal");
+        let backend = InMemoryBackend::shared();
+
+        {
+            let tree = Infinitree::<State>::empty(backend.clone(), key()).unwrap();
+            tree.open_write_ahead_log(&path, ReplayMode::Strict)
+                .unwrap();
+            tree.index().list.push(1);
+            tree.commit(None).unwrap();
+
+            tree.index().list.push(2);
+            tree.index().list.rollback();
+            tree.index().list.push(3);
+        }
+
+        let tree = Infinitree::<State>::open(backend, key()).unwrap();
+        tree.load_all().unwrap();
+        assert!(tree
+            .open_write_ahead_log(&path, ReplayMode::Strict)
+            .unwrap());
+        assert_eq!(
+            tree.index().list.iter().map(|v| *v).collect::<Vec<_>>(),
+            [1, 3]
+        );
+    }
+
+    #[test]
+    fn rebase_or_discard_on_a_moved_head() {
+        let path = wal_path("moved_head.wal");
+        let backend = InMemoryBackend::shared();
+
+        let other = Infinitree::<State>::empty(backend.clone(), key()).unwrap();
+        other.index().map.insert("first".into(), 1);
+        other.commit(None).unwrap();
+
+        {
+            let tree = Infinitree::<State>::open(backend.clone(), key()).unwrap();
+            tree.load_all().unwrap();
+            tree.open_write_ahead_log(&path, ReplayMode::Strict)
+                .unwrap();
+            tree.index().map.insert("logged".into(), 2);
+        }
+
+        other.index().map.insert("other".into(), 3);
+        other.commit(None).unwrap();
+
+        let reopen = || {
+            let tree = Infinitree::<State>::open(backend.clone(), key()).unwrap();
+            tree.load_all().unwrap();
+            tree
+        };
+
+        let tree = reopen();
+        assert!(tree
+            .open_write_ahead_log(&path, ReplayMode::Strict)
+            .is_err());
+        assert_eq!(tree.index().map.get("logged"), None);
+        drop(tree);
+
+        let tree = reopen();
+        assert!(tree
+            .open_write_ahead_log(&path, ReplayMode::Rebase)
+            .unwrap());
+        assert_eq!(tree.index().map.get("logged"), Some(2.into()));
+        assert_eq!(tree.index().map.get("other"), Some(3.into()));
+        drop(tree);
+
+        // the rebased log starts at the current head
+        let tree = reopen();
+        assert!(tree
+            .open_write_ahead_log(&path, ReplayMode::Strict)
+            .unwrap());
+        drop(tree);
+
+        let tree = reopen();
+        other.index().map.insert("latest".into(), 4);
+        other.commit(None).unwrap();
+        tree.refresh().unwrap();
+        assert!(!tree
+            .open_write_ahead_log(&path, ReplayMode::Discard)
+            .unwrap());
+        assert_eq!(tree.index().map.get("logged"), None);
+        assert_eq!(tree.index().map.get("latest"), Some(4.into()));
+    }
+
+    #[test]
+    fn keep_logged_changes_across_merge_and_revert() {
+        let path = wal_path("merge_revert.wal");
+        let backend = InMemoryBackend::shared();
+
+        {
+            let tree = Infinitree::<History>::empty(backend.clone(), key()).unwrap();
+            tree.index().map.insert("base".into(), 0);
+            let base = tree.commit(None).unwrap().unwrap().id;
+            tree.create_branch("feature", base).unwrap();
+            tree.index().map.insert("main".into(), 1);
+            tree.commit(None).unwrap();
+
+            tree.open_write_ahead_log(&path, ReplayMode::Strict)
+                .unwrap();
+            tree.index().map.insert("logged".into(), 2);
+
+            let other = Infinitree::<History>::open(backend.clone(), key()).unwrap();
+            other.checkout(Some("feature".into()));
+            other.load_all().unwrap();
+            other.index().map.insert("feature".into(), 3);
+            other.commit(None).unwrap();
+
+            tree.refresh().unwrap();
+            let feature = tree.refs().branches["feature"];
+            tree.merge(feature, "merge", |_: &str, _: &Conflict<'_>| {
+                Resolution::Ours
+            })
+            .unwrap()
+            .unwrap();
+            tree.revert_to(base, "revert").unwrap().unwrap();
+            tree.sync_write_ahead_log().unwrap();
+        }
+
+        // the log follows the head through the merge and the revert
+        let tree = Infinitree::<History>::open(backend, key()).unwrap();
+        tree.load_all().unwrap();
+        assert!(tree
+            .open_write_ahead_log(&path, ReplayMode::Strict)
+            .unwrap());
+
+        let index = tree.index();
+        assert_eq!(index.map.get("base"), Some(0.into()));
+        assert_eq!(index.map.get("main"), None);
+        assert_eq!(index.map.get("feature"), None);
+        assert_eq!(index.map.get("logged"), Some(2.into()));
+    }
+
+    #[test]
+    fn refresh_drops_replaced_changes_from_the_log() {
+        let path = wal_path("refresh.wal");
+        let backend = InMemoryBackend::shared();
+
+        Infinitree::<State>::empty(backend.clone(), key())
+            .unwrap()
+            .commit(None)
+            .unwrap();
+        let open = || {
+            let tree = Infinitree::<State>::open(backend.clone(), key()).unwrap();
+            tree.load_all().unwrap();
+            tree
+        };
+
+        let (tree, other) = (open(), open());
+        *other.index().value.write() = 2;
+        other.commit(None).unwrap();
+
+        tree.open_write_ahead_log(&path, ReplayMode::Strict)
+            .unwrap();
+        *tree.index().value.write() = 1;
+        tree.refresh().unwrap();
+        assert_eq!(*tree.index().value.read(), 2);
+        drop(tree);
+
+        let tree = open();
+        tree.open_write_ahead_log(&path, ReplayMode::Strict)
+            .unwrap();
+        assert_eq!(*tree.index().value.read(), 2);
+    }
+
+    #[test]
+    fn commit_when_the_log_cant_be_restarted() {
+        let dir = Path::new(TEST_DATA_DIR).join("wal-gone");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let tree = Infinitree::<State>::empty(InMemoryBackend::shared(), key()).unwrap();
+        tree.open_write_ahead_log(dir.join("gone.wal"), ReplayMode::Strict)
+            .unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        // the commit is made, and the log error is kept for the sync
+        tree.index().map.insert("first".into(), 1);
+        assert!(tree.commit(None).unwrap().is_some());
+        assert_eq!(tree.commit_list().len(), 1);
+        assert!(tree.sync_write_ahead_log().is_err());
+
+        tree.index().map.insert("second".into(), 2);
+        assert!(tree.commit(None).is_err());
+
+        std::fs::create_dir_all(&dir).unwrap();
+        assert!(tree.commit(None).unwrap().is_some());
+        tree.sync_write_ahead_log().unwrap();
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,266 @@
+use super::{sealed_root, Commit, CommitId, CommitList, Infinitree};
+use crate::{
+    backends::Backend,
+    crypto::{Key, SealedHeader},
+    fields::Serialized,
+    index::Index,
+    object::BlockBuffer,
+};
+use anyhow::Result;
+use flume::{Receiver, RecvTimeoutError, Sender};
+use serde::{de::DeserializeOwned, Serialize};
+use std::{collections::HashSet, sync::Arc, thread, time::Duration};
+
+/// A new commit, as delivered by a [`Subscription`].
+#[derive(Debug)]
+pub struct CommitEvent<CustomData>
+where
+    CustomData: Serialize,
+{
+    /// The commit that has been added to the tree.
+    pub commit: Arc<Commit<CustomData>>,
+
+    /// The names of the index fields that the commit has written
+    /// to.
+    pub fields: HashSet<String>,
+}
+
+/// Receives the commits that other writers make to a tree.
+///
+/// The tree is watched from a background thread, which stops when
+/// the subscription is dropped.
+///
+/// Errors that happen while reading the root are delivered as well,
+/// but they don't stop the subscription, and the root is read again
+/// after the next interval.
+pub struct Subscription<CustomData>
+where
+    CustomData: Serialize,
+{
+    events: Receiver<Result<CommitEvent<CustomData>>>,
+
+    // the watcher stops once this is dropped
+    _stop: Sender<()>,
+}
+
+impl<CustomData> Subscription<CustomData>
+where
+    CustomData: Serialize,
+{
+    /// Wait for the next commit.
+    pub fn recv(&self) -> Option<Result<CommitEvent<CustomData>>> {
+        self.events.recv().ok()
+    }
+
+    /// Wait for the next commit for at most `timeout`.
+    pub fn recv_timeout(&self, timeout: Duration) -> Option<Result<CommitEvent<CustomData>>> {
+        self.events.recv_timeout(timeout).ok()
+    }
+
+    /// Return the next commit if there's one already waiting.
+    pub fn try_recv(&self) -> Option<Result<CommitEvent<CustomData>>> {
+        self.events.try_recv().ok()
+    }
+}
+
+impl<CustomData> Iterator for Subscription<CustomData>
+where
+    CustomData: Serialize,
+{
+    type Item = Result<CommitEvent<CustomData>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.recv()
+    }
+}
+
+impl<I: Index, CustomData> Infinitree<I, CustomData>
+where
+    CustomData: Serialize + DeserializeOwned + Send + Sync + 'static,
+{
+    /// Watch the tree for commits made by other writers.
+    ///
+    /// The root object is read from the backend every `interval`,
+    /// and every commit that the tree doesn't know about yet is
+    /// delivered through the returned [`Subscription`], oldest first.
+    /// Commits made through this tree, or picked up by
+    /// [`refresh`](Self::refresh), are left out.
+    ///
+    /// The tree itself is not changed. Use
+    /// [`refresh`](Self::refresh) to load the new commits.
+    pub fn subscribe(&self, interval: Duration) -> Result<Subscription<CustomData>> {
+        let (events, receiver) = flume::unbounded();
+        let (stop, stopped) = flume::bounded(0);
+
+        let mut watcher = Watcher {
+            backend: self.backend.clone(),
+            key: self.root.key.clone(),
+            header: self
+                .root
+                .root_head
+                .read()
+                .as_ref()
+                .map(|(_, header)| header.clone()),
+            known: self.root.commit_list.clone(),
+            seen: HashSet::new(),
+        };
+
+        thread::Builder::new()
+            .name("infinitree-watcher".into())
+            .spawn(move || loop {
+                match stopped.recv_timeout(interval) {
+                    Err(RecvTimeoutError::Timeout) => {}
+                    _ => return,
+                }
+
+                let new = match watcher.poll() {
+                    Ok(commits) => commits.into_iter().map(Ok).collect(),
+                    Err(error) => vec![Err(error)],
+                };
+
+                for event in new {
+                    if events.send(event).is_err() {
+                        return;
+                    }
+                }
+            })?;
+
+        Ok(Subscription {
+            events: receiver,
+            _stop: stop,
+        })
+    }
+}
+
+struct Watcher<CustomData>
+where
+    CustomData: Serialize,
+{
+    backend: Arc<dyn Backend>,
+    key: Key,
+    header: Option<SealedHeader>,
+
+    /// The commit list of the tree, which has the commits that it
+    /// made or refreshed.
+    known: Serialized<CommitList<CustomData>>,
+
+    /// Commits that have already been delivered.
+    seen: HashSet<CommitId>,
+}
+
+impl<CustomData> Watcher<CustomData>
+where
+    CustomData: Serialize + DeserializeOwned + Send + Sync + 'static,
+{
+    /// Return the commits that have been added since the last poll.
+    fn poll(&mut self) -> Result<Vec<CommitEvent<CustomData>>> {
+        // only open the root if it has changed since the last time
+        let object = self.backend.read_fresh(&self.key.root_object_id()?)?;
+        if Some(sealed_root::read_head(&object)) == self.header {
+            return Ok(vec![]);
+        }
+
+        let root = sealed_root::open::<CustomData>(
+            BlockBuffer::default(),
+            self.backend.clone(),
+            self.key.clone(),
+        )?;
+        self.header = root
+            .root_head
+            .read()
+            .as_ref()
+            .map(|(_, header)| header.clone());
+
+        let known = self
+            .known
+            .read()
+            .iter()
+            .map(|c| c.id)
+            .collect::<HashSet<_>>();
+        let transaction_log = root.transaction_log.read();
+        let mut events = vec![];
+        for commit in root.commit_list.read().iter() {
+            if known.contains(&commit.id) || !self.seen.insert(commit.id) {
+                continue;
+            }
+
+            let fields = transaction_log
+                .iter()
+                .filter(|(id, _, stream)| id == &commit.id && !stream.is_empty())
+                .map(|(_, field, _)| field.clone())
+                .collect();
+
+            events.push(CommitEvent {
+                commit: commit.clone(),
+                fields,
+            });
+        }
+
+        Ok(events)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        backends::test::InMemoryBackend,
+        crypto::UsernamePassword,
+        fields::{Serialized, VersionedMap},
+        Index, Infinitree,
+    };
+    use std::time::Duration;
+
+    #[derive(Index, Default)]
+    #[infinitree(refresh)]
+    struct Counters {
+        map: VersionedMap<usize, usize>,
+        other: VersionedMap<usize, usize>,
+        last: Serialized<usize>,
+    }
+
+    fn key() -> UsernamePassword {
+        UsernamePassword::with_credentials("watch_user".to_string(), "watch_password".to_string())
+            .unwrap()
+    }
+
+    #[test]
+    fn subscribe_to_commits() {
+        let backend = InMemoryBackend::shared();
+        let writer = Infinitree::<Counters>::empty(backend.clone(), key()).unwrap();
+        writer.index().map.insert(1, 1);
+        writer.commit("first").unwrap();
+
+        let reader = Infinitree::<Counters>::open(backend.clone(), key()).unwrap();
+        let subscription = reader.subscribe(Duration::from_millis(10)).unwrap();
+
+        writer.index().map.insert(2, 2);
+        let second = writer.commit("second").unwrap().unwrap();
+
+        let event = subscription
+            .recv_timeout(Duration::from_secs(10))
+            .unwrap()
+            .unwrap();
+        assert_eq!(event.commit.id, second.id);
+        assert!(event.fields.contains("map"));
+        assert!(!event.fields.contains("other"));
+
+        // nothing else has been committed
+        std::thread::sleep(Duration::from_millis(50));
+        assert!(subscription.try_recv().is_none());
+
+        // commits made through the subscribed tree are left out
+        reader.load_all().unwrap();
+        reader.refresh().unwrap();
+        reader.index().map.insert(3, 3);
+        reader.commit("own").unwrap();
+        writer.refresh().unwrap();
+        writer.index().map.insert(4, 4);
+        let fourth = writer.commit("fourth").unwrap().unwrap();
+
+        let event = subscription
+            .recv_timeout(Duration::from_secs(10))
+            .unwrap()
+            .unwrap();
+        assert_eq!(event.commit.id, fourth.id);
+    }
+}